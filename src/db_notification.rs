@@ -8,6 +8,10 @@ pub const NOTIFICATION_PREFIX_REQUEST: &str = "request:";
 pub const NOTIFICATION_PREFIX_RESPONSE: &str = "response:";
 pub const NOTIFICATION_PREFIX_SETTING: &str = "setting:";
 pub const NOTIFICATION_PREFIX_DBNAME: &str = "database:";
+pub const NOTIFICATION_PREFIX_ROLE: &str = "role:";
+pub const NOTIFICATION_PREFIX_CORS: &str = "cors:";
+// messages for the websocket connections
+pub const NOTIFICATION_PREFIX_WEBSOCKET: &str = "websocket:";
 
 macro_rules! PKG_NAME {
     () => {
@@ -28,6 +32,13 @@ impl Notifier {
     pub async fn notify(&self, msg: String) -> Result<u64, Error> {
         self.db_notifier.notify(msg).await
     }
+
+    /// get subscriper channel for recieving notifications
+    pub fn get_subscriber(
+        &self,
+    ) -> tokio::sync::broadcast::Receiver<nino_structures::NotificationMessage> {
+        self.db_notifier.get_subscriber()
+    }
 }
 
 /// A Postgres DB connector and listener
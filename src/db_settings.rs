@@ -18,6 +18,10 @@ struct CachedValue {
 }
 
 static SETTING_CACHE: OnceLock<RwLock<HashMap<String, CachedValue>>> = OnceLock::new();
+// setting notifications are sent again after the cache is cleared
+static SETTING_CHANGES: OnceLock<
+    tokio::sync::broadcast::Sender<nino_structures::NotificationMessage>,
+> = OnceLock::new();
 
 impl SettingsManager {
     /// Create DB Manager and connection pool
@@ -28,6 +32,7 @@ impl SettingsManager {
         >,
     ) -> SettingsManager {
         SETTING_CACHE.get_or_init(|| RwLock::new(HashMap::new()));
+        SETTING_CHANGES.get_or_init(|| tokio::sync::broadcast::channel(64).0);
         if let Some(subscribe) = db_subscribe {
             tokio::spawn(async move {
                 Self::invalidator(subscribe).await;
@@ -50,13 +55,27 @@ impl SettingsManager {
                         .text
                         .starts_with(db_notification::NOTIFICATION_PREFIX_SETTING)
                     {
-                        SETTING_CACHE.get().unwrap().write().unwrap().clear();
+                        Self::clear_cache();
+                        // no subscribers is not an error
+                        let _r = SETTING_CHANGES.get().unwrap().send(message);
                     }
                 }
             }
         }
     }
 
+    /// the setting notifications, received after the cache is cleared.
+    /// the subscribers reloading settings read the changed values
+    pub fn subscribe(
+        &self,
+    ) -> tokio::sync::broadcast::Receiver<nino_structures::NotificationMessage> {
+        SETTING_CHANGES.get().unwrap().subscribe()
+    }
+
+    fn clear_cache() {
+        SETTING_CACHE.get().unwrap().write().unwrap().clear();
+    }
+
     fn cache_get(settings_key: &str) -> Option<CachedValue> {
         // check if setting is in the cache
         SETTING_CACHE
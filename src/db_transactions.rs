@@ -152,8 +152,7 @@ impl TransactionManager {
     pub fn get_transaction_session(connection_string: String) -> TransactionSession {
         let main_connection_string = connection_string.clone();
 
-        let (request_in, request_out) = async_channel::unbounded::<TransactionSessionRequest>();
-        let (response_in, response_out) = async_channel::unbounded::<TransactionSessionResponse>();
+        let (request_in, request_out) = async_channel::unbounded::<SessionRequest>();
         let builder = thread::Builder::new().name("TX Thread {}".to_string());
         if let Err(error) = builder.spawn(move || {
             let rt = tokio::runtime::Builder::new_current_thread()
@@ -161,32 +160,46 @@ impl TransactionManager {
                 .build()
                 .unwrap();
 
-            let mut tx = TransactionsThread::new(main_connection_string, request_out, response_in);
+            let mut tx = TransactionsThread::new(main_connection_string, request_out);
             rt.block_on(tx.session_loop());
         }) {
             eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
         }
-        TransactionSession {
-            request_in,
-            response_out,
-        }
+        TransactionSession { request_in }
     }
 }
 
+// the request with the channel of its response
+type SessionRequest = (
+    TransactionSessionRequest,
+    Sender<TransactionSessionResponse>,
+);
+
+/// the handle of the transactions thread of a task.
+/// the clones share the thread, every request gets its own response
+#[derive(Clone)]
 pub struct TransactionSession {
-    request_in: Sender<TransactionSessionRequest>,
-    response_out: Receiver<TransactionSessionResponse>,
+    request_in: Sender<SessionRequest>,
 }
 impl TransactionSession {
-    pub fn reload_database_aliases(&mut self) -> Result<(), Error> {
-        if let Err(error) = self
-            .request_in
-            .send_blocking(TransactionSessionRequest::ReloadDBAliases)
-        {
+    /// waits for the response without blocking the thread of the caller
+    async fn request(
+        &self,
+        request: TransactionSessionRequest,
+    ) -> Result<TransactionSessionResponse, Error> {
+        let (response_in, response_out) = async_channel::bounded(1);
+        if let Err(error) = self.request_in.send((request, response_in)).await {
             eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
-            return Err(Error::msg(error));
+            return Err(Error::msg(error.to_string()));
         }
-        match self.response_out.recv_blocking()? {
+        Ok(response_out.recv().await?)
+    }
+
+    pub async fn reload_database_aliases(&self) -> Result<(), Error> {
+        match self
+            .request(TransactionSessionRequest::ReloadDBAliases)
+            .await?
+        {
             TransactionSessionResponse::Ok => Ok(()),
             TransactionSessionResponse::Error(msg) => Err(Error::msg(msg)),
             TransactionSessionResponse::UpsertResult(_) => panic!(),
@@ -195,15 +208,11 @@ impl TransactionSession {
         }
     }
 
-    pub fn create_transaction(&mut self, db_alias: String) -> Result<String, Error> {
-        if let Err(error) = self
-            .request_in
-            .send_blocking(TransactionSessionRequest::CreateTransaction(db_alias))
+    pub async fn create_transaction(&self, db_alias: String) -> Result<String, Error> {
+        match self
+            .request(TransactionSessionRequest::CreateTransaction(db_alias))
+            .await?
         {
-            eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
-            return Err(Error::msg(error));
-        }
-        match self.response_out.recv_blocking()? {
             TransactionSessionResponse::Transaction(alias) => Ok(alias),
             TransactionSessionResponse::Error(msg) => Err(Error::msg(msg)),
             TransactionSessionResponse::UpsertResult(_) => panic!(),
@@ -212,10 +221,11 @@ impl TransactionSession {
         }
     }
 
-    pub fn close_all(&mut self, commit: bool) -> Result<(), Error> {
-        self.request_in
-            .send_blocking(TransactionSessionRequest::CloseAll(commit))?;
-        match self.response_out.recv_blocking()? {
+    pub async fn close_all(&self, commit: bool) -> Result<(), Error> {
+        match self
+            .request(TransactionSessionRequest::CloseAll(commit))
+            .await?
+        {
             TransactionSessionResponse::Ok => Ok(()),
             TransactionSessionResponse::Error(msg) => Err(Error::msg(msg)),
             TransactionSessionResponse::UpsertResult(_) => panic!(),
@@ -224,8 +234,8 @@ impl TransactionSession {
         }
     }
 
-    pub fn query(
-        &mut self,
+    pub async fn query(
+        &self,
         db_alias: String,
         query: String,
         params: Vec<QueryParam>,
@@ -236,9 +246,10 @@ impl TransactionSession {
             params,
         };
 
-        self.request_in
-            .send_blocking(TransactionSessionRequest::Query(query_data))?;
-        match self.response_out.recv_blocking()? {
+        match self
+            .request(TransactionSessionRequest::Query(query_data))
+            .await?
+        {
             TransactionSessionResponse::QueryResult(result) => Ok(result),
             TransactionSessionResponse::Error(msg) => Err(Error::msg(msg)),
             TransactionSessionResponse::UpsertResult(_) => panic!(),
@@ -247,8 +258,8 @@ impl TransactionSession {
         }
     }
 
-    pub fn upsert(
-        &mut self,
+    pub async fn upsert(
+        &self,
         db_alias: String,
         query: String,
         params: Vec<QueryParam>,
@@ -259,9 +270,10 @@ impl TransactionSession {
             params,
         };
 
-        self.request_in
-            .send_blocking(TransactionSessionRequest::Upsert(query_data))?;
-        match self.response_out.recv_blocking()? {
+        match self
+            .request(TransactionSessionRequest::Upsert(query_data))
+            .await?
+        {
             TransactionSessionResponse::UpsertResult(affected) => Ok(affected),
             TransactionSessionResponse::Error(msg) => Err(Error::msg(msg)),
             TransactionSessionResponse::QueryResult(_) => panic!(),
@@ -281,20 +293,14 @@ pub struct TransactionsThread {
     dirty: bool,
     // keep transaction per alias
     db_pool: HashMap<String, Transaction>,
-    request_out: Receiver<TransactionSessionRequest>,
-    response_in: Sender<TransactionSessionResponse>,
+    request_out: Receiver<SessionRequest>,
 }
 
 impl TransactionsThread {
-    fn new(
-        main_connection_string: String,
-        request_out: Receiver<TransactionSessionRequest>,
-        response_in: Sender<TransactionSessionResponse>,
-    ) -> Self {
+    fn new(main_connection_string: String, request_out: Receiver<SessionRequest>) -> Self {
         Self {
             main_connection_string,
             request_out,
-            response_in,
             db_alias_info: HashMap::with_capacity(32),
             db_aliases: HashMap::with_capacity(32),
             dirty: false,
@@ -325,7 +331,7 @@ impl TransactionsThread {
         // wait for message and serve
         loop {
             match self.request_out.recv().await {
-                Ok(message) => {
+                Ok((message, response_in)) => {
                     // process transaction command
                     let result = match message {
                         TransactionSessionRequest::ReloadDBAliases => {
@@ -400,10 +406,9 @@ impl TransactionsThread {
                         }
                     };
                     // send response
-                    if let Err(error) = self.response_in.send(result).await {
-                        // channel has been closed
+                    if let Err(error) = response_in.send(result).await {
+                        // the requester is gone, the next requests are served
                         info!("OK {}:{}: {}", file!(), line!(), error);
-                        break;
                     }
                 }
                 Err(error) => {
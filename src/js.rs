@@ -13,16 +13,32 @@ impl JavaScriptManager {
      */
     pub fn create(
         thread_count: usize,
+        concurrent_tasks: u32,
+        task_timeout_ms: u32,
+        heap_limit: usize,
+        snapshot_modules: Vec<String>,
         inspector_port: u16,
         connection_string: String,
         dynamics: Arc<ResponseManager>,
         settings: Arc<SettingsManager>,
     ) -> Result<(), Error> {
-        js_core::js_init(js_functions::load_module, 4);
+        js_core::js_init(
+            js_functions::load_module,
+            js_functions::terminated_task,
+            4,
+            heap_limit,
+            snapshot_modules,
+        );
         let main_uri = format!("{}{}", js_core::MODULE_URI, nino_constants::MODULE_MAIN).to_owned();
         let main_module = Url::parse(main_uri.as_str())?;
 
-        js_functions::init_js_context(connection_string, dynamics, settings);
+        js_functions::init_js_context(
+            connection_string,
+            dynamics,
+            settings,
+            task_timeout_ms,
+            concurrent_tasks,
+        );
         for id in 0..thread_count {
             js_core::start_js_thread(
                 js_functions::nino_extentions,
@@ -4,12 +4,16 @@ use deno_core::{
     ModuleLoadResponse, ModuleLoader, ModuleSource, ModuleSourceCode, ModuleSpecifier, ModuleType,
     RequestedModuleType, ResolutionKind,
 };
-use deno_core::{PollEventLoopOptions, RuntimeOptions};
+use deno_core::{v8, JsRuntimeForSnapshot, OpState, PollEventLoopOptions, RuntimeOptions};
 use deno_error::JsErrorBox;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::pin::Pin;
 use std::rc::Rc;
-use std::sync::{Arc, OnceLock};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::{Duration, Instant};
 
 use crate::js_inspector;
 
@@ -26,13 +30,46 @@ pub type ModuleLoadingFunction =
 
 pub type ExtentionsSupplier = fn() -> Vec<Extension>;
 
-pub fn js_init(module_loader: ModuleLoadingFunction, thread_pool_size: u32) {
+/// finishes the task of a terminated execution before the runtime is recreated
+pub type TerminationHandler =
+    fn(&mut OpState, TaskLimit) -> Pin<Box<dyn Future<Output = ()> + 'static>>;
+
+static TERMINATION_HANDLER: OnceLock<TerminationHandler> = OnceLock::new();
+// heap limit in bytes of the runtimes. 0 - the V8 default
+static HEAP_LIMIT: OnceLock<usize> = OnceLock::new();
+// modules evaluated into the startup snapshot. empty - no snapshot
+static SNAPSHOT_MODULES: OnceLock<Vec<String>> = OnceLock::new();
+static SNAPSHOT: tokio::sync::Mutex<SnapshotState> = tokio::sync::Mutex::const_new(SnapshotState {
+    current: None,
+    made: 0,
+});
+// modules changed since the snapshot was last checked. empty name - all modules
+static CHANGED_MODULES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+// one runtime restarts at a time, so the other threads keep serving
+static RESTARTS: tokio::sync::Semaphore = tokio::sync::Semaphore::const_new(1);
+
+// snapshots made at most. the outdated ones stay in memory, so the runtimes boot without
+// a snapshot after this many library changes until the server restarts
+const MAX_SNAPSHOTS: usize = 16;
+// how often the watchdog checks the running task
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(100);
+
+pub fn js_init(
+    module_loader: ModuleLoadingFunction,
+    termination_handler: TerminationHandler,
+    thread_pool_size: u32,
+    heap_limit: usize,
+    snapshot_modules: Vec<String>,
+) {
     FNMODULE_LOADER_FUNCTION.get_or_init(|| module_loader);
+    TERMINATION_HANDLER.get_or_init(|| termination_handler);
+    HEAP_LIMIT.get_or_init(|| heap_limit);
+    SNAPSHOT_MODULES.get_or_init(|| snapshot_modules);
     let v8_platform =
         Some(deno_core::v8::new_default_platform(thread_pool_size, false).make_shared());
     // Initialize a runtime instance
     let mut _js_runtime = JsRuntime::new(RuntimeOptions {
-        module_loader: Some(Rc::new(FNModuleLoader {})),
+        module_loader: Some(Rc::new(FNModuleLoader::default())),
         v8_platform,
         inspector: false,
         is_main: true,
@@ -40,22 +77,299 @@ pub fn js_init(module_loader: ModuleLoadingFunction, thread_pool_size: u32) {
     });
 }
 
-pub struct FNModuleLoader {}
+/// the limit a terminated task has exceeded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TaskLimit {
+    Time,
+    Heap,
+}
+
+/// Watches the tasks of a javascript thread and terminates the execution when one is over its limits.
+/// the terminated runtime is dropped and the thread starts a new one
+pub struct TaskWatchdog {
+    isolate: v8::IsolateHandle,
+    // deadlines of the running tasks by task id, the unlimited tasks have none
+    deadlines: Mutex<HashMap<u32, Instant>>,
+    // the tasks over their time limit
+    expired: Mutex<Vec<u32>>,
+    exceeded: Mutex<Option<TaskLimit>>,
+    // terminate_execution stops only running javascript, a task waiting in an op is woken by this
+    terminated: tokio::sync::Notify,
+}
+
+impl TaskWatchdog {
+    fn new(isolate: v8::IsolateHandle) -> Arc<TaskWatchdog> {
+        let this = Arc::new(Self {
+            isolate,
+            deadlines: Mutex::new(HashMap::new()),
+            expired: Mutex::new(Vec::new()),
+            exceeded: Mutex::new(None),
+            terminated: tokio::sync::Notify::new(),
+        });
+        let watched = Arc::downgrade(&this);
+        let thread_name = format!("{}-watchdog", std::thread::current().name().unwrap_or("js"));
+        let started = std::thread::Builder::new()
+            .name(thread_name)
+            .spawn(move || Self::watching(watched));
+        if let Err(error) = started {
+            eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+        }
+        this
+    }
+
+    /// ends with the runtime
+    fn watching(watched: Weak<TaskWatchdog>) {
+        loop {
+            std::thread::sleep(WATCHDOG_INTERVAL);
+            let Some(watchdog) = watched.upgrade() else {
+                return;
+            };
+            // the lock keeps a stopped task from being terminated
+            let mut deadlines = watchdog.deadlines.lock().unwrap();
+            let now = Instant::now();
+            let expired: Vec<u32> = deadlines
+                .iter()
+                .filter(|(_, deadline)| **deadline <= now)
+                .map(|(id, _)| *id)
+                .collect();
+            if !expired.is_empty() {
+                deadlines.retain(|id, _| !expired.contains(id));
+                watchdog.expired.lock().unwrap().extend(expired);
+                watchdog.terminate(TaskLimit::Time);
+            }
+        }
+    }
+
+    /// starts the time budget of a task. 0 - unlimited
+    pub fn start(&self, id: u32, timeout_ms: u32) {
+        let mut deadlines = self.deadlines.lock().unwrap();
+        if timeout_ms > 0 {
+            deadlines.insert(
+                id,
+                Instant::now() + Duration::from_millis(timeout_ms as u64),
+            );
+        } else {
+            deadlines.remove(&id);
+        }
+    }
+
+    pub fn stop(&self, id: u32) {
+        self.deadlines.lock().unwrap().remove(&id);
+    }
+
+    /// the ids of the tasks over their time limit
+    pub fn expired(&self) -> Vec<u32> {
+        self.expired.lock().unwrap().clone()
+    }
+
+    fn terminate(&self, limit: TaskLimit) {
+        let mut exceeded = self.exceeded.lock().unwrap();
+        if exceeded.is_none() {
+            *exceeded = Some(limit);
+            self.isolate.terminate_execution();
+            // the permit is kept when the runtime is not waiting yet
+            self.terminated.notify_one();
+        }
+    }
+
+    pub fn exceeded(&self) -> Option<TaskLimit> {
+        *self.exceeded.lock().unwrap()
+    }
+}
+
+/// the modules a runtime has loaded, including the ones of its startup snapshot
+#[derive(Clone, Default)]
+pub struct LoadedModules(Rc<RefCell<HashSet<String>>>);
+
+impl LoadedModules {
+    pub fn contains(&self, module: &str) -> bool {
+        self.0.borrow().contains(module)
+    }
+
+    fn insert(&self, module: &str) {
+        if !self.contains(module) {
+            self.0.borrow_mut().insert(module.to_string());
+        }
+    }
+}
+
+/// permit to restart the runtime of a thread, held until the new runtime is created
+struct RestartPermit {
+    _permit: tokio::sync::SemaphorePermit<'static>,
+}
+
+/// waits until the runtimes restarting before are created again
+async fn restart_permit() -> RestartPermit {
+    RestartPermit {
+        // the semaphore is never closed
+        _permit: RESTARTS.acquire().await.unwrap(),
+    }
+}
+
+/// V8 startup snapshot with the library modules already evaluated
+struct StartupSnapshot {
+    // hash of the source of every module the snapshot was made of, including the imported ones
+    source_hashes: HashMap<String, u64>,
+    data: &'static [u8],
+}
+
+impl StartupSnapshot {
+    fn modules(&self) -> Vec<String> {
+        self.source_hashes.keys().cloned().collect()
+    }
+}
+
+struct SnapshotState {
+    current: Option<StartupSnapshot>,
+    // snapshots made since the start, each one is leaked
+    made: usize,
+}
+
+/// records the change of a module for the next runtime start, ex: on response:<name> invalidation.
+/// must be called before the runtimes are told to restart
+pub fn module_changed(module: &str) {
+    CHANGED_MODULES.lock().unwrap().push(module.to_string());
+}
+
+fn source_hash(code: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    code.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// the startup snapshot of the configured modules with all the modules evaluated into it,
+/// None when not configured or not possible.
+/// the snapshot is made again when the source of one of its changed modules is different
+async fn startup_snapshot(
+    extensions_supplier: ExtentionsSupplier,
+) -> Option<(&'static [u8], Vec<String>)> {
+    let modules = SNAPSHOT_MODULES
+        .get()
+        .filter(|modules| !modules.is_empty())?;
+
+    // the first thread checks or makes the snapshot, the others wait for it
+    let mut state = SNAPSHOT.lock().await;
+    let changed = std::mem::take(&mut *CHANGED_MODULES.lock().unwrap());
+    if let Some(snapshot) = state.current.as_ref() {
+        if is_snapshot_current(snapshot, &changed).await {
+            return Some((snapshot.data, snapshot.modules()));
+        }
+    }
+    state.current = None;
+    if state.made >= MAX_SNAPSHOTS {
+        return None;
+    }
+
+    match create_snapshot(extensions_supplier, modules).await {
+        Ok((data, source_hashes)) => {
+            // the runtimes need it for their lifetime, the outdated ones are not freed
+            let data: &'static [u8] = Box::leak(data);
+            state.made += 1;
+            if state.made == MAX_SNAPSHOTS {
+                eprintln!(
+                    "ERROR {}:{}:{} snapshots made, the next library changes are not snapshotted until restart",
+                    file!(),
+                    line!(),
+                    MAX_SNAPSHOTS
+                );
+            }
+            let snapshot = StartupSnapshot {
+                source_hashes,
+                data,
+            };
+            let modules = snapshot.modules();
+            state.current = Some(snapshot);
+            Some((data, modules))
+        }
+        Err(error) => {
+            eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+            None
+        }
+    }
+}
+
+/// true when the changed modules of the snapshot still have the sources it was made of.
+/// only the changed modules are loaded again
+async fn is_snapshot_current(snapshot: &StartupSnapshot, changed: &[String]) -> bool {
+    let Some(module_loader) = FNMODULE_LOADER_FUNCTION.get() else {
+        return false;
+    };
+    let mut checked: Vec<&String> = if changed.iter().any(|module| module.is_empty()) {
+        snapshot.source_hashes.keys().collect()
+    } else {
+        changed
+            .iter()
+            .filter(|module| snapshot.source_hashes.contains_key(*module))
+            .collect()
+    };
+    checked.sort();
+    checked.dedup();
+    for module in checked {
+        match module_loader(module.clone()).await {
+            Ok(code) if snapshot.source_hashes.get(module) == Some(&source_hash(&code)) => {}
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// returns the snapshot with the source hashes of the modules loaded into it
+async fn create_snapshot(
+    extensions_supplier: ExtentionsSupplier,
+    modules: &[String],
+) -> Result<(Box<[u8]>, HashMap<String, u64>), Error> {
+    let source_hashes = Rc::new(RefCell::new(HashMap::with_capacity(modules.len())));
+    let mut js_runtime = JsRuntimeForSnapshot::new(RuntimeOptions {
+        module_loader: Some(Rc::new(FNModuleLoader {
+            source_hashes: Some(source_hashes.clone()),
+            ..Default::default()
+        })),
+        extensions: extensions_supplier(),
+        ..Default::default()
+    });
+    for module in modules {
+        let specifier = Url::parse(&format!("{}{}", MODULE_URI, module))?;
+        let mod_id = js_runtime.load_side_es_module(&specifier).await?;
+        let evaluated = js_runtime.mod_evaluate(mod_id);
+        js_runtime.run_event_loop(Default::default()).await?;
+        evaluated.await?;
+    }
+    let data = js_runtime.snapshot();
+    let source_hashes = source_hashes.take();
+    Ok((data, source_hashes))
+}
+
+#[derive(Default)]
+pub struct FNModuleLoader {
+    loaded: LoadedModules,
+    // hashes of the loaded sources, ex: for the snapshot
+    source_hashes: Option<Rc<RefCell<HashMap<String, u64>>>>,
+}
 
 static FNMODULE_LOADER_FUNCTION: OnceLock<ModuleLoadingFunction> = OnceLock::new();
 
 impl FNModuleLoader {
-    async fn async_load(module_name: String) -> Result<ModuleSource, ModuleLoaderError> {
+    fn module_source(module_name: &str, code: String) -> ModuleSource {
+        let module_type = ModuleType::JavaScript;
+        let code = ModuleSourceCode::String(FastString::from(code));
+        let module_string = Url::parse(&format!("{}{}", MODULE_URI, module_name)).unwrap();
+        ModuleSource::new(module_type, code, &module_string, None)
+    }
+
+    async fn async_load(
+        module_name: String,
+        source_hashes: Option<Rc<RefCell<HashMap<String, u64>>>>,
+    ) -> Result<ModuleSource, ModuleLoaderError> {
         let fn_holder = FNMODULE_LOADER_FUNCTION.get();
         match fn_holder {
             Some(module_loader) => match module_loader(module_name.clone()).await {
                 Ok(code) => {
-                    let module_type = ModuleType::JavaScript;
-                    let code = ModuleSourceCode::String(FastString::from(code));
-                    let module_string =
-                        Url::parse(&format!("{}{}", MODULE_URI, module_name)).unwrap();
-                    let module = ModuleSource::new(module_type, code, &module_string, None);
-                    Ok(module)
+                    if let Some(source_hashes) = source_hashes {
+                        source_hashes
+                            .borrow_mut()
+                            .insert(module_name.clone(), source_hash(&code));
+                    }
+                    Ok(Self::module_source(&module_name, code))
                 }
                 Err(_) => Err(JsErrorBox::generic(format!(
                     "module: {} not found",
@@ -96,7 +410,10 @@ impl ModuleLoader for FNModuleLoader {
         _requested_module_type: RequestedModuleType,
     ) -> ModuleLoadResponse {
         let module_path = &module_specifier.path()[1..];
-        ModuleLoadResponse::Async(Self::async_load(String::from(module_path)).boxed_local())
+        self.loaded.insert(module_path);
+        ModuleLoadResponse::Async(
+            Self::async_load(String::from(module_path), self.source_hashes.clone()).boxed_local(),
+        )
     }
 }
 
@@ -134,13 +451,23 @@ async fn start_js(
     forever: bool,
     inspector_port: u16,
 ) {
+    let mut restart_permit = None;
     loop {
-        if let Err(error) = _start_js(extensions_supplier, execute.clone(), inspector_port).await {
-            println!("ERROR: {}", error)
+        let started = _start_js(
+            extensions_supplier,
+            execute.clone(),
+            inspector_port,
+            restart_permit.take(),
+        )
+        .await;
+        if let Err(error) = started {
+            println!("ERROR: {}", error);
         }
         if !forever {
             break;
         }
+        // the tasks of the runtime are done, so a long task does not hold up the other threads
+        restart_permit = Some(self::restart_permit().await);
     }
 }
 
@@ -148,6 +475,7 @@ async fn _start_js(
     extensions_supplier: ExtentionsSupplier,
     execute: ExecuteMode,
     inspector_port: u16,
+    restart_permit: Option<RestartPermit>,
 ) -> Result<(), Error> {
     // inspector
     let inspector = inspector_port > 0;
@@ -160,13 +488,51 @@ async fn _start_js(
     };
 
     // Initialize a runtime instance
+    let heap_limit = HEAP_LIMIT.get().copied().unwrap_or(0);
+    let create_params = if heap_limit > 0 {
+        Some(v8::CreateParams::default().heap_limits(0, heap_limit))
+    } else {
+        None
+    };
+    // the module runtimes start with the library modules from the snapshot
+    let startup_snapshot = match execute {
+        ExecuteMode::JsModule(_) => startup_snapshot(extensions_supplier).await,
+        ExecuteMode::JsCode(_) => None,
+    };
+    // a change of a module imported by the snapshot modules restarts the runtime as well
+    let loaded = LoadedModules::default();
+    let startup_snapshot = startup_snapshot.map(|(data, modules)| {
+        for module in modules {
+            loaded.insert(&module);
+        }
+        data
+    });
     let mut js_runtime = JsRuntime::new(RuntimeOptions {
-        module_loader: Some(Rc::new(FNModuleLoader {})),
+        module_loader: Some(Rc::new(FNModuleLoader {
+            loaded: loaded.clone(),
+            ..Default::default()
+        })),
         extensions: extensions_supplier(),
         inspector: inspector_server.is_some(),
         is_main: true,
+        create_params,
+        startup_snapshot,
         ..Default::default()
     });
+    js_runtime.op_state().borrow_mut().put(loaded);
+    // the next thread may restart
+    drop(restart_permit);
+
+    let watchdog = TaskWatchdog::new(js_runtime.v8_isolate().thread_safe_handle());
+    if heap_limit > 0 {
+        let watched = watchdog.clone();
+        js_runtime.add_near_heap_limit_callback(move |current_limit, _initial_limit| {
+            watched.terminate(TaskLimit::Heap);
+            // room for the termination, the runtime is dropped after it
+            current_limit * 2
+        });
+    }
+    js_runtime.op_state().borrow_mut().put(watchdog.clone());
 
     if let Some(inspector_server) = inspector_server.clone() {
         inspector_server.register_inspector("debugger".into(), &mut js_runtime, false);
@@ -175,6 +541,25 @@ async fn _start_js(
         op_state.borrow_mut().put(inspector);
     }
 
+    // the event loop is dropped when a task over its limits waits in an op (fetch, body read, sleep)
+    let result = tokio::select! {
+        biased;
+        result = execute_js(&mut js_runtime, execute) => result,
+        _ = watchdog.terminated.notified() => Err(Error::msg("execution terminated")),
+    };
+    if let Some(limit) = watchdog.exceeded() {
+        if let Some(handler) = TERMINATION_HANDLER.get() {
+            let terminated = handler(&mut js_runtime.op_state().borrow_mut(), limit);
+            terminated.await;
+        }
+    }
+
+    drop(inspector_server);
+
+    result
+}
+
+async fn execute_js(js_runtime: &mut JsRuntime, execute: ExecuteMode) -> Result<(), Error> {
     match execute {
         ExecuteMode::JsModule(main_module) => {
             // start the mail module and event loop
@@ -199,8 +584,5 @@ async fn _start_js(
                 .await?;
         }
     }
-
-    drop(inspector_server);
-
     Ok(())
 }
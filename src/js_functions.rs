@@ -2,16 +2,24 @@ use crate::db_log::DBLogger;
 use crate::db_notification::{self, Notifier};
 use crate::db_settings::SettingsManager;
 use crate::db_transactions::{QueryParam, TransactionManager, TransactionSession};
+use crate::js_core::{LoadedModules, TaskLimit, TaskWatchdog};
+use crate::js_web;
 use crate::nino_constants::info;
-use crate::nino_structures::{JSTask, LogInfo, ServletTask};
+use crate::nino_structures::{
+    ErrorInfo, JSTask, LogInfo, RequestBody, ServletTask, SocketData, SocketEvent,
+};
+use crate::web_errors::ErrorPageManager;
+use crate::web_jwt::JwtManager;
 use crate::web_responses::ResponseManager;
-use crate::{js_core, nino_constants, nino_functions};
+use crate::web_sockets::{self, SocketMessage};
+use crate::{nino_constants, nino_functions};
 use anyhow::Error;
-use async_channel::Receiver;
+use async_channel::{Receiver, Sender};
+use async_std::io::ReadExt;
 use deno_core::futures::FutureExt;
 use deno_core::*;
 use deno_error::*;
-use http_types::{convert::Serialize, StatusCode, Url};
+use http_types::{convert::Serialize, Response, StatusCode, Url};
 use reqwest::{
     header::{HeaderName, HeaderValue},
     Body, Client, Method, Request,
@@ -26,16 +34,34 @@ use std::{cell::RefCell, rc::Rc, str::FromStr, sync::Arc};
 deno_core::extension!(
     nino_extention,
     ops = [
-        nino_begin_task,
+        nino_a_begin_task,
         nino_a_end_task,
+        nino_stop_tasks,
+        nino_is_module_loaded,
+        nino_get_concurrent_tasks,
         nino_a_sleep,
         nino_a_log,
         nino_get_request,
         nino_get_request_body,
+        nino_get_request_body_bytes,
+        nino_get_request_parts,
+        nino_a_read_request_body,
         nino_set_response_status,
         nino_set_response_header,
+        nino_set_response_cookie,
         nino_a_set_response_send_text,
         nino_a_set_response_send_buf,
+        nino_a_response_write,
+        nino_a_response_end,
+        nino_get_socket_event,
+        nino_get_socket_bytes,
+        nino_socket_send_text,
+        nino_socket_send_binary,
+        nino_socket_join,
+        nino_socket_leave,
+        nino_socket_close,
+        nino_a_socket_publish,
+        nino_a_send_server_error,
         nino_get_invalidation_message,
         nino_get_thread_id,
         nino_broadcast_message,
@@ -43,11 +69,11 @@ deno_core::extension!(
         nino_get_module_invalidation_prefix,
         nino_get_database_invalidation_prefix,
         nino_get_system_id,
-        nino_reload_database_aliases,
-        nino_tx_end,
-        nino_tx_get_connection_name,
-        nino_tx_execute_query,
-        nino_tx_execute_upsert,
+        nino_a_reload_database_aliases,
+        nino_a_tx_end,
+        nino_a_tx_get_connection_name,
+        nino_a_tx_execute_query,
+        nino_a_tx_execute_upsert,
         nino_get_user_jwt,
         nino_password_hash,
         nino_password_verify,
@@ -59,7 +85,7 @@ deno_core::extension!(
 );
 
 pub fn nino_extentions() -> Vec<Extension> {
-    vec![nino_extention::init()]
+    vec![nino_extention::init(), js_web::nino_web_extention::init()]
 }
 
 #[derive(Clone)]
@@ -67,6 +93,8 @@ pub struct NinoContext {
     connection_string: String,
     dynamics: Arc<ResponseManager>,
     settings: Arc<SettingsManager>,
+    task_timeout_ms: u32,
+    concurrent_tasks: u32,
 }
 
 static NINO_CONTEXT: OnceLock<NinoContext> = OnceLock::new();
@@ -75,11 +103,15 @@ pub fn init_js_context(
     connection_string: String,
     dynamics: Arc<ResponseManager>,
     settings: Arc<SettingsManager>,
+    task_timeout_ms: u32,
+    concurrent_tasks: u32,
 ) {
     NINO_CONTEXT.get_or_init(|| NinoContext {
         connection_string,
         dynamics,
         settings,
+        task_timeout_ms,
+        concurrent_tasks,
     });
 }
 
@@ -90,14 +122,18 @@ pub fn init_js_context(
 fn create_js_context_state(state: &mut OpState) {
     let nino = NINO_CONTEXT.get().unwrap();
 
+    let (stop_sx, stop_rx) = async_channel::bounded::<()>(1);
+    let session = TransactionManager::get_transaction_session(nino.connection_string.clone());
     state.put(JSContext {
         web_task_rx: nino.dynamics.get_web_task_rx(),
+        messages_rx: nino.dynamics.subscribe_js_messages(),
+        stop_sx,
+        stop_rx,
         notifier: nino.dynamics.get_notifier().clone(),
-        broadcast_messages: Vec::with_capacity(8),
-        task: None,
+        tasks: HashMap::from([(NO_TASK, TaskContext::new(session))]),
+        last_id: NO_TASK,
+        idle_sessions: Vec::new(),
     });
-    let session = TransactionManager::get_transaction_session(nino.connection_string.clone());
-    state.put::<TransactionSession>(session);
     state.put::<SettingsManager>(nino.settings.as_ref().clone())
 }
 
@@ -117,18 +153,91 @@ async fn load_module_async(module_name: String) -> Result<String, Error> {
     Ok(content_str)
 }
 
-pub struct JSContext {
-    pub notifier: Arc<Notifier>,
-    pub web_task_rx: Receiver<JSTask>,
-    // close request will have a None Task
+// the id of the code running outside of tasks, ex: the transpiler script
+const NO_TASK: u32 = 0;
+
+/// a task in flight with its own transactions
+pub struct TaskContext {
+    // None when the task is finished, ex: the response is sent
     pub task: Option<JSTask>,
+    pub session: TransactionSession,
     // collect broadcast messages to be send after commit
     pub broadcast_messages: Vec<String>,
 }
 
+impl TaskContext {
+    fn new(session: TransactionSession) -> TaskContext {
+        TaskContext {
+            task: None,
+            session,
+            broadcast_messages: Vec::with_capacity(8),
+        }
+    }
+}
+
+pub struct JSContext {
+    pub notifier: Arc<Notifier>,
+    pub web_task_rx: Receiver<JSTask>,
+    // invalidation messages for this thread
+    pub messages_rx: Receiver<String>,
+    // closed to stop the workers of the thread, ex: before restart
+    stop_sx: Sender<()>,
+    stop_rx: Receiver<()>,
+    // the tasks in flight by id. the task ops get the id as first parameter
+    pub tasks: HashMap<u32, TaskContext>,
+    last_id: u32,
+    // sessions of the finished tasks for reuse
+    idle_sessions: Vec<TransactionSession>,
+}
+
 impl JSContext {
-    pub fn clear(&mut self) {
-        self.task = None;
+    pub fn get(&mut self, id: u32) -> Result<&mut TaskContext, JsErrorBox> {
+        self.tasks
+            .get_mut(&id)
+            .ok_or(JsErrorBox::generic("task already closed"))
+    }
+
+    /// the transaction session of the task, used by the ops without borrowing the state
+    pub fn session(&mut self, id: u32) -> Result<TransactionSession, JsErrorBox> {
+        Ok(self.get(id)?.session.clone())
+    }
+
+    /// the task of the id while it is not finished
+    pub fn task(&mut self, id: u32) -> Result<&mut JSTask, JsErrorBox> {
+        self.get(id)?
+            .task
+            .as_mut()
+            .ok_or(JsErrorBox::generic("task already closed"))
+    }
+
+    fn begin(&mut self, task: JSTask) -> u32 {
+        let mut id = self.last_id;
+        loop {
+            id = id.wrapping_add(1);
+            if id != NO_TASK && !self.tasks.contains_key(&id) {
+                break;
+            }
+        }
+        self.last_id = id;
+        let session = self.idle_sessions.pop().unwrap_or_else(|| {
+            let nino = NINO_CONTEXT.get().unwrap();
+            TransactionManager::get_transaction_session(nino.connection_string.clone())
+        });
+        let mut context = TaskContext::new(session);
+        context.task = Some(task);
+        self.tasks.insert(id, context);
+        id
+    }
+
+    /// removes the task context and keeps its session for the next tasks.
+    /// returns the task when it is not finished yet
+    fn finish(&mut self, id: u32) -> Option<JSTask> {
+        if id == NO_TASK {
+            return self.tasks.get_mut(&id)?.task.take();
+        }
+        let context = self.tasks.remove(&id)?;
+        self.idle_sessions.push(context.session);
+        context.task
     }
 }
 
@@ -152,74 +261,206 @@ macro_rules! function {
     }};
 }
 
-#[op2]
-#[string]
-fn nino_begin_task(state: &mut OpState) -> Result<String, JsErrorBox> {
-    let mut module = String::new();
-    let context = state.borrow_mut::<JSContext>();
-    let result = context.web_task_rx.recv_blocking();
-    // return execution module or empty string if not a Servlet
-    match result {
-        Ok(task) => {
-            context.task = Some(task);
-            if let Some(task) = &context.task {
-                match task {
-                    JSTask::Message(_) => {
-                        // nothing to do here
-                    }
-                    JSTask::Servlet(request) => {
-                        module.clone_from(&request.js_module.clone().unwrap());
-                    }
-                }
-            }
-            // info!("new js task");
+/// the task received by a javascript worker
+#[derive(Serialize)]
+pub struct TaskInfo {
+    id: u32,
+    // execution module, empty when not a Servlet or Socket
+    module: String,
+}
+
+#[op2(async)]
+#[serde]
+async fn nino_a_begin_task(op_state: Rc<RefCell<OpState>>) -> Option<TaskInfo> {
+    let (web_task_rx, messages_rx, stop_rx) = {
+        let mut state = op_state.borrow_mut();
+        let context = state.borrow_mut::<JSContext>();
+        (
+            context.web_task_rx.clone(),
+            context.messages_rx.clone(),
+            context.stop_rx.clone(),
+        )
+    };
+    // waits on the event loop, the other tasks of the thread run meanwhile
+    let result = tokio::select! {
+        biased;
+        _ = stop_rx.recv() => None,
+        message = messages_rx.recv() => Some(message.map(JSTask::Message).map_err(Error::from)),
+        task = web_task_rx.recv() => Some(task.map_err(Error::from)),
+    };
+    let task = match result {
+        None => {
+            // stopped for restart
+            return None;
         }
-        Err(error) => {
-            context.clear();
+        Some(Err(error)) => {
             // should happen only when terminating program
             info!("OK {}:{}: {}", function!(), line!(), error);
+            return None;
         }
+        Some(Ok(task)) => task,
+    };
+
+    let default_timeout_ms = NINO_CONTEXT.get().unwrap().task_timeout_ms;
+    let (module, timeout_ms) = match &task {
+        JSTask::Message(_) => (String::new(), 0),
+        JSTask::Servlet(request) => (
+            request.js_module.clone().unwrap_or_default(),
+            task_timeout(request.timeout_ms, default_timeout_ms),
+        ),
+        JSTask::Socket(socket) => (socket.js_module.clone(), default_timeout_ms),
+    };
+    let mut state = op_state.borrow_mut();
+    let id = state.borrow_mut::<JSContext>().begin(task);
+    if let Some(watchdog) = state.try_borrow::<Arc<TaskWatchdog>>() {
+        watchdog.start(id, timeout_ms);
     }
-    Ok(module)
+    Some(TaskInfo { id, module })
 }
 
+/// the workers of the thread get no more tasks and end, so the runtime restarts
 #[op2(fast)]
-fn nino_tx_end(state: &mut OpState, commit: bool) {
-    let tx = state.borrow_mut::<TransactionSession>();
-    if let Err(error) = tx.close_all(commit) {
-        eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+fn nino_stop_tasks(state: &mut OpState) {
+    state.borrow_mut::<JSContext>().stop_sx.close();
+    js_web::cancel_timers(state);
+}
+
+/// true when the runtime has loaded the module, so a change of it needs a restart
+#[op2(fast)]
+fn nino_is_module_loaded(state: &mut OpState, #[string] module: String) -> bool {
+    state
+        .try_borrow::<LoadedModules>()
+        .is_some_and(|loaded| loaded.contains(&module))
+}
+
+#[op2(fast)]
+#[smi]
+fn nino_get_concurrent_tasks() -> u32 {
+    NINO_CONTEXT.get().unwrap().concurrent_tasks
+}
+
+/// the time budget of a task: its own, 0 - the default, negative - unlimited
+fn task_timeout(timeout_ms: i32, default_timeout_ms: u32) -> u32 {
+    match timeout_ms {
+        0 => default_timeout_ms,
+        timeout_ms if timeout_ms < 0 => 0,
+        timeout_ms => timeout_ms as u32,
+    }
+}
+
+/// finishes the tasks of a terminated execution: rolls back their transactions and answers
+/// the requests with 504 for the time and 503 for the heap limit.
+/// the other tasks in flight are answered with 503
+pub fn terminated_task(
+    state: &mut OpState,
+    limit: TaskLimit,
+) -> Pin<Box<dyn Future<Output = ()> + 'static>> {
+    let expired = state
+        .try_borrow::<Arc<TaskWatchdog>>()
+        .map(|watchdog| watchdog.expired())
+        .unwrap_or_default();
+    let settings = state.borrow::<SettingsManager>().clone();
+    let context = state.borrow_mut::<JSContext>();
+    let mut tasks = Vec::with_capacity(context.tasks.len());
+    let mut sessions = Vec::with_capacity(context.tasks.len());
+    for (id, task_context) in context.tasks.iter_mut() {
+        sessions.push(task_context.session.clone());
+        task_context.broadcast_messages.clear();
+        if let Some(task) = task_context.task.take() {
+            let (status, message) = match limit {
+                TaskLimit::Time if expired.contains(id) => {
+                    (StatusCode::GatewayTimeout, "execution time limit exceeded")
+                }
+                TaskLimit::Heap => (StatusCode::ServiceUnavailable, "memory limit exceeded"),
+                TaskLimit::Time => (
+                    StatusCode::ServiceUnavailable,
+                    "execution terminated by an other task over its time limit",
+                ),
+            };
+            tasks.push((task, status, message));
+        }
+    }
+    async move {
+        let nino = NINO_CONTEXT.get().unwrap();
+        for session in sessions {
+            if let Err(error) = session.close_all(false).await {
+                eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+            }
+        }
+        let errors = ErrorPageManager::new(Arc::new(settings), nino.dynamics.clone());
+        for (task, status, message) in tasks {
+            DBLogger::log(task_log_info(&task, message.to_string())).await;
+            eprintln!("ERROR {}:{}:{}", file!(), line!(), message);
+            // socket tasks are dropped, the connection reads the next frame
+            let JSTask::Servlet(mut servlet_task) = task else {
+                continue;
+            };
+            if servlet_task.chunked {
+                // the head is already sent - the client sees the response truncated
+                servlet_task.stream.release(false);
+                continue;
+            }
+            servlet_task.error = Some(ErrorInfo {
+                status: status.into(),
+                id: String::new(),
+                message: message.to_string(),
+            });
+            // the error page module could exceed the limits as well
+            if let Err(error) = errors.send_error(servlet_task, false, &[]).await {
+                eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+            }
+        }
+    }
+    .boxed_local()
+}
+
+#[op2(async)]
+async fn nino_a_tx_end(op_state: Rc<RefCell<OpState>>, #[smi] task: u32, commit: bool) {
+    let session = op_state
+        .borrow_mut()
+        .borrow_mut::<JSContext>()
+        .session(task);
+    match session {
+        Ok(session) => {
+            if let Err(error) = session.close_all(commit).await {
+                eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+            }
+        }
+        Err(error) => {
+            eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+        }
     }
 }
 
 #[op2(async)]
-async fn nino_a_end_task(op_state: Rc<RefCell<OpState>>) -> Result<bool, JsErrorBox> {
-    let stream;
-    let response;
+async fn nino_a_end_task(
+    op_state: Rc<RefCell<OpState>>,
+    #[smi] task: u32,
+) -> Result<bool, JsErrorBox> {
+    let servlet_task;
     {
         let mut state = op_state.borrow_mut();
-        let context = state.borrow_mut::<JSContext>();
-
-        if context.task.is_none() {
-            //task already closed
-            return Ok(false);
+        if let Some(watchdog) = state.try_borrow::<Arc<TaskWatchdog>>() {
+            watchdog.stop(task);
         }
+        let context = state.borrow_mut::<JSContext>();
 
-        match context.task.take().unwrap() {
-            JSTask::Message(_) => {
-                // nothing to do here
+        match context.finish(task) {
+            None => {
+                //task already closed
                 return Ok(false);
             }
-            JSTask::Servlet(request) => {
-                stream = request.stream;
-                response = request.response;
-                context.clear();
+            Some(JSTask::Message(_)) | Some(JSTask::Socket(_)) => {
+                // the socket connection reads the next frame when the task is dropped
+                return Ok(false);
+            }
+            Some(JSTask::Servlet(request)) => {
+                servlet_task = request;
             }
         }
     }
 
-    if let Err(error) =
-        nino_functions::send_response_to_stream(stream, &mut response.unwrap()).await
-    {
+    if let Err(error) = send_servlet_response(servlet_task, None).await {
         eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
     }
     Ok(true)
@@ -236,15 +477,21 @@ pub struct HttpRequest {
     query: String,
     parameters: HashMap<String, Vec<String>>,
     post_parameters: HashMap<String, Vec<String>>,
+    headers: HashMap<String, Vec<String>>,
+    cookies: HashMap<String, String>,
+    params: HashMap<String, String>,
+    body_streamed: bool,
     user: String,
+    // set only for error page modules
+    error: Option<ErrorInfo>,
 }
 
 #[op2]
 #[serde]
-fn nino_get_request(state: &mut OpState) -> Result<HttpRequest, JsErrorBox> {
+fn nino_get_request(state: &mut OpState, #[smi] task: u32) -> Result<HttpRequest, JsErrorBox> {
     let context = state.borrow_mut::<JSContext>();
 
-    if let Some(task) = &context.task {
+    if let Some(task) = &context.get(task)?.task {
         match task {
             JSTask::Servlet(servlet) => {
                 let url = servlet.request.url();
@@ -267,26 +514,50 @@ fn nino_get_request(state: &mut OpState) -> Result<HttpRequest, JsErrorBox> {
                 }
 
                 let mut post_parameters: HashMap<String, Vec<String>> = HashMap::new();
-                if !servlet.body.contains(' ') {
-                    let post_url_str = format!("{}?{}", js_core::MODULE_URI, servlet.body);
-                    if let Ok(url) = Url::parse(&post_url_str) {
-                        for (key, value) in url.query_pairs() {
-                            let key = key.to_string();
-                            let value = value.to_string();
-                            match post_parameters.get_mut(&key) {
-                                None => {
-                                    let mut vec: Vec<String> = Vec::with_capacity(2);
-                                    vec.push(value);
-                                    post_parameters.insert(key, vec);
-                                }
-                                Some(vec) => {
-                                    vec.push(value);
-                                }
-                            }
-                        }
+                let essence = servlet
+                    .request
+                    .content_type()
+                    .map(|mime| mime.essence().to_string());
+                // like before the forms, bodies of other types without spaces are parsed as well
+                let is_form = match (essence.as_deref(), &servlet.body) {
+                    (Some("application/x-www-form-urlencoded"), _) => true,
+                    (Some("multipart/form-data"), _) => false,
+                    (_, RequestBody::Buffered(body)) => !body.contains(&b' '),
+                    (_, RequestBody::Streamed(_)) => false,
+                };
+                if let (true, RequestBody::Buffered(body)) = (is_form, &servlet.body) {
+                    for (key, value) in deno_core::url::form_urlencoded::parse(body) {
+                        post_parameters
+                            .entry(key.to_string())
+                            .or_default()
+                            .push(value.to_string());
+                    }
+                }
+                // multipart text fields are available as post parameters too
+                for part in servlet.parts.iter() {
+                    if part.filename.is_none() {
+                        post_parameters
+                            .entry(part.name.clone())
+                            .or_default()
+                            .push(String::from_utf8_lossy(&part.content).to_string());
                     }
                 }
 
+                // header names are lowercased for case-insensitive lookup
+                let mut headers: HashMap<String, Vec<String>> = HashMap::new();
+                for (name, values) in servlet.request.iter() {
+                    let vec = headers.entry(name.as_str().to_lowercase()).or_default();
+                    for value in values.iter() {
+                        vec.push(value.as_str().to_string());
+                    }
+                }
+                let cookies = match servlet.request.header("Cookie") {
+                    Some(values) => {
+                        nino_functions::parse_cookies(values.iter().map(|v| v.as_str()))
+                    }
+                    None => HashMap::new(),
+                };
+
                 let request = HttpRequest {
                     url: url.clone(),
                     method: servlet.request.method().to_string(),
@@ -296,12 +567,17 @@ fn nino_get_request(state: &mut OpState) -> Result<HttpRequest, JsErrorBox> {
                     query,
                     parameters,
                     post_parameters,
+                    headers,
+                    cookies,
+                    params: servlet.params.clone(),
+                    body_streamed: matches!(servlet.body, RequestBody::Streamed(_)),
                     user: servlet.user.clone(),
+                    error: servlet.error.clone(),
                 };
                 //deno_core::serde_json::to_string(&request).unwrap()
                 Ok(request)
             }
-            JSTask::Message(_) => Err(JsErrorBox::generic("task is not a request")),
+            _ => Err(JsErrorBox::generic("task is not a request")),
         }
     } else {
         Err(JsErrorBox::generic("no current task"))
@@ -309,18 +585,22 @@ fn nino_get_request(state: &mut OpState) -> Result<HttpRequest, JsErrorBox> {
 }
 
 #[op2(fast)]
-fn nino_set_response_status(state: &mut OpState, status: u16) -> Result<(), JsErrorBox> {
+fn nino_set_response_status(
+    state: &mut OpState,
+    #[smi] task: u32,
+    status: u16,
+) -> Result<(), JsErrorBox> {
     let context = state.borrow_mut::<JSContext>();
 
-    if let Some(task) = &mut context.task {
+    if let Some(task) = &mut context.get(task)?.task {
         match task {
             JSTask::Servlet(servlet) => {
                 let status = StatusCode::try_from(status).unwrap();
-                let response = servlet.response.as_mut().unwrap();
+                let response = started_response(servlet)?;
                 response.set_status(status);
                 Ok(())
             }
-            JSTask::Message(_) => Err(JsErrorBox::generic("task is not a request")),
+            _ => Err(JsErrorBox::generic("task is not a request")),
         }
     } else {
         Err(JsErrorBox::generic("no current task"))
@@ -330,52 +610,85 @@ fn nino_set_response_status(state: &mut OpState, status: u16) -> Result<(), JsEr
 #[op2(fast)]
 fn nino_set_response_header(
     state: &mut OpState,
+    #[smi] task: u32,
     #[string] key: String,
     #[string] value: String,
 ) -> Result<(), JsErrorBox> {
     let context = state.borrow_mut::<JSContext>();
 
-    if let Some(task) = &mut context.task {
+    if let Some(task) = &mut context.get(task)?.task {
         match task {
             JSTask::Servlet(servlet) => {
-                let response = servlet.response.as_mut().unwrap();
+                let response = started_response(servlet)?;
                 response.remove_header(&*key);
                 response.append_header(&*key, &*value);
                 Ok(())
             }
-            JSTask::Message(_) => Err(JsErrorBox::generic("task is not a request")),
+            _ => Err(JsErrorBox::generic("task is not a request")),
         }
     } else {
         Err(JsErrorBox::generic("no current task"))
     }
 }
 
-fn take_servlet_task(op_state: Rc<RefCell<OpState>>) -> Result<ServletTask, JsErrorBox> {
-    let mut state = op_state.borrow_mut();
+#[op2]
+fn nino_set_response_cookie(
+    state: &mut OpState,
+    #[smi] task: u32,
+    #[string] name: String,
+    #[string] value: String,
+    #[serde] options: nino_functions::CookieOptions,
+) -> Result<(), JsErrorBox> {
     let context = state.borrow_mut::<JSContext>();
 
-    if context.task.is_none() {
+    if let Some(task) = &mut context.get(task)?.task {
+        match task {
+            JSTask::Servlet(servlet) => {
+                let cookie =
+                    nino_functions::cookie_to_string(&name, &value, &options).map_err(any_error)?;
+                let response = started_response(servlet)?;
+                // multiple cookies are send as separate Set-Cookie headers
+                response.append_header("Set-Cookie", &*cookie);
+                Ok(())
+            }
+            _ => Err(JsErrorBox::generic("task is not a request")),
+        }
+    } else {
+        Err(JsErrorBox::generic("no current task"))
+    }
+}
+
+/// the response of the servlet while its head is not sent yet
+fn started_response(servlet: &mut ServletTask) -> Result<&mut Response, JsErrorBox> {
+    servlet
+        .response
+        .as_mut()
+        .ok_or(JsErrorBox::generic("response already started"))
+}
+
+fn take_servlet_task(op_state: Rc<RefCell<OpState>>, task: u32) -> Result<ServletTask, JsErrorBox> {
+    let mut state = op_state.borrow_mut();
+    let task_context = state.borrow_mut::<JSContext>().get(task)?;
+
+    let Some(task) = task_context.task.take() else {
         //task already closed
         return Err(JsErrorBox::generic("task already closed"));
-    }
+    };
 
-    match context.task.take().unwrap() {
+    match task {
         JSTask::Servlet(servlet) => Ok(servlet),
-        JSTask::Message(_) => Err(JsErrorBox::generic("task is not a request")),
+        _ => Err(JsErrorBox::generic("task is not a request")),
     }
 }
 
 #[op2(async)]
 async fn nino_a_set_response_send_text(
     op_state: Rc<RefCell<OpState>>,
+    #[smi] task: u32,
     #[string] body: String,
 ) -> Result<(), JsErrorBox> {
-    let mut servlet_task = take_servlet_task(op_state)?;
-    let mut response = servlet_task.response.take().unwrap();
-    let stream = servlet_task.stream;
-
-    response.set_body(body);
-    if let Err(error) = nino_functions::send_response_to_stream(stream, &mut response).await {
+    let servlet_task = take_servlet_task(op_state, task)?;
+    if let Err(error) = send_servlet_response(servlet_task, Some(body.as_bytes())).await {
         eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
     };
     Ok(())
@@ -384,21 +697,169 @@ async fn nino_a_set_response_send_text(
 #[op2(async)]
 async fn nino_a_set_response_send_buf(
     op_state: Rc<RefCell<OpState>>,
+    #[smi] task: u32,
     #[buffer] bytes: JsBuffer,
 ) -> Result<(), JsErrorBox> {
-    let mut servlet_task = take_servlet_task(op_state)?;
-    let mut response = servlet_task.response.take().unwrap();
-    let stream = servlet_task.stream;
-
+    let servlet_task = take_servlet_task(op_state, task)?;
     let data: &[u8] = &bytes;
-    response.set_body(data);
-
-    if let Err(error) = nino_functions::send_response_to_stream(stream, &mut response).await {
+    if let Err(error) = send_servlet_response(servlet_task, Some(data)).await {
         eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
     };
     Ok(())
 }
 
+/// finishes the servlet response. chunked responses get the body as the last chunk,
+/// the others are sent whole with the body when given
+async fn send_servlet_response(
+    mut servlet_task: ServletTask,
+    body: Option<&[u8]>,
+) -> Result<(), Error> {
+    let stream = servlet_task.stream;
+    if servlet_task.chunked {
+        return nino_functions::end_chunked(stream, body.unwrap_or_default()).await;
+    }
+    let mut response = servlet_task.response.take().unwrap();
+    if let Some(body) = body {
+        response.set_body(body);
+    }
+    nino_functions::send_response_to_stream(stream, &mut response).await
+}
+
+/// writes a chunk of the response body. the first write sends the status and the headers
+/// with Transfer-Encoding: chunked, so they can not be changed any more
+#[op2(async)]
+async fn nino_a_response_write(
+    op_state: Rc<RefCell<OpState>>,
+    #[smi] task: u32,
+    #[buffer] bytes: JsBuffer,
+) -> Result<(), JsErrorBox> {
+    let (stream, response) = {
+        let mut state = op_state.borrow_mut();
+        let context = state.borrow_mut::<JSContext>();
+        match context.get(task)?.task.as_mut() {
+            Some(JSTask::Servlet(servlet)) => {
+                let response = if servlet.chunked {
+                    None
+                } else {
+                    servlet.chunked = true;
+                    servlet.response.take()
+                };
+                (servlet.stream.clone(), response)
+            }
+            Some(_) => return Err(JsErrorBox::generic("task is not a request")),
+            None => return Err(JsErrorBox::generic("task already closed")),
+        }
+    };
+
+    if let Some(mut response) = response {
+        nino_functions::start_chunked(&stream, &mut response)
+            .await
+            .map_err(any_error)?;
+    }
+    let data: &[u8] = &bytes;
+    nino_functions::write_chunk(&stream, data)
+        .await
+        .map_err(any_error)
+}
+
+/// finishes the response. responses without written chunks are sent with empty body
+#[op2(async)]
+async fn nino_a_response_end(
+    op_state: Rc<RefCell<OpState>>,
+    #[smi] task: u32,
+) -> Result<(), JsErrorBox> {
+    let servlet_task = take_servlet_task(op_state, task)?;
+    send_servlet_response(servlet_task, None)
+        .await
+        .map_err(any_error)
+}
+
+/// the websocket event of the task, available in JS as the socket of the handler
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SocketEventInfo {
+    #[serde(rename = "type")]
+    event_type: String,
+    id: String,
+    path: String,
+    user: String,
+    params: HashMap<String, String>,
+    // text message, binary messages are read with nino_get_socket_bytes
+    text: Option<String>,
+    binary: bool,
+}
+
+/// the websocket event of the current task, null for the other tasks
+#[op2]
+#[serde]
+fn nino_get_socket_event(state: &mut OpState, #[smi] task: u32) -> Option<SocketEventInfo> {
+    let context = state.borrow_mut::<JSContext>();
+    match context.get(task).ok()?.task.as_ref() {
+        Some(JSTask::Socket(socket)) => Some(SocketEventInfo {
+            event_type: socket.event.name().to_string(),
+            id: socket.id.clone(),
+            path: socket.request_path.clone(),
+            user: socket.user.clone(),
+            params: socket.params.clone(),
+            text: match &socket.event {
+                SocketEvent::Message(SocketData::Text(text)) => Some(text.clone()),
+                _ => None,
+            },
+            binary: matches!(&socket.event, SocketEvent::Message(SocketData::Binary(_))),
+        }),
+        _ => None,
+    }
+}
+
+#[op2]
+#[serde]
+fn nino_get_socket_bytes(state: &mut OpState, #[smi] task: u32) -> Result<ToJsBuffer, JsErrorBox> {
+    let context = state.borrow_mut::<JSContext>();
+    match context.get(task)?.task.as_ref() {
+        Some(JSTask::Socket(socket)) => match &socket.event {
+            SocketEvent::Message(SocketData::Binary(bytes)) => Ok(bytes.clone().into()),
+            _ => Err(JsErrorBox::generic("socket event is not a binary message")),
+        },
+        Some(_) => Err(JsErrorBox::generic("task is not a socket event")),
+        None => Err(JsErrorBox::generic("no current task")),
+    }
+}
+
+/// sends to a connection of this instance. false when the connection is not here
+#[op2(fast)]
+fn nino_socket_send_text(#[string] id: String, #[string] text: String) -> bool {
+    web_sockets::send(&id, SocketData::Text(text))
+}
+
+#[op2(fast)]
+fn nino_socket_send_binary(#[string] id: String, #[buffer] bytes: &[u8]) -> bool {
+    web_sockets::send(&id, SocketData::Binary(bytes.to_vec()))
+}
+
+#[op2(fast)]
+fn nino_socket_join(#[string] id: String, #[string] group: String) -> bool {
+    web_sockets::join(&id, &group)
+}
+
+#[op2(fast)]
+fn nino_socket_leave(#[string] id: String, #[string] group: String) {
+    web_sockets::leave(&id, &group)
+}
+
+#[op2(fast)]
+fn nino_socket_close(#[string] id: String) -> bool {
+    web_sockets::close(&id)
+}
+
+/// sends the text to a connection or a group of connections on all instances
+#[op2(async)]
+async fn nino_a_socket_publish(#[serde] message: SocketMessage) -> Result<(), JsErrorBox> {
+    let nino = NINO_CONTEXT.get().unwrap();
+    web_sockets::publish(&nino.dynamics.get_notifier(), &message)
+        .await
+        .map_err(any_error)
+}
+
 #[op2(async)]
 async fn nino_a_sleep(#[bigint] millis: u64) {
     // Future must be Poll::Pending on first call
@@ -407,16 +868,12 @@ async fn nino_a_sleep(#[bigint] millis: u64) {
 
 #[op2]
 #[string]
-fn nino_get_invalidation_message(state: &mut OpState) -> String {
+fn nino_get_invalidation_message(state: &mut OpState, #[smi] task: u32) -> String {
     let context = state.borrow_mut::<JSContext>();
 
-    if context.task.is_some() {
-        match context.task.as_mut().unwrap() {
-            JSTask::Message(message) => message.clone(),
-            JSTask::Servlet(_) => String::new(),
-        }
-    } else {
-        String::new()
+    match context.task(task) {
+        Ok(JSTask::Message(message)) => message.clone(),
+        _ => String::new(),
     }
 }
 
@@ -427,23 +884,31 @@ fn nino_get_thread_id() -> String {
 }
 
 #[op2(fast)]
-fn nino_broadcast_message(state: &mut OpState, #[string] message: String) {
+fn nino_broadcast_message(
+    state: &mut OpState,
+    #[smi] task: u32,
+    #[string] message: String,
+) -> Result<(), JsErrorBox> {
     let context = state.borrow_mut::<JSContext>();
-    context.broadcast_messages.push(message);
+    context.get(task)?.broadcast_messages.push(message);
+    Ok(())
 }
 
 #[op2(async)]
-async fn nino_a_broadcast_message(op_state: Rc<RefCell<OpState>>, commit: bool) {
+async fn nino_a_broadcast_message(op_state: Rc<RefCell<OpState>>, #[smi] task: u32, commit: bool) {
     let notifier;
     let mut messages: Vec<String> = Vec::with_capacity(8);
     {
         let mut state = op_state.borrow_mut();
         let context = state.borrow_mut::<JSContext>();
         notifier = context.notifier.clone();
+        let Ok(task_context) = context.get(task) else {
+            return;
+        };
         if commit {
-            messages.append(&mut context.broadcast_messages);
+            messages.append(&mut task_context.broadcast_messages);
         } else {
-            context.broadcast_messages.clear();
+            task_context.broadcast_messages.clear();
         }
     };
     if commit {
@@ -574,34 +1039,54 @@ fn _query_types_to_params(
     Ok((query[0].clone(), query_params))
 }
 
-#[op2(fast)]
-fn nino_reload_database_aliases(state: &mut OpState) -> Result<(), JsErrorBox> {
-    let tx = state.borrow_mut::<TransactionSession>();
-    tx.reload_database_aliases().map_err(any_error)
+#[op2(async)]
+async fn nino_a_reload_database_aliases(op_state: Rc<RefCell<OpState>>) -> Result<(), JsErrorBox> {
+    let sessions: Vec<TransactionSession> = {
+        let mut state = op_state.borrow_mut();
+        let context = state.borrow_mut::<JSContext>();
+        context
+            .tasks
+            .values()
+            .map(|task_context| task_context.session.clone())
+            .chain(context.idle_sessions.iter().cloned())
+            .collect()
+    };
+    for tx in sessions {
+        tx.reload_database_aliases().await.map_err(any_error)?;
+    }
+    Ok(())
 }
 
-#[op2]
+#[op2(async)]
 #[string]
-fn nino_tx_get_connection_name(
-    state: &mut OpState,
+async fn nino_a_tx_get_connection_name(
+    op_state: Rc<RefCell<OpState>>,
+    #[smi] task: u32,
     #[string] db_alias: String,
 ) -> Result<String, JsErrorBox> {
-    let tx = state.borrow_mut::<TransactionSession>();
-    tx.create_transaction(db_alias).map_err(any_error)
+    let tx = op_state
+        .borrow_mut()
+        .borrow_mut::<JSContext>()
+        .session(task)?;
+    tx.create_transaction(db_alias).await.map_err(any_error)
 }
 
-#[op2]
+#[op2(async)]
 #[serde]
-fn nino_tx_execute_query(
-    state: &mut OpState,
+async fn nino_a_tx_execute_query(
+    op_state: Rc<RefCell<OpState>>,
+    #[smi] task: u32,
     #[string] db_alias: String,
     #[serde] query: Vec<String>,
     #[serde] query_types: Vec<i16>,
 ) -> Result<QueryResult, JsErrorBox> {
     let (query, params) = query_types_to_params(query, query_types)?;
-    let tx = state.borrow_mut::<TransactionSession>();
+    let tx = op_state
+        .borrow_mut()
+        .borrow_mut::<JSContext>()
+        .session(task)?;
 
-    let result = tx.query(db_alias, query, params).map_err(any_error)?;
+    let result = tx.query(db_alias, query, params).await.map_err(any_error)?;
     Ok(QueryResult {
         rows: result.rows,
         row_names: result.row_names,
@@ -609,41 +1094,163 @@ fn nino_tx_execute_query(
     })
 }
 
-#[op2]
+#[op2(async)]
 #[bigint]
-fn nino_tx_execute_upsert(
-    state: &mut OpState,
+async fn nino_a_tx_execute_upsert(
+    op_state: Rc<RefCell<OpState>>,
+    #[smi] task: u32,
     #[string] db_alias: String,
     #[serde] query: Vec<String>,
     #[serde] query_types: Vec<i16>,
 ) -> Result<u64, JsErrorBox> {
     let (query, params) = query_types_to_params(query, query_types)?;
-    let tx = state.borrow_mut::<TransactionSession>();
-    tx.upsert(db_alias, query, params).map_err(any_error)
+    let tx = op_state
+        .borrow_mut()
+        .borrow_mut::<JSContext>()
+        .session(task)?;
+    tx.upsert(db_alias, query, params).await.map_err(any_error)
 }
 
-#[op2]
-#[string]
-fn nino_get_request_body(state: &mut OpState) -> Result<String, JsErrorBox> {
+fn get_buffered_body(state: &mut OpState, task: u32) -> Result<&Vec<u8>, JsErrorBox> {
     let context = state.borrow_mut::<JSContext>();
 
-    if context.task.is_some() {
-        if let Some(JSTask::Servlet(servlet)) = context.task.as_mut() {
-            Ok(servlet.body.clone())
-        } else {
-            Err(JsErrorBox::generic("task is not a request"))
+    if let JSTask::Servlet(servlet) = context.task(task)? {
+        match &servlet.body {
+            RequestBody::Buffered(body) => Ok(body),
+            RequestBody::Streamed(_) => Err(JsErrorBox::generic(
+                "request body is streamed, use request.readBody()",
+            )),
         }
     } else {
-        Err(JsErrorBox::generic("no current task"))
+        Err(JsErrorBox::generic("task is not a request"))
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpRequestPart {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+    content: ToJsBuffer,
+}
+
+#[op2]
+#[serde]
+fn nino_get_request_parts(
+    state: &mut OpState,
+    #[smi] task: u32,
+) -> Result<Vec<HttpRequestPart>, JsErrorBox> {
+    let context = state.borrow_mut::<JSContext>();
+
+    match context.get(task)?.task.as_ref() {
+        Some(JSTask::Servlet(servlet)) => Ok(servlet
+            .parts
+            .iter()
+            .map(|part| HttpRequestPart {
+                name: part.name.clone(),
+                filename: part.filename.clone(),
+                content_type: part.content_type.clone(),
+                content: part.content.clone().into(),
+            })
+            .collect()),
+        Some(_) => Err(JsErrorBox::generic("task is not a request")),
+        None => Err(JsErrorBox::generic("no current task")),
+    }
+}
+
+#[op2]
+#[string]
+fn nino_get_request_body(state: &mut OpState, #[smi] task: u32) -> Result<String, JsErrorBox> {
+    let body = get_buffered_body(state, task)?;
+    Ok(String::from_utf8_lossy(body).to_string())
+}
+
+#[op2]
+#[serde]
+fn nino_get_request_body_bytes(
+    state: &mut OpState,
+    #[smi] task: u32,
+) -> Result<ToJsBuffer, JsErrorBox> {
+    let body = get_buffered_body(state, task)?;
+    Ok(body.clone().into())
+}
+
+// the biggest chunk of a streamed request body read at once
+const MAX_BODY_CHUNK: usize = 1024 * 1024;
+
+/// reads the next chunk of a streamed request body.
+/// returns null when the whole body has been read.
+/// a body over the size limit is answered with 413
+#[op2(async)]
+#[serde]
+async fn nino_a_read_request_body(
+    op_state: Rc<RefCell<OpState>>,
+    #[smi] task: u32,
+    #[smi] max_length: u32,
+) -> Result<Option<ToJsBuffer>, JsErrorBox> {
+    let reader = {
+        let mut state = op_state.borrow_mut();
+        let context = state.borrow_mut::<JSContext>();
+        match context.get(task)?.task.as_ref() {
+            Some(JSTask::Servlet(servlet)) => match &servlet.body {
+                RequestBody::Streamed(reader) => reader.clone(),
+                RequestBody::Buffered(_) => {
+                    return Err(JsErrorBox::generic(
+                        "request body is buffered, use request.getBodyBytes()",
+                    ))
+                }
+            },
+            Some(_) => return Err(JsErrorBox::generic("task is not a request")),
+            None => return Err(JsErrorBox::generic("no current task")),
+        }
+    };
+
+    let mut reader = reader.lock().await;
+    let length = (max_length as usize).clamp(1, MAX_BODY_CHUNK);
+    let mut buffer = vec![0u8; length];
+    let length = reader
+        .body
+        .read(&mut buffer)
+        .await
+        .map_err(|e| JsErrorBox::generic(e.to_string()))?;
+    if length == 0 {
+        return Ok(None);
+    }
+    reader.read += length;
+    if reader.read > reader.max_size {
+        let message = format!(
+            "request body exceeds the maximum size of {} bytes",
+            reader.max_size
+        );
+        drop(reader);
+        // the client gets the same 413 as for the buffered bodies
+        if let Ok(mut servlet_task) = take_servlet_task(op_state, task) {
+            if servlet_task.chunked {
+                servlet_task.stream.release(false);
+            } else {
+                // the rest of the body is not read, the connection can not be reused
+                servlet_task.stream.keep_alive = false;
+                let mut response = Response::new(StatusCode::PayloadTooLarge);
+                response.set_body(message.as_str());
+                if let Err(error) =
+                    nino_functions::send_response_to_stream(servlet_task.stream, &mut response)
+                        .await
+                {
+                    eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+                }
+            }
+        }
+        return Err(JsErrorBox::generic(message));
     }
+    buffer.truncate(length);
+    Ok(Some(buffer.into()))
 }
 
 #[op2]
 #[string]
 fn nino_get_user_jwt(_state: &mut OpState, #[string] user: String) -> Result<String, JsErrorBox> {
-    let mut map: HashMap<String, String> = HashMap::new();
-    map.insert(nino_constants::JWT_USER.to_string(), user);
-    nino_functions::jwt_from_map(nino_constants::PROGRAM_NAME, map).map_err(any_error)
+    JwtManager::sign(&user).map_err(any_error)
 }
 
 #[op2]
@@ -774,6 +1381,7 @@ async fn _nino_a_fetch_binary(
 #[op2(async)]
 async fn nino_a_set_response_from_fetch(
     op_state: Rc<RefCell<OpState>>,
+    #[smi] task: u32,
     #[string] url: String,
     #[bigint] timeout: i64,
     #[string] method: String,
@@ -782,8 +1390,12 @@ async fn nino_a_set_response_from_fetch(
 ) -> Result<(), JsErrorBox> {
     let response_in = fetch(url, timeout, method, headers, body).await?;
 
-    let servlet_task = take_servlet_task(op_state)?;
+    let servlet_task = take_servlet_task(op_state, task)?;
     let stream_out = servlet_task.stream;
+    if servlet_task.chunked {
+        stream_out.release(false);
+        return Err(JsErrorBox::generic("response already started"));
+    }
 
     nino_functions::send_request_to_stream(response_in, stream_out)
         .await
@@ -793,38 +1405,83 @@ async fn nino_a_set_response_from_fetch(
 #[op2(async)]
 async fn nino_a_log(
     op_state: Rc<RefCell<OpState>>,
+    #[smi] task: u32,
     #[string] log_text: String,
 ) -> Result<(), JsErrorBox> {
     let log = {
         let mut state = op_state.borrow_mut();
         let context = state.borrow_mut::<JSContext>();
-        if context.task.is_none() {
-            //task already closed
-            return Err(JsErrorBox::generic("task already closed"));
-        }
-
-        let task = context.task.as_ref().unwrap();
-
-        match task {
-            JSTask::Servlet(task) => LogInfo {
-                method: task.method.clone(),
-                request: task.request_path.clone(),
-                response: task.js_module.clone().unwrap(),
-                message: log_text,
-            },
-            JSTask::Message(msg) => LogInfo {
-                method: "MESSAGE".into(),
-                request: msg.clone(),
-                response: "".into(),
-                message: log_text,
-            },
-        }
+        task_log_info(context.task(task)?, log_text)
     };
 
     DBLogger::log(log).await;
     Ok(())
 }
 
+fn task_log_info(task: &JSTask, message: String) -> LogInfo {
+    match task {
+        JSTask::Servlet(task) => LogInfo {
+            method: task.method.clone(),
+            request: task.request_path.clone(),
+            response: task.js_module.clone().unwrap_or_default(),
+            message,
+        },
+        JSTask::Socket(socket) => LogInfo {
+            method: "WEBSOCKET".into(),
+            request: socket.request_path.clone(),
+            response: socket.js_module.clone(),
+            message,
+        },
+        JSTask::Message(msg) => LogInfo {
+            method: "MESSAGE".into(),
+            request: msg.clone(),
+            response: "".into(),
+            message,
+        },
+    }
+}
+
+/// logs the error trace of the current task with an error id and sends the server error page.
+/// executable error pages are queued as new tasks with the error in request.error
+#[op2(async)]
+async fn nino_a_send_server_error(
+    op_state: Rc<RefCell<OpState>>,
+    #[smi] task: u32,
+    #[string] trace: String,
+) -> Result<(), JsErrorBox> {
+    let (settings, log, is_servlet) = {
+        let mut state = op_state.borrow_mut();
+        let settings = state.borrow::<SettingsManager>().clone();
+        let current = state.borrow_mut::<JSContext>().task(task)?;
+        (
+            settings,
+            task_log_info(current, String::new()),
+            matches!(current, JSTask::Servlet(_)),
+        )
+    };
+    let nino = NINO_CONTEXT.get().unwrap();
+    let errors = ErrorPageManager::new(Arc::new(settings), nino.dynamics.clone());
+    let error = errors.server_error(trace, log).await;
+    if !is_servlet {
+        // messages have no one to respond to
+        return Ok(());
+    }
+
+    let mut servlet_task = take_servlet_task(op_state, task)?;
+    if servlet_task.chunked {
+        // the head is already sent - the client sees the response truncated
+        servlet_task.stream.release(false);
+        return Ok(());
+    }
+    // a failing error page is answered without the page, to avoid error loops
+    let use_page = servlet_task.error.is_none();
+    servlet_task.error = Some(error);
+    errors
+        .send_error(servlet_task, use_page, &[])
+        .await
+        .map_err(any_error)
+}
+
 // sync to async
 // #[op2]
 // async fn nino_async_task(state: Rc<RefCell<OpState>>) -> Result<(), JsErrorBox> {
@@ -0,0 +1,452 @@
+use crate::db_settings::SettingsManager;
+use crate::nino_constants;
+use anyhow::Error;
+use base64::alphabet;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig, STANDARD};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use deno_core::url::{quirks, Url};
+use deno_core::*;
+use deno_error::*;
+use http_types::convert::{Deserialize, Serialize};
+use openssl::hash::MessageDigest;
+use reqwest::{redirect, Client, Method};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::{cell::RefCell, rc::Rc};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+// ops of the standard web APIs, the globals are installed by the _web module
+deno_core::extension!(
+    nino_web_extention,
+    ops = [
+        nino_a_web_timer,
+        nino_web_timer_cancel,
+        nino_web_url_parse,
+        nino_web_url_set,
+        nino_web_url_search_params_parse,
+        nino_web_url_search_params_stringify,
+        nino_web_atob,
+        nino_web_btoa,
+        nino_web_random_values,
+        nino_web_random_uuid,
+        nino_web_digest,
+        nino_a_web_fetch,
+        nino_web_fetch_cancel,
+    ],
+    state = create_web_state,
+);
+
+fn create_web_state(state: &mut OpState) {
+    state.put(WebTimers::default());
+    state.put(WebFetches::default());
+}
+
+fn any_error(error: Error) -> JsErrorBox {
+    JsErrorBox::generic(error.to_string())
+}
+
+/// the pending timers of the runtime by timer id
+#[derive(Default)]
+pub struct WebTimers {
+    timers: HashMap<u32, oneshot::Sender<()>>,
+}
+
+/// the fetch() requests with an abort signal by fetch id
+#[derive(Default)]
+pub struct WebFetches {
+    fetches: HashMap<u32, oneshot::Sender<()>>,
+}
+
+/// cancels the pending timers and fetches, so they do not keep the event loop of a stopped runtime
+pub fn cancel_timers(state: &mut OpState) {
+    if let Some(web_timers) = state.try_borrow_mut::<WebTimers>() {
+        web_timers.timers.clear();
+    }
+    if let Some(web_fetches) = state.try_borrow_mut::<WebFetches>() {
+        web_fetches.fetches.clear();
+    }
+}
+
+/// waits for the timer. false when it is cancelled
+#[op2(async)]
+async fn nino_a_web_timer(
+    op_state: Rc<RefCell<OpState>>,
+    #[smi] id: u32,
+    #[smi] millis: u32,
+) -> bool {
+    let (cancel_sx, cancel_rx) = oneshot::channel::<()>();
+    op_state
+        .borrow_mut()
+        .borrow_mut::<WebTimers>()
+        .timers
+        .insert(id, cancel_sx);
+    // the dropped sender cancels the timer as well
+    let fired = tokio::select! {
+        _ = tokio::time::sleep(std::time::Duration::from_millis(millis as u64)) => true,
+        _ = cancel_rx => false,
+    };
+    if fired {
+        op_state
+            .borrow_mut()
+            .borrow_mut::<WebTimers>()
+            .timers
+            .remove(&id);
+    }
+    fired
+}
+
+#[op2(fast)]
+fn nino_web_timer_cancel(state: &mut OpState, #[smi] id: u32) {
+    state.borrow_mut::<WebTimers>().timers.remove(&id);
+}
+
+/// the components of an URL as the properties of the URL class
+#[derive(Serialize)]
+pub struct UrlParts {
+    href: String,
+    origin: String,
+    protocol: String,
+    username: String,
+    password: String,
+    host: String,
+    hostname: String,
+    port: String,
+    pathname: String,
+    search: String,
+    hash: String,
+}
+
+impl From<&Url> for UrlParts {
+    fn from(url: &Url) -> Self {
+        UrlParts {
+            href: quirks::href(url).to_string(),
+            origin: quirks::origin(url),
+            protocol: quirks::protocol(url).to_string(),
+            username: quirks::username(url).to_string(),
+            password: quirks::password(url).to_string(),
+            host: quirks::host(url).to_string(),
+            hostname: quirks::hostname(url).to_string(),
+            port: quirks::port(url).to_string(),
+            pathname: quirks::pathname(url).to_string(),
+            search: quirks::search(url).to_string(),
+            hash: quirks::hash(url).to_string(),
+        }
+    }
+}
+
+fn parse_url(href: &str, base: Option<&str>) -> Result<Url, JsErrorBox> {
+    let base = base.map(Url::parse).transpose();
+    let url = base.and_then(|base| Url::options().base_url(base.as_ref()).parse(href));
+    url.map_err(|error| JsErrorBox::type_error(format!("invalid URL {}: {}", href, error)))
+}
+
+#[op2]
+#[serde]
+fn nino_web_url_parse(
+    #[string] href: String,
+    #[string] base: Option<String>,
+) -> Result<UrlParts, JsErrorBox> {
+    let url = parse_url(&href, base.as_deref())?;
+    Ok(UrlParts::from(&url))
+}
+
+/// sets a component of the URL. invalid values are ignored like in the browsers
+#[op2]
+#[serde]
+fn nino_web_url_set(
+    #[string] href: String,
+    #[string] part: String,
+    #[string] value: String,
+) -> Result<UrlParts, JsErrorBox> {
+    let mut url = parse_url(&href, None)?;
+    let _ignored = match part.as_str() {
+        "href" => {
+            url = parse_url(&value, None)?;
+            Ok(())
+        }
+        "protocol" => quirks::set_protocol(&mut url, &value),
+        "username" => quirks::set_username(&mut url, &value),
+        "password" => quirks::set_password(&mut url, &value),
+        "host" => quirks::set_host(&mut url, &value),
+        "hostname" => quirks::set_hostname(&mut url, &value),
+        "port" => quirks::set_port(&mut url, &value),
+        "pathname" => {
+            quirks::set_pathname(&mut url, &value);
+            Ok(())
+        }
+        "search" => {
+            quirks::set_search(&mut url, &value);
+            Ok(())
+        }
+        "hash" => {
+            quirks::set_hash(&mut url, &value);
+            Ok(())
+        }
+        _ => return Err(JsErrorBox::type_error(format!("URL has no {}", part))),
+    };
+    Ok(UrlParts::from(&url))
+}
+
+#[op2]
+#[serde]
+fn nino_web_url_search_params_parse(#[string] query: String) -> Vec<(String, String)> {
+    let query = query.strip_prefix('?').unwrap_or(&query);
+    deno_core::url::form_urlencoded::parse(query.as_bytes())
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[op2]
+#[string]
+fn nino_web_url_search_params_stringify(#[serde] params: Vec<(String, String)>) -> String {
+    deno_core::url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(params)
+        .finish()
+}
+
+// the forgiving base64 of atob: the padding is optional and the unused bits are ignored
+const FORGIVING_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_allow_trailing_bits(true)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// decodes base64 to a binary string, a char per byte
+#[op2]
+#[string]
+fn nino_web_atob(#[string] data: String) -> Result<String, JsErrorBox> {
+    let data: String = data.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    let bytes = FORGIVING_BASE64
+        .decode(data)
+        .map_err(|error| JsErrorBox::generic(format!("invalid base64: {}", error)))?;
+    Ok(bytes.into_iter().map(char::from).collect())
+}
+
+/// encodes a binary string to base64. chars over 0xFF are not allowed
+#[op2]
+#[string]
+fn nino_web_btoa(#[string] data: String) -> Result<String, JsErrorBox> {
+    let bytes = data
+        .chars()
+        .map(u8::try_from)
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| JsErrorBox::generic("btoa() string contains characters over 0xFF"))?;
+    Ok(STANDARD.encode(bytes))
+}
+
+#[op2(fast)]
+fn nino_web_random_values(#[buffer] buffer: &mut [u8]) -> Result<(), JsErrorBox> {
+    openssl::rand::rand_bytes(buffer).map_err(|error| JsErrorBox::generic(error.to_string()))
+}
+
+#[op2]
+#[string]
+fn nino_web_random_uuid() -> String {
+    Uuid::new_v4().to_string()
+}
+
+#[op2]
+#[serde]
+fn nino_web_digest(
+    #[string] algorithm: String,
+    #[buffer] data: &[u8],
+) -> Result<ToJsBuffer, JsErrorBox> {
+    let digest = match algorithm.to_uppercase().as_str() {
+        "SHA-1" => MessageDigest::sha1(),
+        "SHA-256" => MessageDigest::sha256(),
+        "SHA-384" => MessageDigest::sha384(),
+        "SHA-512" => MessageDigest::sha512(),
+        _ => {
+            return Err(JsErrorBox::generic(format!(
+                "digest algorithm {} is not supported",
+                algorithm
+            )))
+        }
+    };
+    let hash = openssl::hash::hash(digest, data)
+        .map_err(|error| JsErrorBox::generic(error.to_string()))?;
+    Ok(hash.to_vec().into())
+}
+
+/// the response of fetch() with the whole body
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebFetchResponse {
+    url: String,
+    redirected: bool,
+    status: u16,
+    status_text: String,
+    headers: Vec<(String, String)>,
+    body: ToJsBuffer,
+}
+
+// the clients are shared, so the connections are reused
+static FETCH_CLIENT: OnceLock<Client> = OnceLock::new();
+static FETCH_CLIENT_NO_REDIRECT: OnceLock<Client> = OnceLock::new();
+
+fn fetch_client(follow_redirects: bool) -> &'static Client {
+    if follow_redirects {
+        FETCH_CLIENT.get_or_init(Client::new)
+    } else {
+        FETCH_CLIENT_NO_REDIRECT.get_or_init(|| {
+            Client::builder()
+                .redirect(redirect::Policy::none())
+                .build()
+                .unwrap_or_default()
+        })
+    }
+}
+
+/// the request of fetch(). the id identifies the fetch for the abort signal, 0 - no signal
+#[derive(Deserialize)]
+pub struct WebFetchRequest {
+    id: u32,
+    url: String,
+    method: String,
+    headers: Vec<(String, String)>,
+    redirect: String,
+}
+
+/// the time and size limits of fetch()
+struct WebFetchLimits {
+    timeout_ms: u64,
+    max_body_size: usize,
+}
+
+#[op2(async)]
+#[serde]
+async fn nino_a_web_fetch(
+    op_state: Rc<RefCell<OpState>>,
+    #[serde] request: WebFetchRequest,
+    #[buffer] body: JsBuffer,
+) -> Result<WebFetchResponse, JsErrorBox> {
+    let settings = op_state.borrow().try_borrow::<SettingsManager>().cloned();
+    let limits = match settings {
+        Some(settings) => WebFetchLimits {
+            timeout_ms: settings
+                .get_setting_i32(
+                    nino_constants::SETTINGS_JS_FETCH_TIMEOUT,
+                    nino_constants::SETTINGS_JS_FETCH_TIMEOUT_DEFAULT,
+                )
+                .await
+                .max(0) as u64,
+            max_body_size: settings
+                .get_setting_usize(
+                    nino_constants::SETTINGS_NINO_WEB_MAX_BODY_SIZE,
+                    nino_constants::SETTINGS_NINO_WEB_MAX_BODY_SIZE_DEFAULT,
+                )
+                .await,
+        },
+        None => WebFetchLimits {
+            timeout_ms: nino_constants::SETTINGS_JS_FETCH_TIMEOUT_DEFAULT as u64,
+            max_body_size: nino_constants::SETTINGS_NINO_WEB_MAX_BODY_SIZE_DEFAULT as usize,
+        },
+    };
+
+    let id = request.id;
+    let (abort_sx, abort_rx) = oneshot::channel::<()>();
+    if id > 0 {
+        op_state
+            .borrow_mut()
+            .borrow_mut::<WebFetches>()
+            .fetches
+            .insert(id, abort_sx);
+    }
+    let fetched = web_fetch(request, body.to_vec(), &limits);
+    let fetched = async {
+        if limits.timeout_ms > 0 {
+            tokio::time::timeout(Duration::from_millis(limits.timeout_ms), fetched)
+                .await
+                .unwrap_or_else(|_| Err(Error::msg("fetch() timed out")))
+        } else {
+            fetched.await
+        }
+    };
+    // the dropped sender aborts the fetch as well, ex: on restart
+    let result = tokio::select! {
+        result = fetched => result,
+        _ = abort_rx, if id > 0 => Err(Error::msg("fetch() aborted")),
+    };
+    if id > 0 {
+        op_state
+            .borrow_mut()
+            .borrow_mut::<WebFetches>()
+            .fetches
+            .remove(&id);
+    }
+    result.map_err(any_error)
+}
+
+/// aborts the fetch of the abort signal
+#[op2(fast)]
+fn nino_web_fetch_cancel(state: &mut OpState, #[smi] id: u32) {
+    state.borrow_mut::<WebFetches>().fetches.remove(&id);
+}
+
+async fn web_fetch(
+    request: WebFetchRequest,
+    body: Vec<u8>,
+    limits: &WebFetchLimits,
+) -> Result<WebFetchResponse, Error> {
+    let method = Method::from_bytes(request.method.as_bytes())?;
+    let follow_redirects = request.redirect != "manual" && request.redirect != "error";
+    let mut builder = fetch_client(follow_redirects).request(method, request.url.as_str());
+    for (name, value) in request.headers {
+        builder = builder.header(name, value);
+    }
+    if !body.is_empty() {
+        builder = builder.body(body);
+    }
+    let mut response = builder.send().await?;
+
+    let status = response.status();
+    if status.is_redirection() && request.redirect == "error" {
+        return Err(Error::msg(format!(
+            "fetch() redirected with {} while redirect is error",
+            status.as_u16()
+        )));
+    }
+    let url = response.url().to_string();
+    let redirected = url != request.url;
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.as_str().to_string(),
+                String::from_utf8_lossy(value.as_bytes()).to_string(),
+            )
+        })
+        .collect();
+    let too_large = || {
+        Error::msg(format!(
+            "fetch() response body is bigger than {} bytes",
+            limits.max_body_size
+        ))
+    };
+    if response
+        .content_length()
+        .is_some_and(|length| length > limits.max_body_size as u64)
+    {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > limits.max_body_size {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(WebFetchResponse {
+        url,
+        redirected,
+        status: status.as_u16(),
+        status_text: status.canonical_reason().unwrap_or_default().to_string(),
+        headers,
+        body: body.into(),
+    })
+}
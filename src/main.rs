@@ -7,12 +7,25 @@ mod js;
 mod js_core;
 mod js_functions;
 mod js_inspector;
+mod js_web;
 mod nino_constants;
 mod nino_functions;
 mod nino_structures;
 mod web;
+mod web_cache;
+mod web_compression;
+mod web_cors;
+mod web_errors;
+mod web_events;
+mod web_jwt;
+mod web_limits;
+mod web_multipart;
 mod web_requests;
 mod web_responses;
+mod web_roles;
+mod web_sockets;
+mod web_stream;
+mod web_tls;
 
 use crate::{db_settings::SettingsManager, nino_constants::info};
 use db_log::DBLogger;
@@ -134,6 +147,36 @@ async fn get_db_settings(connection_string: String) -> InitialSettings {
             nino_constants::SETTINGS_JS_THREAD_COUNT_DEFAULT,
         )
         .await;
+    let js_concurrent_tasks = settings
+        .get_setting_i32(
+            nino_constants::SETTINGS_JS_CONCURRENT_TASKS,
+            nino_constants::SETTINGS_JS_CONCURRENT_TASKS_DEFAULT,
+        )
+        .await
+        .max(1) as u32;
+    let js_task_timeout_ms = settings
+        .get_setting_i32(
+            nino_constants::SETTINGS_JS_TASK_TIMEOUT,
+            nino_constants::SETTINGS_JS_TASK_TIMEOUT_DEFAULT,
+        )
+        .await
+        .max(0) as u32;
+    let js_heap_limit = settings
+        .get_setting_usize(
+            nino_constants::SETTINGS_JS_HEAP_LIMIT,
+            nino_constants::SETTINGS_JS_HEAP_LIMIT_DEFAULT,
+        )
+        .await;
+    let js_snapshot_modules = settings
+        .get_setting_str(
+            nino_constants::SETTINGS_JS_SNAPSHOT_MODULES,
+            nino_constants::SETTINGS_JS_SNAPSHOT_MODULES_DEFAULT,
+        )
+        .await
+        .split(',')
+        .map(|module| module.trim().to_string())
+        .filter(|module| !module.is_empty())
+        .collect();
     let debug_port = settings
         .get_setting_i32(
             nino_constants::SETTINGS_NINO_DEBUG_PORT,
@@ -158,6 +201,10 @@ async fn get_db_settings(connection_string: String) -> InitialSettings {
         debug_port,
         db_pool_size,
         js_thread_count,
+        js_concurrent_tasks,
+        js_task_timeout_ms,
+        js_heap_limit,
+        js_snapshot_modules,
     }
 }
 
@@ -186,18 +233,38 @@ async fn nino_init(settings: InitialSettings) -> Result<(), Error> {
         db_notifier.get_subscriber(),
     ));
 
+    let roles = Arc::new(web_roles::RoleManager::new(
+        db.clone(),
+        db_notifier.get_subscriber(),
+    ));
+
+    let cors = Arc::new(web_cors::CorsManager::new(
+        db.clone(),
+        db_notifier.get_subscriber(),
+    ));
+
+    let _jwt =
+        web_jwt::JwtManager::new(settings_manager.clone(), settings_manager.subscribe()).await;
+
+    let _tls =
+        web_tls::TlsManager::new(settings_manager.clone(), settings_manager.subscribe()).await;
+
     let dyn_subscriber = db_notifier.get_subscriber();
     let notifier = Arc::new(db_notification::Notifier::new(Arc::new(db_notifier)));
 
     let responses = Arc::new(web_responses::ResponseManager::new(
         db.clone(),
-        settings.js_thread_count,
         notifier.clone(),
         dyn_subscriber,
+        settings_manager.clone(),
     ));
 
     js::JavaScriptManager::create(
         settings.js_thread_count,
+        settings.js_concurrent_tasks,
+        settings.js_task_timeout_ms,
+        settings.js_heap_limit,
+        settings.js_snapshot_modules.clone(),
         settings.debug_port,
         db.get_connection_string(),
         responses.clone(),
@@ -217,6 +284,8 @@ async fn nino_init(settings: InitialSettings) -> Result<(), Error> {
         settings_manager.clone(),
         requests.clone(),
         responses.clone(),
+        roles.clone(),
+        cors.clone(),
     )
     .await;
 
@@ -33,6 +33,10 @@ pub const RESPONSE_TABLE: &str = concat!(PKG_NAME!(), "_response");
 pub const DATABASE_TABLE: &str = concat!(PKG_NAME!(), "_database");
 // LOG table
 pub const LOG_TABLE: &str = concat!(PKG_NAME!(), "_log");
+// USER ROLE table
+pub const USER_ROLE_TABLE: &str = concat!(PKG_NAME!(), "_user_role");
+// CORS rules table
+pub const CORS_TABLE: &str = concat!(PKG_NAME!(), "_cors");
 
 // JS settings
 pub const MODULE_MAIN: &str = "_main";
@@ -56,6 +60,27 @@ pub const SETTINGS_NINO_THREAD_COUNT_DEFAULT: i32 = 4;
 pub const SETTINGS_JS_THREAD_COUNT: &str = "nino_js_thread_count";
 pub const SETTINGS_JS_THREAD_COUNT_DEFAULT: i32 = 1;
 
+// defines how many requests a JavaScript thread serves at once while they await I/O
+pub const SETTINGS_JS_CONCURRENT_TASKS: &str = "nino_js_concurrent_tasks";
+pub const SETTINGS_JS_CONCURRENT_TASKS_DEFAULT: i32 = 1;
+
+// execution time budget in ms of a request or websocket event handler. 0 - unlimited
+pub const SETTINGS_JS_TASK_TIMEOUT: &str = "nino_js_task_timeout";
+pub const SETTINGS_JS_TASK_TIMEOUT_DEFAULT: i32 = 30000;
+
+// time limit in ms of a fetch() request, including reading the response body. 0 - unlimited
+pub const SETTINGS_JS_FETCH_TIMEOUT: &str = "nino_js_fetch_timeout";
+pub const SETTINGS_JS_FETCH_TIMEOUT_DEFAULT: i32 = 30000;
+
+// heap limit in bytes of a JavaScript thread. 0 - the V8 default
+pub const SETTINGS_JS_HEAP_LIMIT: &str = "nino_js_heap_limit";
+pub const SETTINGS_JS_HEAP_LIMIT_DEFAULT: i32 = 0;
+
+// comma separated library modules in the V8 startup snapshot of the JavaScript threads. empty - no snapshot
+pub const SETTINGS_JS_SNAPSHOT_MODULES: &str = "nino_js_snapshot_modules";
+pub const SETTINGS_JS_SNAPSHOT_MODULES_DEFAULT: &str =
+    "_web,_task,_log,_db,_jsqlx,_fetch,_notify,_socket";
+
 // defines web serving port
 pub const SETTINGS_NINO_WEB_SERVER_PORT: &str = "nino_web_server_port";
 pub const SETTINGS_NINO_WEB_SERVER_PORT_DEFAULT: i32 = 8080;
@@ -68,10 +93,122 @@ pub const SETTINGS_DB_CONNECTION_POOL_SIZE_DEFAULT: i32 = 0;
 pub const SETTINGS_NINO_DEBUG_PORT: &str = "nino_debug_port";
 pub const SETTINGS_NINO_DEBUG_PORT_DEFAULT: i32 = 0;
 
+// comma separated IPv4/IPv6 addresses to listen on. 0.0.0.0 / :: for all interfaces
+pub const SETTINGS_NINO_WEB_BIND_ADDRESSES: &str = "nino_web_bind_addresses";
+pub const SETTINGS_NINO_WEB_BIND_ADDRESSES_DEFAULT: &str = "127.0.0.1";
+
+// separate port serving only the admin paths. 0 - disabled
+pub const SETTINGS_NINO_WEB_ADMIN_PORT: &str = "nino_web_admin_port";
+pub const SETTINGS_NINO_WEB_ADMIN_PORT_DEFAULT: i32 = 0;
+
+// comma separated request paths (with their sub paths) served only on the admin port
+pub const SETTINGS_NINO_WEB_ADMIN_PATHS: &str = "nino_web_admin_paths";
+pub const SETTINGS_NINO_WEB_ADMIN_PATHS_DEFAULT: &str = "portal,login";
+
 // defines web request timeout
 pub const SETTINGS_NINO_WEB_REQUEST_TIMEOUT: &str = "nino_web_request_timeout_ms";
 pub const SETTINGS_NINO_WEB_REQUEST_TIMEOUT_DEFAULT: i32 = 30000;
 
+// keep alive connections are closed after this idle time. 0 - disables keep alive
+pub const SETTINGS_NINO_WEB_KEEP_ALIVE_TIMEOUT: &str = "nino_web_keep_alive_timeout_ms";
+pub const SETTINGS_NINO_WEB_KEEP_ALIVE_TIMEOUT_DEFAULT: i32 = 5000;
+
+// maximum requests served on a single connection
+pub const SETTINGS_NINO_WEB_KEEP_ALIVE_MAX_REQUESTS: &str = "nino_web_keep_alive_max_requests";
+pub const SETTINGS_NINO_WEB_KEEP_ALIVE_MAX_REQUESTS_DEFAULT: i32 = 100;
+
+// defines the maximum request body size in bytes. bigger requests are rejected with 413
+pub const SETTINGS_NINO_WEB_MAX_BODY_SIZE: &str = "nino_web_max_body_size";
+pub const SETTINGS_NINO_WEB_MAX_BODY_SIZE_DEFAULT: i32 = 16 * 1024 * 1024;
+
+// defines up to what size the request body is read in memory.
+// bigger bodies or with unknown length are streamed to the JS handlers
+pub const SETTINGS_NINO_WEB_MAX_BUFFERED_BODY_SIZE: &str = "nino_web_max_buffered_body_size";
+pub const SETTINGS_NINO_WEB_MAX_BUFFERED_BODY_SIZE_DEFAULT: i32 = 1024 * 1024;
+
+// defines the maximum size in bytes of a single multipart/form-data part
+pub const SETTINGS_NINO_WEB_MAX_PART_SIZE: &str = "nino_web_max_part_size";
+pub const SETTINGS_NINO_WEB_MAX_PART_SIZE_DEFAULT: i32 = 8 * 1024 * 1024;
+
+// responses smaller than this are not compressed. 0 - disables compression
+pub const SETTINGS_NINO_WEB_COMPRESSION_MIN_SIZE: &str = "nino_web_compression_min_size";
+pub const SETTINGS_NINO_WEB_COMPRESSION_MIN_SIZE_DEFAULT: i32 = 1024;
+
+// byte budget of the in memory cache of static contents and their compressed variants
+pub const SETTINGS_NINO_WEB_STATIC_CACHE_SIZE: &str = "nino_web_static_cache_size";
+pub const SETTINGS_NINO_WEB_STATIC_CACHE_SIZE_DEFAULT: i32 = 64 * 1024 * 1024;
+
+// static contents up to this size are cached and compressed.
+// bigger ones are streamed from the database in chunks and sent uncompressed
+pub const SETTINGS_NINO_WEB_STATIC_CACHE_ITEM_SIZE: &str = "nino_web_static_cache_item_size";
+pub const SETTINGS_NINO_WEB_STATIC_CACHE_ITEM_SIZE_DEFAULT: i32 = 4 * 1024 * 1024;
+
+// interval in ms of the pings keeping the idle event streams open. 0 - no pings
+pub const SETTINGS_NINO_WEB_EVENTS_PING_INTERVAL: &str = "nino_web_events_ping_interval";
+pub const SETTINGS_NINO_WEB_EVENTS_PING_INTERVAL_DEFAULT: i32 = 30000;
+
+// maximum number of open connections. further connections are answered with 503. 0 - unlimited
+pub const SETTINGS_NINO_WEB_MAX_CONNECTIONS: &str = "nino_web_max_connections";
+pub const SETTINGS_NINO_WEB_MAX_CONNECTIONS_DEFAULT: i32 = 1024;
+
+// default requests per minute of a user or an address for every request. 0 - unlimited
+pub const SETTINGS_NINO_WEB_RATE_LIMIT: &str = "nino_web_rate_limit";
+pub const SETTINGS_NINO_WEB_RATE_LIMIT_DEFAULT: i32 = 0;
+
+// requests per minute of an address for all requests, checked before routing. 0 - unlimited
+pub const SETTINGS_NINO_WEB_ADDRESS_RATE_LIMIT: &str = "nino_web_address_rate_limit";
+pub const SETTINGS_NINO_WEB_ADDRESS_RATE_LIMIT_DEFAULT: i32 = 0;
+
+// javascript tasks waiting for a thread above which dynamic requests are answered with 503. 0 - unlimited
+pub const SETTINGS_NINO_WEB_TASK_BACKLOG: &str = "nino_web_task_backlog";
+pub const SETTINGS_NINO_WEB_TASK_BACKLOG_DEFAULT: i32 = 1000;
+
+// Cache-Control of public static responses without own cache policy.
+// no-cache revalidates the content with ETag/Last-Modified on every use
+pub const SETTINGS_NINO_WEB_CACHE_CONTROL: &str = "nino_web_cache_control";
+pub const SETTINGS_NINO_WEB_CACHE_CONTROL_DEFAULT: &str = "no-cache";
+
+// TLS certificate chain and private key - PEM content or path to PEM file.
+// the server is HTTPS when the certificate is defined
+pub const SETTINGS_NINO_WEB_TLS_CERTIFICATE: &str = "nino_web_tls_certificate";
+pub const SETTINGS_NINO_WEB_TLS_PRIVATE_KEY: &str = "nino_web_tls_private_key";
+
+// plain HTTP port redirecting to HTTPS. 0 - disabled
+pub const SETTINGS_NINO_WEB_HTTP_REDIRECT_PORT: &str = "nino_web_http_redirect_port";
+pub const SETTINGS_NINO_WEB_HTTP_REDIRECT_PORT_DEFAULT: i32 = 0;
+
+// jwt signing algorithm - HS256, RS256 or ES256
+pub const SETTINGS_NINO_JWT_ALGORITHM: &str = "nino_jwt_algorithm";
+pub const SETTINGS_NINO_JWT_ALGORITHM_DEFAULT: &str = "HS256";
+
+// jwt HS256 secrets. the environment variable with the upper case name has priority
+pub const SETTINGS_NINO_JWT_SECRET: &str = "nino_jwt_secret";
+pub const SETTINGS_NINO_JWT_SECRET_PREVIOUS: &str = "nino_jwt_secret_previous";
+
+// jwt RS256/ES256 PEM keys. the environment variable with the upper case name has priority
+pub const SETTINGS_NINO_JWT_PRIVATE_KEY: &str = "nino_jwt_private_key";
+pub const SETTINGS_NINO_JWT_PUBLIC_KEY: &str = "nino_jwt_public_key";
+pub const SETTINGS_NINO_JWT_PUBLIC_KEY_PREVIOUS: &str = "nino_jwt_public_key_previous";
+
+// jwt issuer claim
+pub const SETTINGS_NINO_JWT_ISSUER: &str = "nino_jwt_issuer";
+pub const SETTINGS_NINO_JWT_ISSUER_DEFAULT: &str = PROGRAM_NAME;
+
+// jwt lifetime in seconds
+pub const SETTINGS_NINO_JWT_EXPIRATION_S: &str = "nino_jwt_expiration_s";
+pub const SETTINGS_NINO_JWT_EXPIRATION_S_DEFAULT: i32 = 8 * 60 * 60;
+
+// response names of the error pages - static or executable. empty serves plain text
+pub const SETTINGS_NINO_WEB_ERROR_PAGE_FORBIDDEN: &str = "nino_web_error_page_forbidden";
+pub const SETTINGS_NINO_WEB_ERROR_PAGE_NOT_FOUND: &str = "nino_web_error_page_not_found";
+pub const SETTINGS_NINO_WEB_ERROR_PAGE_METHOD_NOT_ALLOWED: &str =
+    "nino_web_error_page_method_not_allowed";
+pub const SETTINGS_NINO_WEB_ERROR_PAGE_SERVER_ERROR: &str = "nino_web_error_page_server_error";
+
+// 1 - server errors show the stack trace, 0 - only the error id. the trace is always logged
+pub const SETTINGS_NINO_WEB_ERROR_STACK_TRACE: &str = "nino_web_error_stack_trace";
+pub const SETTINGS_NINO_WEB_ERROR_STACK_TRACE_DEFAULT: i32 = 0;
+
 // login servlet - gets the original path in the query
 pub const SETTINGS_NINO_LOGIN_PATH: &str = "nino_login_paths";
-pub const SETTINGS_NINO_LOGIN_PATH_DEFAULT: &str = "/login";
\ No newline at end of file
+pub const SETTINGS_NINO_LOGIN_PATH_DEFAULT: &str = "/login";
@@ -1,12 +1,14 @@
 use crate::nino_constants;
-use async_std::{io::WriteExt, net::TcpStream};
+use crate::web_compression;
+use crate::web_stream::WebConnection;
+use async_std::io::WriteExt;
 use bcrypt::{hash, verify, DEFAULT_COST};
 use deno_core::anyhow::Error;
-use hmac::{digest::KeyInit, Hmac};
-use http_types::Response;
-use jwt::{SignWithKey, VerifyWithKey};
-use sha2::Sha256;
+use http_types::convert::Deserialize;
+use http_types::{Response, StatusCode};
 use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::SystemTime;
 
 /// Get the postgres connection string from the
 /// program parameters or system environment variable (in dat order of existance).
@@ -64,63 +66,452 @@ pub fn normalize_path(path: String) -> String {
     result.into_iter().collect()
 }
 
+/// parse comma separated IPv4/IPv6 addresses. IPv6 can be in brackets ([::1])
+pub fn parse_bind_addresses(addresses: &str) -> Result<Vec<IpAddr>, Error> {
+    let mut result: Vec<IpAddr> = Vec::new();
+    for address in addresses
+        .split(',')
+        .map(|a| a.trim())
+        .filter(|a| !a.is_empty())
+    {
+        let ip = address
+            .strip_prefix('[')
+            .and_then(|a| a.strip_suffix(']'))
+            .unwrap_or(address);
+        match ip.parse::<IpAddr>() {
+            Ok(ip) => result.push(ip),
+            Err(error) => {
+                return Err(Error::msg(format!(
+                    "invalid bind address {}: {}",
+                    address, error
+                )))
+            }
+        }
+    }
+    if result.is_empty() {
+        return Err(Error::msg("no bind address defined"));
+    }
+    Ok(result)
+}
+
+/// true if the normalized path is one of the prefixes or under one of them
+pub fn path_has_prefix(path: &str, prefixes: &[String]) -> bool {
+    prefixes.iter().any(|prefix| {
+        path == prefix
+            || (path.starts_with(prefix.as_str()) && path[prefix.len()..].starts_with('/'))
+    })
+}
+
+enum RouteSegment {
+    Literal(String),
+    // :name - a single path segment
+    Param(String),
+    // *name - the rest of the path
+    Rest(String),
+}
+
+/// request path pattern like api/users/:id or static/*rest
+pub struct RoutePattern {
+    path: String,
+    segments: Vec<RouteSegment>,
+}
+
+impl RoutePattern {
+    /// returns None for paths without parameters - they are matched exactly
+    pub fn parse(path: &str) -> Result<Option<RoutePattern>, Error> {
+        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let mut segments: Vec<RouteSegment> = Vec::with_capacity(parts.len());
+        for (index, part) in parts.iter().enumerate() {
+            if let Some(name) = part.strip_prefix(':') {
+                segments.push(RouteSegment::Param(name.to_string()));
+            } else if let Some(name) = part.strip_prefix('*') {
+                if index + 1 != parts.len() {
+                    return Err(Error::msg(format!(
+                        "route {}: *{} should be the last segment",
+                        path, name
+                    )));
+                }
+                segments.push(RouteSegment::Rest(name.to_string()));
+            } else {
+                segments.push(RouteSegment::Literal(part.to_string()));
+            }
+        }
+        if segments
+            .iter()
+            .all(|segment| matches!(segment, RouteSegment::Literal(_)))
+        {
+            return Ok(None);
+        }
+        Ok(Some(RoutePattern {
+            path: path.to_string(),
+            segments,
+        }))
+    }
+
+    /// matches the percent decoded url path and returns the captured parameters.
+    /// the path is not decoded again, so the routing sees the same path as the other checks
+    pub fn matches(&self, url_path: &str) -> Option<HashMap<String, String>> {
+        let parts: Vec<&str> = url_path.split('/').filter(|s| !s.is_empty()).collect();
+        if parts.iter().any(|part| *part == "." || *part == "..") {
+            return None;
+        }
+        let mut params: HashMap<String, String> = HashMap::new();
+        for (index, segment) in self.segments.iter().enumerate() {
+            match segment {
+                RouteSegment::Literal(literal) => {
+                    if !parts.get(index)?.eq_ignore_ascii_case(literal) {
+                        return None;
+                    }
+                }
+                RouteSegment::Param(name) => {
+                    params.insert(name.clone(), parts.get(index)?.to_string());
+                }
+                RouteSegment::Rest(name) => {
+                    params.insert(name.clone(), parts[index.min(parts.len())..].join("/"));
+                    return Some(params);
+                }
+            }
+        }
+        if parts.len() != self.segments.len() {
+            return None;
+        }
+        Some(params)
+    }
+
+    /// patterns are tried in order: longer literal prefix, more literals,
+    /// without rest, more segments and finally by path
+    pub fn precedence(&self, other: &RoutePattern) -> std::cmp::Ordering {
+        let key = |pattern: &RoutePattern| {
+            let prefix = pattern
+                .segments
+                .iter()
+                .take_while(|segment| matches!(segment, RouteSegment::Literal(_)))
+                .count();
+            let literals = pattern
+                .segments
+                .iter()
+                .filter(|segment| matches!(segment, RouteSegment::Literal(_)))
+                .count();
+            let rest = pattern
+                .segments
+                .iter()
+                .any(|segment| matches!(segment, RouteSegment::Rest(_)));
+            (prefix, literals, !rest, pattern.segments.len())
+        };
+        key(other)
+            .cmp(&key(self))
+            .then_with(|| self.path.cmp(&other.path))
+    }
+}
+
+/// decodes %XX sequences, invalid ones are kept as they are
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut result: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%'
+            && index + 2 < bytes.len()
+            && bytes[index + 1].is_ascii_hexdigit()
+            && bytes[index + 2].is_ascii_hexdigit()
+        {
+            let hex = |b: u8| (b as char).to_digit(16).unwrap() as u8;
+            result.push(hex(bytes[index + 1]) * 16 + hex(bytes[index + 2]));
+            index += 3;
+        } else {
+            result.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+/// weak comparison of the If-None-Match header with the entity tag
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// the byte ranges of a Range header
+#[derive(Debug, PartialEq)]
+pub enum ByteRanges {
+    // no or invalid Range header - the full content is served
+    Full,
+    // inclusive start and end positions, ordered and not overlapping
+    Partial(Vec<(usize, usize)>),
+    Unsatisfiable,
+}
+
+// more ranges are ignored and the full content is served
+const MAX_RANGES: usize = 16;
+
+/// parses the Range header for content with the size.
+/// overlapping and adjacent ranges are coalesced
+pub fn parse_ranges(range: &str, size: usize) -> ByteRanges {
+    let Some(specs) = range.trim().strip_prefix("bytes=") else {
+        return ByteRanges::Full;
+    };
+    let specs: Vec<&str> = specs
+        .split(',')
+        .map(|spec| spec.trim())
+        .filter(|spec| !spec.is_empty())
+        .collect();
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return ByteRanges::Full;
+    }
+    let mut ranges: Vec<(usize, usize)> = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let Some((start, end)) = spec.split_once('-') else {
+            return ByteRanges::Full;
+        };
+        let (start, end) = (start.trim(), end.trim());
+        if start.is_empty() {
+            // suffix range - the last bytes of the content
+            let Ok(suffix) = end.parse::<usize>() else {
+                return ByteRanges::Full;
+            };
+            if suffix > 0 && size > 0 {
+                ranges.push((size.saturating_sub(suffix), size - 1));
+            }
+            continue;
+        }
+        let Ok(start) = start.parse::<usize>() else {
+            return ByteRanges::Full;
+        };
+        let end = if end.is_empty() {
+            usize::MAX
+        } else {
+            match end.parse::<usize>() {
+                Ok(end) if end >= start => end,
+                _ => return ByteRanges::Full,
+            }
+        };
+        if start < size {
+            ranges.push((start, end.min(size - 1)));
+        }
+    }
+    if ranges.is_empty() {
+        return ByteRanges::Unsatisfiable;
+    }
+    ranges.sort();
+    let mut coalesced: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match coalesced.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => coalesced.push((start, end)),
+        }
+    }
+    ByteRanges::Partial(coalesced)
+}
+
+/// the IMF-fixdate format of HTTP dates
+pub fn http_date(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+pub fn parse_http_date(date: &str) -> Option<SystemTime> {
+    chrono::DateTime::parse_from_rfc2822(date.trim())
+        .ok()
+        .map(SystemTime::from)
+}
+
 const HTTP: &str = "HTTP/1.1";
 const CRLF: &str = "\r\n";
 const SEPARATOR: &str = ": ";
 const CONTENT_LENGTH: &str = "Content-Length";
+const CONNECTION: &str = "Connection";
+const TRANSFER_ENCODING: &str = "Transfer-Encoding";
+const LAST_CHUNK: &[u8] = b"0\r\n\r\n";
+const HOP_BY_HOP_HEADERS: [&str; 5] = [
+    "Connection",
+    "Keep-Alive",
+    "Transfer-Encoding",
+    "Content-Length",
+    "Upgrade",
+];
 
 pub async fn send_response_to_stream(
-    stream: Box<TcpStream>,
+    stream: WebConnection,
     response: &mut Response,
 ) -> Result<(), Error> {
-    match response.body_bytes().await {
-        Err(error) => {
-            eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
-        }
-        Ok(body) => {
-            if response.header(CONTENT_LENGTH).is_none() {
-                response.insert_header(CONTENT_LENGTH, format!("{}", body.len()));
-            }
+    let result = write_response(&stream, response).await;
+    // keep alive connections go back to the serving loop, the rest are closed
+    stream.release(result.is_ok());
+    result
+}
 
-            //write status
-            let mut header_string = String::with_capacity(1024);
-            header_string.push_str(HTTP);
-            header_string.push(' ');
-            header_string.push_str(&format!("{}", response.status()));
-            header_string.push(' ');
-            header_string.push_str(response.status().canonical_reason());
-            header_string.push_str(CRLF);
-
-            // write header
-            for (header_key, header_value) in response.iter() {
-                header_string.push_str(header_key.as_str());
-                header_string.push_str(SEPARATOR);
-                header_string.push_str(header_value.as_str());
-                header_string.push_str(CRLF);
-            }
+/// sends the response with a body kept outside of the response, without copying it
+pub async fn send_bytes_to_stream(
+    stream: WebConnection,
+    response: &mut Response,
+    body: &[u8],
+) -> Result<(), Error> {
+    let result = write_response_bytes(&stream, response, body).await;
+    stream.release(result.is_ok());
+    result
+}
 
-            //write separtor
-            header_string.push_str(CRLF);
+async fn write_response(stream: &WebConnection, response: &mut Response) -> Result<(), Error> {
+    let mut body = response
+        .body_bytes()
+        .await
+        .map_err(|error| Error::msg(error.to_string()))?;
+    if let Some(compression) = &stream.compression {
+        body = web_compression::compress_response(compression, response, body).await;
+    }
+    write_response_bytes(stream, response, &body).await
+}
 
-            //write body
-            {
-                let mut http_bytes = header_string.as_bytes();
-                async_std::io::copy(&mut http_bytes, &mut stream.clone()).await?;
-                async_std::io::copy(&mut body.as_slice(), &mut stream.clone()).await?;
+async fn write_response_bytes(
+    stream: &WebConnection,
+    response: &mut Response,
+    body: &[u8],
+) -> Result<(), Error> {
+    // 204 and 304 responses have no body nor length
+    let bodyless = matches!(
+        response.status(),
+        StatusCode::NoContent | StatusCode::NotModified
+    );
+    if response.header(CONTENT_LENGTH).is_none() && !bodyless {
+        response.insert_header(CONTENT_LENGTH, format!("{}", body.len()));
+    }
+    write_response_head(stream, response).await?;
+
+    //write body - HEAD responses keep the Content-Length of the body without sending it
+    let mut out = stream.stream.clone();
+    if !stream.head {
+        out.write_all(body).await?;
+    }
+    // TLS streams are buffered
+    out.flush().await?;
+    Ok(())
+}
+
+/// writes the status and the headers. the body is written and flushed by the caller.
+/// Content-Length should be already set
+pub async fn write_response_head(
+    stream: &WebConnection,
+    response: &mut Response,
+) -> Result<(), Error> {
+    response.insert_header(CONNECTION, connection_header(stream));
+    for (name, value) in &stream.headers {
+        match response.header(*name).map(|values| values.last().as_str().to_string()) {
+            // the response varies by both
+            Some(vary) if name.eq_ignore_ascii_case("Vary") => {
+                response.insert_header(*name, format!("{}, {}", vary, value));
+            }
+            Some(_) => {}
+            None => {
+                response.insert_header(*name, value.as_str());
             }
         }
-    };
+    }
 
-    //close socket - always
-    if stream.shutdown(std::net::Shutdown::Both).is_err() {
-        // stream already closed
+    //write status
+    let mut header_string = String::with_capacity(1024);
+    header_string.push_str(HTTP);
+    header_string.push(' ');
+    header_string.push_str(&format!("{}", response.status()));
+    header_string.push(' ');
+    header_string.push_str(response.status().canonical_reason());
+    header_string.push_str(CRLF);
+
+    // write header
+    for (header_key, header_value) in response.iter() {
+        header_string.push_str(header_key.as_str());
+        header_string.push_str(SEPARATOR);
+        header_string.push_str(header_value.as_str());
+        header_string.push_str(CRLF);
     }
+
+    //write separtor
+    header_string.push_str(CRLF);
+
+    stream
+        .stream
+        .clone()
+        .write_all(header_string.as_bytes())
+        .await?;
+    Ok(())
+}
+
+/// sends the response head of a body of unknown length.
+/// the body is written with write_chunk and finished with end_chunked
+pub async fn start_chunked(stream: &WebConnection, response: &mut Response) -> Result<(), Error> {
+    response.remove_header(CONTENT_LENGTH);
+    response.insert_header(TRANSFER_ENCODING, "chunked");
+    write_response_head(stream, response).await
+}
+
+/// the chunk size in hex, the data and CRLF
+pub fn chunk_frame(chunk: &[u8]) -> Vec<u8> {
+    let size = format!("{:x}{}", chunk.len(), CRLF);
+    let mut frame = Vec::with_capacity(size.len() + chunk.len() + CRLF.len());
+    frame.extend_from_slice(size.as_bytes());
+    frame.extend_from_slice(chunk);
+    frame.extend_from_slice(CRLF.as_bytes());
+    frame
+}
+
+/// writes and flushes the chunk, so the client gets it without waiting for the rest
+pub async fn write_chunk(stream: &WebConnection, chunk: &[u8]) -> Result<(), Error> {
+    // an empty chunk would end the body
+    if stream.head || chunk.is_empty() {
+        return Ok(());
+    }
+    let mut out = stream.stream.clone();
+    out.write_all(&chunk_frame(chunk)).await?;
+    out.flush().await?;
     Ok(())
 }
 
+/// writes the last data chunk and the end of the chunked body
+pub async fn end_chunked(stream: WebConnection, chunk: &[u8]) -> Result<(), Error> {
+    let result = write_last_chunk(&stream, chunk).await;
+    stream.release(result.is_ok());
+    result
+}
+
+async fn write_last_chunk(stream: &WebConnection, chunk: &[u8]) -> Result<(), Error> {
+    let mut out = stream.stream.clone();
+    if !stream.head {
+        let mut frame = if chunk.is_empty() {
+            Vec::with_capacity(LAST_CHUNK.len())
+        } else {
+            chunk_frame(chunk)
+        };
+        frame.extend_from_slice(LAST_CHUNK);
+        out.write_all(&frame).await?;
+    }
+    out.flush().await?;
+    Ok(())
+}
+
+fn connection_header(stream: &WebConnection) -> &'static str {
+    if stream.keep_alive {
+        "keep-alive"
+    } else {
+        "close"
+    }
+}
+
 pub async fn send_request_to_stream(
     response_in: reqwest::Response,
-    mut stream_out: Box<TcpStream>,
+    stream_out: WebConnection,
+) -> Result<(), Error> {
+    let result = write_request(response_in, &stream_out).await;
+    stream_out.release(result.is_ok());
+    result
+}
+
+async fn write_request(
+    response_in: reqwest::Response,
+    stream_out: &WebConnection,
 ) -> Result<(), Error> {
     //write status
     let mut header_string = String::with_capacity(1024);
@@ -136,30 +527,45 @@ pub async fn send_request_to_stream(
     header_string.push_str(canonical_reason);
     header_string.push_str(CRLF);
 
-    // write header
+    // write header - the body is sent whole, so the framing headers are replaced
     for (header_key, header_value) in response_in.headers() {
+        if HOP_BY_HOP_HEADERS
+            .iter()
+            .any(|header| header_key.as_str().eq_ignore_ascii_case(header))
+        {
+            continue;
+        }
         header_string.push_str(header_key.as_str());
         header_string.push_str(SEPARATOR);
         header_string.push_str(header_value.to_str()?);
         header_string.push_str(CRLF);
     }
+    let bytes = response_in.bytes().await?;
+    header_string.push_str(&format!(
+        "{}{}{}{}",
+        CONTENT_LENGTH,
+        SEPARATOR,
+        bytes.len(),
+        CRLF
+    ));
+    header_string.push_str(&format!(
+        "{}{}{}{}",
+        CONNECTION,
+        SEPARATOR,
+        connection_header(stream_out),
+        CRLF
+    ));
 
     //write separtor
     header_string.push_str(CRLF);
 
     //copy stream
-    {
-        let mut http_bytes = header_string.as_bytes();
-        async_std::io::copy(&mut http_bytes, &mut stream_out.clone()).await?;
-        let bytes = response_in.bytes().await?;
-        stream_out.write(&bytes).await?;
-        stream_out.flush().await?;
-    }
-
-    //close socket - always
-    if stream_out.shutdown(std::net::Shutdown::Both).is_err() {
-        // stream already closed
+    let mut stream = stream_out.stream.clone();
+    stream.write_all(header_string.as_bytes()).await?;
+    if !stream_out.head {
+        stream.write_all(&bytes).await?;
     }
+    stream.flush().await?;
     Ok(())
 }
 
@@ -181,34 +587,187 @@ pub fn password_verify(password: &str, hash: &str) -> Result<bool, Error> {
     }
 }
 
-pub fn jwt_from_map(secret: &str, map: HashMap<String, String>) -> Result<String, Error> {
-    let key: Hmac<Sha256> = Hmac::new_from_slice(secret.as_bytes())?;
-    let jwt = map.sign_with_key(&key)?;
-    Ok(jwt)
+/// parse the values of the Cookie headers into a name to value map.
+/// the first occurrence of a cookie name wins as the browsers send the most specific path first
+pub fn parse_cookies<'a>(cookie_headers: impl Iterator<Item = &'a str>) -> HashMap<String, String> {
+    let mut cookies: HashMap<String, String> = HashMap::new();
+    for cookie_header in cookie_headers {
+        for cookie_token in cookie_header.split(';') {
+            let token = cookie_token.trim();
+            if let Some((name, value)) = token.split_once('=') {
+                let name = name.trim();
+                if name.is_empty() {
+                    continue;
+                }
+                let value = value.trim();
+                // quoted values are allowed by the rfc6265
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                cookies
+                    .entry(name.to_string())
+                    .or_insert_with(|| value.to_string());
+            }
+        }
+    }
+    cookies
 }
 
-pub fn jwt_to_map(secret: &str, jwt: &str) -> Result<HashMap<String, String>, Error> {
-    let key: Hmac<Sha256> = Hmac::new_from_slice(secret.as_bytes())?;
-    let map_decoded: HashMap<String, String> = jwt.verify_with_key(&key)?;
-    Ok(map_decoded)
+/// attributes for the Set-Cookie response header
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CookieOptions {
+    pub path: Option<String>,
+    pub domain: Option<String>,
+    pub max_age: Option<i64>,
+    pub http_only: bool,
+    pub secure: bool,
+    pub same_site: Option<String>,
+}
+
+fn is_cookie_name_char(c: char) -> bool {
+    c.is_ascii_graphic() && !"()<>@,;:\\\"/[]?={}".contains(c)
+}
+
+fn is_cookie_value_char(c: char) -> bool {
+    c.is_ascii_graphic() && !"\",;\\".contains(c)
+}
+
+/// create Set-Cookie header value from name, value and cookie attributes
+pub fn cookie_to_string(name: &str, value: &str, options: &CookieOptions) -> Result<String, Error> {
+    if name.is_empty() || !name.chars().all(is_cookie_name_char) {
+        return Err(Error::msg(format!("invalid cookie name: '{}'", name)));
+    }
+    if !value.chars().all(is_cookie_value_char) {
+        return Err(Error::msg(format!("invalid cookie value for: '{}'", name)));
+    }
+    let mut cookie = format!("{}={}", name, value);
+    if let Some(path) = &options.path {
+        cookie.push_str("; Path=");
+        cookie.push_str(path);
+    }
+    if let Some(domain) = &options.domain {
+        cookie.push_str("; Domain=");
+        cookie.push_str(domain);
+    }
+    if let Some(max_age) = options.max_age {
+        cookie.push_str(&format!("; Max-Age={}", max_age));
+    }
+    let mut secure = options.secure;
+    if let Some(same_site) = &options.same_site {
+        let same_site = match same_site.to_lowercase().as_str() {
+            "strict" => "Strict",
+            "lax" => "Lax",
+            "none" => {
+                // browsers reject SameSite=None without Secure
+                secure = true;
+                "None"
+            }
+            _ => {
+                return Err(Error::msg(format!(
+                    "invalid cookie SameSite value: '{}'",
+                    same_site
+                )))
+            }
+        };
+        cookie.push_str("; SameSite=");
+        cookie.push_str(same_site);
+    }
+    if secure {
+        cookie.push_str("; Secure");
+    }
+    if options.http_only {
+        cookie.push_str("; HttpOnly");
+    }
+    Ok(cookie)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::nino_functions::{
-        jwt_from_map, jwt_to_map, normalize_path, password_hash, password_verify,
+        chunk_frame, cookie_to_string, etag_matches, http_date, normalize_path,
+        parse_bind_addresses, parse_cookies, parse_http_date, parse_ranges, password_hash,
+        password_verify, path_has_prefix, percent_decode, ByteRanges, CookieOptions, RoutePattern,
     };
-    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_parse_cookies() {
+        let headers = [
+            "nino=jwt.value; theme=\"dark\"",
+            " lang = en ;nino=other;broken",
+        ];
+        let cookies = parse_cookies(headers.into_iter());
+        assert_eq!(cookies.len(), 3);
+        assert_eq!(cookies.get("nino").unwrap(), "jwt.value");
+        assert_eq!(cookies.get("theme").unwrap(), "dark");
+        assert_eq!(cookies.get("lang").unwrap(), "en");
+    }
+
+    #[test]
+    fn test_cookie_to_string() {
+        let options = CookieOptions {
+            path: Some("/".into()),
+            max_age: Some(3600),
+            http_only: true,
+            same_site: Some("none".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            cookie_to_string("nino", "value", &options).unwrap(),
+            "nino=value; Path=/; Max-Age=3600; SameSite=None; Secure; HttpOnly"
+        );
+        assert_eq!(
+            cookie_to_string("nino", "", &CookieOptions::default()).unwrap(),
+            "nino="
+        );
+        assert!(cookie_to_string("ni;no", "value", &options).is_err());
+        assert!(cookie_to_string("nino", "va lue", &options).is_err());
+    }
+
+    #[test]
+    fn test_conditional_headers() {
+        assert!(etag_matches("\"a1\", \"b2\"", "\"b2\""));
+        assert!(etag_matches("W/\"b2\"", "\"b2\""));
+        assert!(etag_matches("*", "\"b2\""));
+        assert!(!etag_matches("\"b2-br\"", "\"b2\""));
+
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(time));
+        assert_eq!(parse_http_date("yesterday"), None);
+    }
+
+    #[test]
+    fn test_parse_ranges() {
+        assert_eq!(
+            parse_ranges("bytes=0-99", 1000),
+            ByteRanges::Partial(vec![(0, 99)])
+        );
+        assert_eq!(
+            parse_ranges("bytes=900-", 1000),
+            ByteRanges::Partial(vec![(900, 999)])
+        );
+        assert_eq!(
+            parse_ranges("bytes=-100, 0-1999", 1000),
+            ByteRanges::Partial(vec![(0, 999)])
+        );
+        assert_eq!(
+            parse_ranges("bytes=500-599, 0-9, 10-19", 1000),
+            ByteRanges::Partial(vec![(0, 19), (500, 599)])
+        );
+        assert_eq!(parse_ranges("bytes=1000-", 1000), ByteRanges::Unsatisfiable);
+        assert_eq!(parse_ranges("bytes=-0", 1000), ByteRanges::Unsatisfiable);
+        assert_eq!(parse_ranges("bytes=9-1", 1000), ByteRanges::Full);
+        assert_eq!(parse_ranges("items=0-1", 1000), ByteRanges::Full);
+        assert_eq!(parse_ranges("bytes=a-b", 1000), ByteRanges::Full);
+    }
 
     #[test]
-    fn test_jwt_hashing() {
-        let secret = String::from("nino");
-        let mut map: HashMap<String, String> = HashMap::new();
-        map.insert("key".to_owned(), "value".to_owned());
-        let jwt = jwt_from_map(&secret, map).unwrap();
-        assert!(!jwt.is_empty());
-        let map_decoded = jwt_to_map(&secret, &jwt).unwrap();
-        assert!("value" == map_decoded.get("key").unwrap());
+    fn test_chunk_frame() {
+        assert_eq!(chunk_frame(b"hello"), b"5\r\nhello\r\n".to_vec());
+        assert_eq!(chunk_frame(&[b'a'; 26])[..4], *b"1a\r\n");
     }
 
     #[test]
@@ -236,6 +795,67 @@ mod tests {
         assert_eq!(normalize_path(String::from("")), String::from("/"),);
     }
 
+    #[test]
+    fn test_parse_bind_addresses() {
+        let addresses = parse_bind_addresses("0.0.0.0, [::1],::").unwrap();
+        assert_eq!(addresses.len(), 3);
+        assert!(addresses[0].is_ipv4());
+        assert_eq!(addresses[1].to_string(), "::1");
+        assert!(addresses[2].is_unspecified());
+        assert!(parse_bind_addresses("localhost").is_err());
+        assert!(parse_bind_addresses(" , ").is_err());
+    }
+
+    #[test]
+    fn test_path_has_prefix() {
+        let prefixes = vec![String::from("portal"), String::from("health")];
+        assert!(path_has_prefix("portal", &prefixes));
+        assert!(path_has_prefix("portal/rest", &prefixes));
+        assert!(!path_has_prefix("portals", &prefixes));
+        assert!(!path_has_prefix("demo/portal", &prefixes));
+    }
+
+    #[test]
+    fn test_route_pattern() {
+        assert!(RoutePattern::parse("api/users").unwrap().is_none());
+        assert!(RoutePattern::parse("static/*rest/more").is_err());
+
+        let user = RoutePattern::parse("api/users/:id").unwrap().unwrap();
+        let params = user.matches("/api/Users/42").unwrap();
+        assert_eq!(params.get("id").unwrap(), "42");
+        assert!(user.matches("/api/users").is_none());
+        assert!(user.matches("/api/users/42/roles").is_none());
+        assert!(user.matches("/api/users/..").is_none());
+
+        let rest = RoutePattern::parse("static/*rest").unwrap().unwrap();
+        let params = rest.matches("/static//css/My Style.css").unwrap();
+        assert_eq!(params.get("rest").unwrap(), "css/My Style.css");
+        // the decoded path is not decoded again
+        let params = rest.matches("/static/a%61").unwrap();
+        assert_eq!(params.get("rest").unwrap(), "a%61");
+        assert_eq!(rest.matches("/static").unwrap().get("rest").unwrap(), "");
+
+        let mut patterns = vec![
+            RoutePattern::parse("*all").unwrap().unwrap(),
+            RoutePattern::parse("api/*rest").unwrap().unwrap(),
+            RoutePattern::parse("api/:name/list").unwrap().unwrap(),
+            RoutePattern::parse("api/users/:id").unwrap().unwrap(),
+        ];
+        patterns.sort_by(|a, b| a.precedence(b));
+        let order: Vec<&str> = patterns.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(
+            order,
+            vec!["api/users/:id", "api/:name/list", "api/*rest", "*all"]
+        );
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%20b%2Fc"), "a b/c");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%C3%A9"), "%zzé");
+    }
+
     #[test]
     fn test_password_hashing() {
         let password = String::from("p@ssw0rd");
@@ -1,6 +1,10 @@
 use crate::nino_constants::info;
-use async_std::net::TcpStream;
-use http_types::{Request, Response};
+use crate::web_multipart::MultipartPart;
+use crate::web_stream::WebConnection;
+use async_std::sync::Mutex;
+use http_types::{convert::Serialize, Body, Request, Response};
+use std::collections::HashMap;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct InitialSettings {
@@ -9,6 +13,10 @@ pub struct InitialSettings {
     pub thread_count: usize,
     pub db_pool_size: usize,
     pub js_thread_count: usize,
+    pub js_concurrent_tasks: u32,
+    pub js_task_timeout_ms: u32,
+    pub js_heap_limit: usize,
+    pub js_snapshot_modules: Vec<String>,
     pub debug_port: u16,
 }
 
@@ -18,6 +26,13 @@ impl InitialSettings {
         info!("thread_count: {}", self.thread_count);
         info!("db_pool_size: {}", self.db_pool_size);
         info!("js_thread_count: {}", self.js_thread_count);
+        info!("js_concurrent_tasks: {}", self.js_concurrent_tasks);
+        info!("js_task_timeout_ms: {}", self.js_task_timeout_ms);
+        info!("js_heap_limit: {}", self.js_heap_limit);
+        info!(
+            "js_snapshot_modules: {}",
+            self.js_snapshot_modules.join(",")
+        );
         info!("debug_port: {}", self.debug_port);
         // skiping db connection log for security reasons
     }
@@ -28,6 +43,45 @@ impl InitialSettings {
 pub enum JSTask {
     Servlet(ServletTask),
     Message(String),
+    Socket(SocketTask),
+}
+
+/// websocket event handled by the open, message or close export of the module
+#[derive(Clone)]
+pub struct SocketTask {
+    pub id: String,
+    pub event: SocketEvent,
+    pub js_module: String,
+    pub request_path: String,
+    pub user: String,
+    // path parameters captured by the route pattern
+    pub params: HashMap<String, String>,
+    // dropped with the task when the event is handled, the next frame is read after it
+    pub done: async_channel::Sender<()>,
+}
+
+#[derive(Clone)]
+pub enum SocketEvent {
+    Open,
+    Message(SocketData),
+    Close,
+}
+
+impl SocketEvent {
+    /// the name of the handling function
+    pub fn name(&self) -> &'static str {
+        match self {
+            SocketEvent::Open => "open",
+            SocketEvent::Message(_) => "message",
+            SocketEvent::Close => "close",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum SocketData {
+    Text(String),
+    Binary(Vec<u8>),
 }
 
 #[derive(Clone)]
@@ -37,9 +91,41 @@ pub struct ServletTask {
     pub js_module: Option<String>,
     pub request: Request,
     pub user: String,
-    pub body: String,
+    // path parameters captured by the route pattern
+    pub params: HashMap<String, String>,
+    pub body: RequestBody,
+    pub parts: Vec<MultipartPart>,
     pub response: Option<Response>,
-    pub stream: Box<TcpStream>,
+    pub stream: WebConnection,
+    // error served by an error page module
+    pub error: Option<ErrorInfo>,
+    // the response head is sent and the body is written in chunks
+    pub chunked: bool,
+    // execution time budget in ms. 0 uses the default, negative is unlimited
+    pub timeout_ms: i32,
+}
+
+/// the error of an error page - available in JS as request.error
+#[derive(Clone, Serialize)]
+pub struct ErrorInfo {
+    pub status: u16,
+    // identifies the logged trace of server errors
+    pub id: String,
+    pub message: String,
+}
+
+#[derive(Clone)]
+pub enum RequestBody {
+    // the whole body is read in memory
+    Buffered(Vec<u8>),
+    // body is read on demand from the connection
+    Streamed(Arc<Mutex<BodyReader>>),
+}
+
+pub struct BodyReader {
+    pub body: Body,
+    pub read: usize,
+    pub max_size: usize,
 }
 
 #[derive(Clone)]
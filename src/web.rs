@@ -3,23 +3,75 @@ use crate::nino_constants::{
     self, info, SETTINGS_NINO_LOGIN_PATH, SETTINGS_NINO_LOGIN_PATH_DEFAULT,
 };
 use crate::nino_functions;
-use crate::nino_structures::ServletTask;
-use crate::web_requests::RequestManager;
-use crate::web_responses::ResponseManager;
+use crate::nino_structures::{BodyReader, ErrorInfo, RequestBody, ServletTask};
+use crate::web_compression::{Compression, Encoding};
+use crate::web_cors::CorsManager;
+use crate::web_errors::ErrorPageManager;
+use crate::web_events;
+use crate::web_jwt::JwtManager;
+use crate::web_limits::RateLimiter;
+use crate::web_multipart::{multipart_boundary, MultipartError, MultipartParser, MultipartPart};
+use crate::web_requests::{allowed_methods, select_method, RequestInfo, RequestManager};
+use crate::web_responses::{ResponseInfo, ResponseManager};
+use crate::web_roles::RoleManager;
+use crate::web_sockets::{self, SocketInfo, SocketManager};
+use crate::web_stream::{RequestReader, WebConnection, WebStream};
+use crate::web_tls::TlsManager;
+use async_std::io::ReadExt;
 use async_std::net::{TcpListener, TcpStream};
+use async_std::sync::Mutex;
 use deno_core::anyhow::{self, Error};
 use http_types::headers::HeaderValues;
-use http_types::{Method, Request, Response, StatusCode, Url};
+use http_types::{Method, Request, Response, StatusCode};
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+const STATIC_METHODS: &str = "GET, HEAD, OPTIONS";
+const PRIVATE_CACHE_CONTROL: &str = "private, no-cache";
+// Retry-After of the overloaded server answers
+const RETRY_AFTER_S: u64 = 1;
+const MULTIPART_READ_CHUNK: usize = 64 * 1024;
+
+/// public listeners serve all requests except the admin paths when admin port is defined.
+/// admin listeners serve only the admin paths
+#[derive(Clone, Copy, PartialEq)]
+enum ListenerKind {
+    Public,
+    Admin,
+}
 
 /// A Web Server with dispatching requests to static and dynamic manager
+#[derive(Clone)]
 pub struct WebManager {
     port: u16,
+    bind_addresses: String,
+    admin_port: u16,
+    admin_paths: Vec<String>,
     request_timeout_ms: u32,
+    max_body_size: usize,
+    max_buffered_body_size: usize,
+    max_part_size: usize,
+    http_redirect_port: u16,
+    keep_alive_timeout_ms: u32,
+    keep_alive_max_requests: usize,
+    compression_min_size: usize,
+    cache_control: String,
+    events_ping_ms: u32,
+    task_backlog: usize,
+    // None - unlimited connections
+    connections: Option<Arc<Semaphore>>,
+    limiter: RateLimiter,
+    // all requests of an address, before routing
+    address_limiter: RateLimiter,
     settings: Arc<SettingsManager>,
     requests: Arc<RequestManager>,
     responses: Arc<ResponseManager>,
+    roles: Arc<RoleManager>,
+    cors: Arc<CorsManager>,
+    errors: ErrorPageManager,
+    sockets: SocketManager,
 }
 
 impl WebManager {
@@ -27,6 +79,8 @@ impl WebManager {
         settings: Arc<SettingsManager>,
         requests: Arc<RequestManager>,
         responses: Arc<ResponseManager>,
+        roles: Arc<RoleManager>,
+        cors: Arc<CorsManager>,
     ) -> WebManager {
         let port = settings
             .get_setting_i32(
@@ -34,55 +88,211 @@ impl WebManager {
                 nino_constants::SETTINGS_NINO_WEB_SERVER_PORT_DEFAULT,
             )
             .await as u16;
+        let bind_addresses = settings
+            .get_setting_str(
+                nino_constants::SETTINGS_NINO_WEB_BIND_ADDRESSES,
+                nino_constants::SETTINGS_NINO_WEB_BIND_ADDRESSES_DEFAULT,
+            )
+            .await;
+        let admin_port = settings
+            .get_setting_i32(
+                nino_constants::SETTINGS_NINO_WEB_ADMIN_PORT,
+                nino_constants::SETTINGS_NINO_WEB_ADMIN_PORT_DEFAULT,
+            )
+            .await as u16;
+        let admin_paths = settings
+            .get_setting_str(
+                nino_constants::SETTINGS_NINO_WEB_ADMIN_PATHS,
+                nino_constants::SETTINGS_NINO_WEB_ADMIN_PATHS_DEFAULT,
+            )
+            .await
+            .split(',')
+            .map(|path| nino_functions::normalize_path(path.trim().to_string()))
+            .filter(|path| path != "/")
+            .collect();
         let request_timeout_ms = settings
             .get_setting_i32(
                 nino_constants::SETTINGS_NINO_WEB_REQUEST_TIMEOUT,
                 nino_constants::SETTINGS_NINO_WEB_REQUEST_TIMEOUT_DEFAULT,
             )
             .await as u32;
+        let max_body_size = settings
+            .get_setting_usize(
+                nino_constants::SETTINGS_NINO_WEB_MAX_BODY_SIZE,
+                nino_constants::SETTINGS_NINO_WEB_MAX_BODY_SIZE_DEFAULT,
+            )
+            .await;
+        let max_buffered_body_size = settings
+            .get_setting_usize(
+                nino_constants::SETTINGS_NINO_WEB_MAX_BUFFERED_BODY_SIZE,
+                nino_constants::SETTINGS_NINO_WEB_MAX_BUFFERED_BODY_SIZE_DEFAULT,
+            )
+            .await;
+        let max_part_size = settings
+            .get_setting_usize(
+                nino_constants::SETTINGS_NINO_WEB_MAX_PART_SIZE,
+                nino_constants::SETTINGS_NINO_WEB_MAX_PART_SIZE_DEFAULT,
+            )
+            .await;
+        let http_redirect_port = settings
+            .get_setting_i32(
+                nino_constants::SETTINGS_NINO_WEB_HTTP_REDIRECT_PORT,
+                nino_constants::SETTINGS_NINO_WEB_HTTP_REDIRECT_PORT_DEFAULT,
+            )
+            .await as u16;
+        let keep_alive_timeout_ms = settings
+            .get_setting_i32(
+                nino_constants::SETTINGS_NINO_WEB_KEEP_ALIVE_TIMEOUT,
+                nino_constants::SETTINGS_NINO_WEB_KEEP_ALIVE_TIMEOUT_DEFAULT,
+            )
+            .await as u32;
+        let keep_alive_max_requests = settings
+            .get_setting_usize(
+                nino_constants::SETTINGS_NINO_WEB_KEEP_ALIVE_MAX_REQUESTS,
+                nino_constants::SETTINGS_NINO_WEB_KEEP_ALIVE_MAX_REQUESTS_DEFAULT,
+            )
+            .await;
+        let compression_min_size = settings
+            .get_setting_usize(
+                nino_constants::SETTINGS_NINO_WEB_COMPRESSION_MIN_SIZE,
+                nino_constants::SETTINGS_NINO_WEB_COMPRESSION_MIN_SIZE_DEFAULT,
+            )
+            .await;
+        let cache_control = settings
+            .get_setting_str(
+                nino_constants::SETTINGS_NINO_WEB_CACHE_CONTROL,
+                nino_constants::SETTINGS_NINO_WEB_CACHE_CONTROL_DEFAULT,
+            )
+            .await;
+        let events_ping_ms = settings
+            .get_setting_i32(
+                nino_constants::SETTINGS_NINO_WEB_EVENTS_PING_INTERVAL,
+                nino_constants::SETTINGS_NINO_WEB_EVENTS_PING_INTERVAL_DEFAULT,
+            )
+            .await
+            .max(0) as u32;
+        let max_connections = settings
+            .get_setting_usize(
+                nino_constants::SETTINGS_NINO_WEB_MAX_CONNECTIONS,
+                nino_constants::SETTINGS_NINO_WEB_MAX_CONNECTIONS_DEFAULT,
+            )
+            .await;
+        let connections = if max_connections > 0 {
+            Some(Arc::new(Semaphore::new(max_connections)))
+        } else {
+            None
+        };
+        let rate_limit = settings
+            .get_setting_i32(
+                nino_constants::SETTINGS_NINO_WEB_RATE_LIMIT,
+                nino_constants::SETTINGS_NINO_WEB_RATE_LIMIT_DEFAULT,
+            )
+            .await
+            .max(0) as u32;
+        let address_rate_limit = settings
+            .get_setting_i32(
+                nino_constants::SETTINGS_NINO_WEB_ADDRESS_RATE_LIMIT,
+                nino_constants::SETTINGS_NINO_WEB_ADDRESS_RATE_LIMIT_DEFAULT,
+            )
+            .await
+            .max(0) as u32;
+        let task_backlog = settings
+            .get_setting_usize(
+                nino_constants::SETTINGS_NINO_WEB_TASK_BACKLOG,
+                nino_constants::SETTINGS_NINO_WEB_TASK_BACKLOG_DEFAULT,
+            )
+            .await;
+        let errors = ErrorPageManager::new(settings.clone(), responses.clone());
+        let sockets = SocketManager::new(
+            responses.get_notifier(),
+            responses.get_web_task_sx(),
+            max_body_size,
+            task_backlog,
+        );
         WebManager {
             port,
+            bind_addresses,
+            admin_port,
+            admin_paths,
             request_timeout_ms,
+            max_body_size,
+            max_buffered_body_size,
+            max_part_size,
+            http_redirect_port,
+            keep_alive_timeout_ms,
+            keep_alive_max_requests,
+            compression_min_size,
+            cache_control,
+            events_ping_ms,
+            task_backlog,
+            connections,
+            limiter: RateLimiter::new(rate_limit),
+            address_limiter: RateLimiter::new(address_rate_limit),
             settings,
             requests,
             responses,
+            roles,
+            cors,
+            errors,
+            sockets,
         }
     }
 
     pub async fn start(&self) -> Result<(), Error> {
-        let listener = TcpListener::bind(("127.0.0.1", self.port)).await?;
-        println!("starting HTTP server at http://localhost:{}", self.port);
-        let bl = Box::new(listener);
-        Self::listening(
-            bl,
-            self.request_timeout_ms,
-            self.settings.clone(),
-            self.requests.clone(),
-            self.responses.clone(),
-        )
-        .await;
+        let addresses = nino_functions::parse_bind_addresses(&self.bind_addresses)?;
+        let tls = TlsManager::acceptor().is_some();
+        let scheme = if tls { "https" } else { "http" };
+        let mut listeners: Vec<(Box<TcpListener>, ListenerKind)> = Vec::new();
+        for ip in addresses {
+            let address = SocketAddr::new(ip, self.port);
+            listeners.push((
+                Box::new(TcpListener::bind(address).await?),
+                ListenerKind::Public,
+            ));
+            println!("starting server at {}://{}", scheme, address);
+            if self.admin_port > 0 {
+                let address = SocketAddr::new(ip, self.admin_port);
+                listeners.push((
+                    Box::new(TcpListener::bind(address).await?),
+                    ListenerKind::Admin,
+                ));
+                println!("starting admin server at {}://{}", scheme, address);
+            }
+            // bound even without TLS, since the certificate can be set at runtime
+            if self.http_redirect_port > 0 {
+                let address = SocketAddr::new(ip, self.http_redirect_port);
+                let redirect_listener = TcpListener::bind(address).await?;
+                println!("redirecting http://{} to port {}", address, self.port);
+                tokio::task::spawn(self.clone().redirecting(Box::new(redirect_listener)));
+            }
+        }
+        // serve the first listener in the current task
+        let (first, first_kind) = listeners.remove(0);
+        for (listener, kind) in listeners {
+            tokio::task::spawn(self.clone().listening(listener, kind));
+        }
+        self.clone().listening(first, first_kind).await;
     }
 
-    async fn listening(
-        listener: Box<TcpListener>,
-        request_timeout_ms: u32,
-        settings: Arc<SettingsManager>,
-        requests: Arc<RequestManager>,
-        responses: Arc<ResponseManager>,
-    ) -> ! {
+    async fn listening(self, listener: Box<TcpListener>, kind: ListenerKind) -> ! {
         // serving loop
         loop {
             let conn = listener.accept().await;
             match conn {
                 Ok((stream, _socket_addr)) => {
+                    // the permit is held until the connection is closed
+                    let permit = match &self.connections {
+                        None => None,
+                        Some(connections) => match connections.clone().try_acquire_owned() {
+                            Ok(permit) => Some(permit),
+                            Err(_) => {
+                                tokio::task::spawn(self.clone().reject_connection(stream));
+                                continue;
+                            }
+                        },
+                    };
                     // spawn new task
-                    tokio::task::spawn(Self::serve_request(
-                        request_timeout_ms,
-                        Box::new(stream),
-                        settings.clone(),
-                        requests.clone(),
-                        responses.clone(),
-                    ));
+                    tokio::task::spawn(self.clone().accept_connection(stream, kind, permit));
                 }
                 Err(error) => {
                     eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
@@ -91,99 +301,321 @@ impl WebManager {
         }
     }
 
-    async fn serve_request(
-        request_timeout_ms: u32,
-        stream: Box<TcpStream>,
-        settings: Arc<SettingsManager>,
-        requests: Arc<RequestManager>,
-        responses: Arc<ResponseManager>,
-    ) {
-        let from_addres = match stream.peer_addr() {
-            Ok(address) => address,
-            Err(error) => {
-                eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
-                return;
+    async fn redirecting(self, listener: Box<TcpListener>) -> ! {
+        // plain HTTP loop redirecting everything to the server port
+        loop {
+            match listener.accept().await {
+                Ok((stream, _socket_addr)) => {
+                    tokio::task::spawn(self.clone().redirect_to_https(stream));
+                }
+                Err(error) => {
+                    eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+                }
             }
-        };
+        }
+    }
 
-        // add request timeout - to avoid slow lorry attacks
+    async fn redirect_to_https(self, stream: TcpStream) {
+        let stream = Box::new(WebStream::Plain(stream));
+        let reader = RequestReader::new((*stream).clone());
         match tokio::time::timeout(
-            tokio::time::Duration::from_millis(request_timeout_ms as u64),
-            async_h1::server::decode(stream.clone()),
+            tokio::time::Duration::from_millis(self.request_timeout_ms as u64),
+            async_h1::server::decode(reader),
         )
         .await
         {
-            Err(error) => info!("OK {}:{}:{}", file!(), line!(), error),
+            Ok(Ok(Some((request, _)))) => {
+                // the TLS settings can change at runtime, the scheme is decided per request
+                let (scheme, default_port) = match TlsManager::acceptor() {
+                    Some(_) => ("https", 443),
+                    None => ("http", 80),
+                };
+                let mut url = request.url().clone();
+                let port = if self.port == default_port {
+                    None
+                } else {
+                    Some(self.port)
+                };
+                if url.set_scheme(scheme).is_ok() && url.set_port(port).is_ok() {
+                    Self::response_308_redirect(WebConnection::closing(stream), &url.into()).await;
+                    return;
+                }
+            }
+            Ok(Ok(None)) => {}
             Ok(Err(error)) => info!("ERROR {}:{}:{}", file!(), line!(), error),
-            Ok(Ok(request)) => {
-                match request {
-                    Some(request) => {
-                        let (request, _) = request;
-                        // queue task with request
-                        if let Err(error) = Self::dispatch_request(
-                            from_addres,
-                            request,
-                            stream.clone(),
-                            settings,
-                            requests,
-                            responses,
-                        )
-                        .await
-                        {
-                            // requestor has closed the stream
-                            info!("ERROR {}:{}:{}", file!(), line!(), error);
-                        }
-                        return;
+            Err(error) => info!("OK {}:{}:{}", file!(), line!(), error),
+        }
+        let _r = stream.shutdown(std::net::Shutdown::Both);
+    }
+
+    async fn accept_connection(
+        self,
+        stream: TcpStream,
+        kind: ListenerKind,
+        _permit: Option<OwnedSemaphorePermit>,
+    ) {
+        if let Some(stream) = self.accept_stream(stream).await {
+            self.serve_request(Box::new(stream), kind).await;
+        }
+    }
+
+    /// answers the connections over the limit with 503 without reading the request
+    async fn reject_connection(self, stream: TcpStream) {
+        if let Some(stream) = self.accept_stream(stream).await {
+            let stream = Box::new(stream);
+            Self::response_retry(
+                WebConnection::closing(stream.clone()),
+                StatusCode::ServiceUnavailable,
+                RETRY_AFTER_S,
+            )
+            .await;
+            let _r = stream.shutdown(std::net::Shutdown::Both);
+        }
+    }
+
+    async fn accept_stream(&self, stream: TcpStream) -> Option<WebStream> {
+        match TlsManager::acceptor() {
+            None => Some(WebStream::Plain(stream)),
+            Some(acceptor) => {
+                // the handshake is limited by the request timeout as well
+                match tokio::time::timeout(
+                    tokio::time::Duration::from_millis(self.request_timeout_ms as u64),
+                    acceptor.accept(stream),
+                )
+                .await
+                {
+                    Ok(Ok(stream)) => Some(WebStream::tls(stream)),
+                    Ok(Err(error)) => {
+                        info!("ERROR {}:{}:{}", file!(), line!(), error);
+                        None
                     }
-                    None => {
-                        // requestor has closed the stream
-                        // info!("ERROR {}:{}:{}", file!(), line!(), "should not happen");
+                    Err(error) => {
+                        info!("OK {}:{}:{}", file!(), line!(), error);
+                        None
                     }
                 }
             }
         }
-        //invalid/unrecognized request
+    }
+
+    async fn serve_request(self, stream: Box<WebStream>, kind: ListenerKind) {
+        let from_addres = match stream.peer_addr() {
+            Ok(address) => address,
+            Err(error) => {
+                eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+                return;
+            }
+        };
+
+        // serve the requests of the connection one after another
+        let reader = RequestReader::new((*stream).clone());
+        let mut served: usize = 0;
+        loop {
+            // add request timeout - to avoid slow lorry attacks
+            // and idle timeout for the next requests of keep alive connection
+            let timeout_ms = if served == 0 {
+                self.request_timeout_ms
+            } else {
+                self.keep_alive_timeout_ms
+            };
+            let request = match tokio::time::timeout(
+                tokio::time::Duration::from_millis(timeout_ms as u64),
+                async_h1::server::decode(reader.clone()),
+            )
+            .await
+            {
+                Err(error) => {
+                    info!("OK {}:{}:{}", file!(), line!(), error);
+                    break;
+                }
+                Ok(Err(error)) => {
+                    info!("ERROR {}:{}:{}", file!(), line!(), error);
+                    break;
+                }
+                // requestor has closed the stream
+                Ok(Ok(None)) => break,
+                Ok(Ok(Some((request, _)))) => request,
+            };
+            served += 1;
+
+            let keep_alive = self.keep_alive_timeout_ms > 0
+                && served < self.keep_alive_max_requests
+                && !request
+                    .header("Connection")
+                    .is_some_and(|values| values.last().as_str().eq_ignore_ascii_case("close"));
+            let (release_sx, release_rx) = async_channel::bounded::<bool>(1);
+            let head = request.method() == Method::Head;
+            let mut connection = WebConnection::new(stream.clone(), keep_alive, head, release_sx);
+            connection.compression = self.negotiate_compression(&request);
+
+            // queue task with request
+            if let Err(error) = self
+                .dispatch_request(from_addres, request, connection, kind)
+                .await
+            {
+                // requestor has closed the stream
+                info!("ERROR {}:{}:{}", file!(), line!(), error);
+                break;
+            }
+
+            // wait for the response - all connection clones dropped without response closes it
+            match release_rx.recv().await {
+                Ok(true) if reader.next_request() => {}
+                _ => break,
+            }
+        }
         //close connection
         let _r = stream.shutdown(std::net::Shutdown::Both);
     }
 
     async fn dispatch_request(
+        &self,
         from_address: SocketAddr,
         mut request: Request,
-        stream: Box<TcpStream>,
-        settings: Arc<SettingsManager>,
-        requests: Arc<RequestManager>,
-        responses: Arc<ResponseManager>,
+        mut stream: WebConnection,
+        kind: ListenerKind,
     ) -> Result<(), Error> {
         let method = request.method();
         let url = request.url().clone();
-        let request_path = nino_functions::normalize_path(url.path().to_string());
+        // decoded once - the routing, admin paths, CORS and rate limits see the same path
+        let url_path = nino_functions::percent_decode(url.path());
+        let request_path = nino_functions::normalize_path(url_path.clone());
         let mut user = String::new();
 
         println!("REQUEST: {} {} {}", method, from_address, url);
 
-        match requests.get_request(&request_path).await? {
+        // not found, redirected and denied requests are limited as well
+        if let Err(retry_after) = self.address_limiter.check(from_address.ip().to_string(), 0) {
+            Self::response_retry(stream, StatusCode::TooManyRequests, retry_after).await;
+            return Ok(());
+        }
+
+        if !self.is_served_by(kind, &request_path) {
+            // admin paths are visible only on the admin port
+            let message = format!("url not found: {} ", url);
+            self.response_error(stream, request, user, StatusCode::NotFound, message, &[])
+                .await;
+            return Ok(());
+        }
+
+        if let Some(origin) = request
+            .header("Origin")
+            .map(|v| v.last().as_str().to_string())
+        {
+            if let Some(policy) = self.cors.get_policy(&request_path).await? {
+                let preflight_method = request
+                    .header("Access-Control-Request-Method")
+                    .map(|v| v.last().as_str().to_string());
+                match preflight_method {
+                    // preflights are answered before routing and authorization
+                    Some(preflight_method) if method == Method::Options => {
+                        let request_headers = request
+                            .header("Access-Control-Request-Headers")
+                            .map(|v| v.iter().map(|h| h.as_str()).collect::<Vec<_>>().join(","))
+                            .unwrap_or_default();
+                        match policy.preflight_headers(&origin, &preflight_method, &request_headers)
+                        {
+                            Some(headers) => Self::response_preflight(stream, &headers).await,
+                            None => {
+                                let message = format!("CORS request not allowed: {}", origin);
+                                let status = StatusCode::Forbidden;
+                                self.response_error(stream, request, user, status, message, &[])
+                                    .await;
+                            }
+                        }
+                        return Ok(());
+                    }
+                    _ => stream.headers = policy.response_headers(&origin),
+                }
+            }
+        }
+
+        match self.requests.get_request(&request_path, &url_path).await? {
             None => {
-                Self::response_404(stream, &url).await;
+                let message = format!("url not found: {} ", url);
+                self.response_error(stream, request, user, StatusCode::NotFound, message, &[])
+                    .await;
                 Ok(())
             }
-            Some(request_info) => {
+            Some((requests, _params))
+                if requests
+                    .iter()
+                    .any(|info| self.is_admin_response(kind, &info.name)) =>
+            {
+                // the admin responses are not served on the public port through other paths
+                let message = format!("url not found: {} ", url);
+                self.response_error(stream, request, user, StatusCode::NotFound, message, &[])
+                    .await;
+                Ok(())
+            }
+            Some((requests, params)) => {
+                let Some(request_info) = select_method(&requests, method.as_ref()) else {
+                    // the methods of protected paths are disclosed only to the authorized users
+                    let protected = requests
+                        .iter()
+                        .any(|info| info.authorize || !info.required_roles.is_empty());
+                    if protected && !Self::check_authorization(&request, &mut user) {
+                        self.response_login_redirect(stream, &url).await;
+                        return Ok(());
+                    }
+                    let mut permitted = !protected;
+                    for info in requests.iter() {
+                        if !permitted {
+                            permitted =
+                                self.roles.has_any_role(&user, &info.required_roles).await?;
+                        }
+                    }
+                    if !permitted {
+                        let message = format!("access denied: {}", url.path());
+                        let status = StatusCode::Forbidden;
+                        self.response_error(stream, request, user, status, message, &[])
+                            .await;
+                        return Ok(());
+                    }
+                    // the path is defined, but not for this method
+                    let allow = allowed_methods(&requests);
+                    if method == Method::Options {
+                        Self::response_allow(stream, StatusCode::NoContent, &allow).await;
+                    } else {
+                        let message = format!("method not allowed: {} {}", method, url.path());
+                        let status = StatusCode::MethodNotAllowed;
+                        let headers = [("Allow", allow.as_str())];
+                        self.response_error(stream, request, user, status, message, &headers)
+                            .await;
+                    }
+                    return Ok(());
+                };
                 if request_info.redirect {
                     Self::response_307_redirect(stream, &request_info.name).await;
                     Ok(())
-                } else if request_info.authorize && !Self::check_authorization(&request, &mut user)
+                } else if (request_info.authorize || !request_info.required_roles.is_empty())
+                    && !Self::check_authorization(&request, &mut user)
+                {
+                    self.response_login_redirect(stream, &url).await;
+                    Ok(())
+                } else if !self
+                    .roles
+                    .has_any_role(&user, &request_info.required_roles)
+                    .await?
                 {
-                    // redirect to login
-                    // TODO: add this as parameter
-                    let mut redirect_url = url.clone();
-                    let login_path = settings
-                        .get_setting_str(SETTINGS_NINO_LOGIN_PATH, SETTINGS_NINO_LOGIN_PATH_DEFAULT)
+                    // authenticated, but without any of the required roles
+                    let message = format!("access denied: {}", url.path());
+                    self.response_error(stream, request, user, StatusCode::Forbidden, message, &[])
+                        .await;
+                    Ok(())
+                } else if let Err(retry_after) =
+                    self.check_rate_limit(&from_address, &user, &request_info)
+                {
+                    Self::response_retry(stream, StatusCode::TooManyRequests, retry_after).await;
+                    Ok(())
+                } else if request_info.events {
+                    let prefixes = web_events::event_prefixes(&request_info.name, &user, &params);
+                    let subscriber = self.responses.get_notifier().get_subscriber();
+                    web_events::serve_events(stream, subscriber, prefixes, self.events_ping_ms)
                         .await;
-                    redirect_url.set_path(&login_path);
-                    Self::response_307_redirect(stream, &redirect_url.into()).await;
                     Ok(())
                 } else {
-                    let response_info = responses.get_response(&request_info.name).await?;
+                    let response_info = self.responses.get_response(&request_info.name).await?;
                     match response_info {
                         None => {
                             // no response defined
@@ -196,16 +628,82 @@ impl WebManager {
                                     request_info.name
                                 )
                             );
-                            Self::response_404(stream, &url).await;
+                            let message = format!("url not found: {} ", url);
+                            let status = StatusCode::NotFound;
+                            self.response_error(stream, request, user, status, message, &[])
+                                .await;
+                            Ok(())
+                        }
+                        Some(response_info) if request_info.websocket && response_info.execute => {
+                            let Some(accept) = web_sockets::accept_key(&request) else {
+                                let message = format!("websocket upgrade required: {}", url.path());
+                                let status = StatusCode::UpgradeRequired;
+                                let headers =
+                                    [("Upgrade", "websocket"), ("Sec-WebSocket-Version", "13")];
+                                self.response_error(
+                                    stream, request, user, status, message, &headers,
+                                )
+                                .await;
+                                return Ok(());
+                            };
+                            if !self
+                                .is_socket_origin_allowed(&request, &request_path)
+                                .await?
+                            {
+                                let message =
+                                    format!("websocket origin not allowed: {}", url.path());
+                                let status = StatusCode::Forbidden;
+                                self.response_error(stream, request, user, status, message, &[])
+                                    .await;
+                                return Ok(());
+                            }
+                            let socket = SocketInfo {
+                                js_module: request_info.name,
+                                request_path,
+                                user,
+                                params,
+                            };
+                            self.sockets.serve(stream, accept, socket).await;
                             Ok(())
                         }
                         Some(response_info) => {
                             if response_info.execute {
+                                if self.task_backlog > 0
+                                    && self.responses.task_backlog() >= self.task_backlog
+                                {
+                                    // the javascript threads are behind
+                                    let status = StatusCode::ServiceUnavailable;
+                                    Self::response_retry(stream, status, RETRY_AFTER_S).await;
+                                    return Ok(());
+                                }
                                 // execute the JS
-                                let body = request
-                                    .body_string()
-                                    .await
-                                    .map_err(|e| Error::msg(e.to_string()))?;
+                                let boundary = request
+                                    .header("Content-Type")
+                                    .and_then(|values| multipart_boundary(values.last().as_str()));
+                                let read = match &boundary {
+                                    Some(boundary) => {
+                                        self.read_multipart(&mut request, boundary).await?
+                                    }
+                                    None => match self.read_body(&mut request).await? {
+                                        Some(body) => Ok((body, Vec::new())),
+                                        None => Err(MultipartError::BodyTooLarge),
+                                    },
+                                };
+                                let (body, parts) = match read {
+                                    Ok(read) => read,
+                                    Err(error) => {
+                                        let status = match error {
+                                            MultipartError::Malformed(_) => StatusCode::BadRequest,
+                                            MultipartError::PartTooLarge(_)
+                                            | MultipartError::BodyTooLarge => {
+                                                StatusCode::PayloadTooLarge
+                                            }
+                                        };
+                                        Self::response_text(stream, status, error.to_string())
+                                            .await;
+                                        return Ok(());
+                                    }
+                                };
                                 let method = method.to_string();
                                 let servlet_task = ServletTask {
                                     method,
@@ -213,34 +711,63 @@ impl WebManager {
                                     request,
                                     stream: stream.clone(),
                                     user,
+                                    params,
                                     body,
+                                    parts,
                                     js_module: None,
                                     response: None,
+                                    error: None,
+                                    chunked: false,
+                                    timeout_ms: 0,
                                 };
-                                responses
+                                self.responses
                                     .serve_dynamic(servlet_task, &request_info, &response_info)
                                     .await
-                                //ok - stream should be served and closed
+                                //ok - stream should be served and released
                             } else {
                                 // return static as response
-                                let method = request.method();
-                                if Method::Get != method {
-                                    // handle only GET static requests
-                                    return Err(Error::msg(
-                                        "static requests handles only GET requests",
-                                    ));
+                                match method {
+                                    Method::Get | Method::Head => {}
+                                    Method::Options => {
+                                        Self::response_allow(
+                                            stream,
+                                            StatusCode::NoContent,
+                                            STATIC_METHODS,
+                                        )
+                                        .await;
+                                        return Ok(());
+                                    }
+                                    _ => {
+                                        // static responses serve only GET and HEAD
+                                        let message = format!(
+                                            "method not allowed: {} {}",
+                                            method,
+                                            url.path()
+                                        );
+                                        self.response_error(
+                                            stream,
+                                            request,
+                                            user,
+                                            StatusCode::MethodNotAllowed,
+                                            message,
+                                            &[("Allow", STATIC_METHODS)],
+                                        )
+                                        .await;
+                                        return Ok(());
+                                    }
                                 }
-                                let web_client_caching =
-                                    !request_info.authorize && !response_info.execute;
-                                responses
+                                let cache_control =
+                                    self.cache_control(&request_info, &response_info);
+                                self.responses
                                     .serve_static(
                                         request_info,
                                         response_info,
+                                        &request,
                                         stream.clone(),
-                                        web_client_caching,
+                                        &cache_control,
                                     )
                                     .await
-                                //ok - stream should be served and closed
+                                //ok - stream should be served and released
                             }
                         }
                     }
@@ -249,6 +776,145 @@ impl WebManager {
         }
     }
 
+    /// the response cache policy, otherwise the default for public responses.
+    /// responses for authorized users are not cached in shared caches
+    fn cache_control(&self, request_info: &RequestInfo, response_info: &ResponseInfo) -> String {
+        if !response_info.cache_control.is_empty() {
+            response_info.cache_control.clone()
+        } else if request_info.authorize || !request_info.required_roles.is_empty() {
+            PRIVATE_CACHE_CONTROL.to_string()
+        } else {
+            self.cache_control.clone()
+        }
+    }
+
+    fn negotiate_compression(&self, request: &Request) -> Option<Compression> {
+        if self.compression_min_size == 0 {
+            return None;
+        }
+        let accept_encoding = request.header("Accept-Encoding")?;
+        let accept_encoding: Vec<&str> = accept_encoding.iter().map(|v| v.as_str()).collect();
+        Encoding::negotiate(&accept_encoding.join(",")).map(|encoding| Compression {
+            encoding,
+            min_size: self.compression_min_size,
+        })
+    }
+
+    /// the requests are limited per authenticated user, otherwise per address
+    fn check_rate_limit(
+        &self,
+        from_address: &SocketAddr,
+        user: &str,
+        request_info: &RequestInfo,
+    ) -> Result<(), u64> {
+        let client = if user.is_empty() {
+            from_address.ip().to_string()
+        } else {
+            format!("user:{}", user)
+        };
+        let key = format!("{} {} {}", client, request_info.method, request_info.name);
+        self.limiter.check(key, request_info.rate_limit)
+    }
+
+    fn is_served_by(&self, kind: ListenerKind, request_path: &str) -> bool {
+        if self.admin_port == 0 {
+            return true;
+        }
+        let admin_path = nino_functions::path_has_prefix(request_path, &self.admin_paths);
+        match kind {
+            ListenerKind::Public => !admin_path,
+            ListenerKind::Admin => admin_path,
+        }
+    }
+
+    /// browsers send the cookies with cross-site upgrades without CORS checks, so the
+    /// upgrades are accepted from the same origin or an origin listed in the CORS rule.
+    /// clients without Origin are not browsers
+    async fn is_socket_origin_allowed(
+        &self,
+        request: &Request,
+        request_path: &str,
+    ) -> Result<bool, Error> {
+        let Some(origin) = request
+            .header("Origin")
+            .map(|v| v.last().as_str().to_string())
+        else {
+            return Ok(true);
+        };
+        if web_sockets::is_same_origin(request, &origin) {
+            return Ok(true);
+        }
+        let policy = self.cors.get_policy(request_path).await?;
+        Ok(policy.is_some_and(|policy| policy.lists_origin(&origin)))
+    }
+
+    /// true for the responses of the admin paths on the public port, ex: / mapped to portal/main
+    fn is_admin_response(&self, kind: ListenerKind, response_name: &str) -> bool {
+        self.admin_port > 0
+            && kind == ListenerKind::Public
+            && nino_functions::path_has_prefix(response_name, &self.admin_paths)
+    }
+
+    /// small bodies are read in memory, while larger or with unknown length are
+    /// left for streaming reads from the JS handler.
+    /// returns None when the body length exceeds the maximum body size
+    async fn read_body(&self, request: &mut Request) -> Result<Option<RequestBody>, Error> {
+        match request.len() {
+            Some(length) if length > self.max_body_size => Ok(None),
+            Some(length) if length <= self.max_buffered_body_size => {
+                let body = request
+                    .body_bytes()
+                    .await
+                    .map_err(|e| Error::msg(e.to_string()))?;
+                Ok(Some(RequestBody::Buffered(body)))
+            }
+            _ => {
+                let reader = BodyReader {
+                    body: request.take_body(),
+                    read: 0,
+                    max_size: self.max_body_size,
+                };
+                Ok(Some(RequestBody::Streamed(Arc::new(Mutex::new(reader)))))
+            }
+        }
+    }
+
+    /// multipart bodies are always read in memory to be parsed into parts.
+    /// the parts are parsed while the body is read, so the reading stops
+    /// at the first part over the maximum part size
+    async fn read_multipart(
+        &self,
+        request: &mut Request,
+        boundary: &str,
+    ) -> Result<Result<(RequestBody, Vec<MultipartPart>), MultipartError>, Error> {
+        if request
+            .len()
+            .is_some_and(|length| length > self.max_body_size)
+        {
+            return Ok(Err(MultipartError::BodyTooLarge));
+        }
+        let mut parser = MultipartParser::new(boundary, self.max_part_size);
+        let mut reader = request.take_body();
+        let mut body: Vec<u8> = Vec::new();
+        let mut chunk = vec![0u8; MULTIPART_READ_CHUNK];
+        loop {
+            let length = reader.read(&mut chunk).await?;
+            if length == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..length]);
+            if body.len() > self.max_body_size {
+                return Ok(Err(MultipartError::BodyTooLarge));
+            }
+            if let Err(error) = parser.parse(&body) {
+                return Ok(Err(error));
+            }
+        }
+        Ok(parser
+            .finish(&body)
+            .map(|parts| (RequestBody::Buffered(body), parts)))
+    }
+
     fn check_cookie(cookies: &HeaderValues, current_user: &mut String) -> bool {
         // TODO: add config for this
         let cookie_prefix = "nino=";
@@ -297,24 +963,21 @@ impl WebManager {
 
     fn jwt_to_user(jwt: &str, current_user: &mut String) -> bool {
         // check if jwt is valid
-        // TODO: add config for this
-        match nino_functions::jwt_to_map(nino_constants::PROGRAM_NAME, jwt) {
+        match JwtManager::verify(jwt) {
             Err(error) => {
                 eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
                 false
             }
-            Ok(map) => {
+            Ok(username) => {
                 // jwt is valid
-                if let Some(username) = map.get(nino_constants::JWT_USER) {
-                    current_user.clear();
-                    current_user.push_str(username);
-                }
+                current_user.clear();
+                current_user.push_str(&username);
                 true
             }
         }
     }
 
-    async fn response_307_redirect(stream: Box<TcpStream>, url: &String) {
+    async fn response_307_redirect(stream: WebConnection, url: &String) {
         // TODO: introduce 404 handler
         let mut response = Response::new(StatusCode::TemporaryRedirect);
         response.append_header("Location", url);
@@ -322,17 +985,95 @@ impl WebManager {
             eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
         }
     }
-    async fn response_404(stream: Box<TcpStream>, url: &Url) {
-        // TODO: introduce 404 handler
-        let mut response = Response::new(StatusCode::NotFound);
-        let content = format!("url not found: {} ", url);
+    async fn response_308_redirect(stream: WebConnection, url: &String) {
+        let mut response = Response::new(StatusCode::PermanentRedirect);
+        response.append_header("Location", url);
+        if let Err(error) = nino_functions::send_response_to_stream(stream, &mut response).await {
+            eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+        }
+    }
+    async fn response_login_redirect(&self, stream: WebConnection, url: &http_types::Url) {
+        // TODO: add this as parameter
+        let mut redirect_url = url.clone();
+        let login_path = self
+            .settings
+            .get_setting_str(SETTINGS_NINO_LOGIN_PATH, SETTINGS_NINO_LOGIN_PATH_DEFAULT)
+            .await;
+        redirect_url.set_path(&login_path);
+        Self::response_307_redirect(stream, &redirect_url.into()).await;
+    }
+    async fn response_text(stream: WebConnection, status: StatusCode, content: String) {
+        let mut response = Response::new(status);
         response.set_body(http_types::Body::from_string(content));
         if let Err(error) = nino_functions::send_response_to_stream(stream, &mut response).await {
             eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
         }
     }
+    async fn response_allow(stream: WebConnection, status: StatusCode, allow: &str) {
+        let mut response = Response::new(status);
+        response.insert_header("Allow", allow);
+        if let Err(error) = nino_functions::send_response_to_stream(stream, &mut response).await {
+            eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+        }
+    }
+    async fn response_retry(stream: WebConnection, status: StatusCode, retry_after_s: u64) {
+        let mut response = Response::new(status);
+        response.insert_header("Retry-After", retry_after_s.to_string());
+        response.set_body(http_types::Body::from_string(
+            status.canonical_reason().to_string(),
+        ));
+        if let Err(error) = nino_functions::send_response_to_stream(stream, &mut response).await {
+            eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+        }
+    }
+    async fn response_preflight(stream: WebConnection, headers: &[(&str, String)]) {
+        let mut response = Response::new(StatusCode::NoContent);
+        for (name, value) in headers {
+            response.insert_header(*name, value.as_str());
+        }
+        if let Err(error) = nino_functions::send_response_to_stream(stream, &mut response).await {
+            eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+        }
+    }
+    /// serves the error page configured for the status.
+    /// executable error pages get the original request with the error in request.error
+    async fn response_error(
+        &self,
+        stream: WebConnection,
+        request: Request,
+        user: String,
+        status: StatusCode,
+        message: String,
+        headers: &[(&str, &str)],
+    ) {
+        let servlet_task = ServletTask {
+            method: request.method().to_string(),
+            request_path: nino_functions::normalize_path(nino_functions::percent_decode(
+                request.url().path(),
+            )),
+            request,
+            stream,
+            user,
+            params: HashMap::new(),
+            body: RequestBody::Buffered(Vec::new()),
+            parts: Vec::new(),
+            js_module: None,
+            response: None,
+            error: Some(ErrorInfo {
+                status: status.into(),
+                id: String::new(),
+                message,
+            }),
+            chunked: false,
+            timeout_ms: 0,
+        };
+        if let Err(error) = self.errors.send_error(servlet_task, true, headers).await {
+            // the dropped connection is closed
+            eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+        }
+    }
     /*
-    async fn response_500(stream: Box<TcpStream>, error: Error) {
+    async fn response_500(stream: WebConnection, error: Error) {
         let mut response = Response::new(StatusCode::InternalServerError);
         let content = format!("ERROR: {}", error);
         response.set_body(http_types::Body::from_string(content));
@@ -0,0 +1,164 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// Least recently used cache of contents limited by their total size in bytes.
+/// the least recently used contents are evicted when the budget is exceeded
+pub struct ContentCache<K> {
+    budget: usize,
+    used: usize,
+    tick: u64,
+    entries: HashMap<K, (Arc<Vec<u8>>, u64)>,
+    // usage tick to key - the first is the least recently used
+    usage: BTreeMap<u64, K>,
+    // changed on clear and remove_matching, so the contents read before are not cached
+    generation: u64,
+}
+
+impl<K: Clone + Eq + Hash> ContentCache<K> {
+    pub fn new(budget: usize) -> ContentCache<K> {
+        ContentCache {
+            budget,
+            used: 0,
+            tick: 0,
+            entries: HashMap::new(),
+            usage: BTreeMap::new(),
+            generation: 0,
+        }
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict();
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<Arc<Vec<u8>>> {
+        self.tick += 1;
+        let (content, used) = self.entries.get_mut(key)?;
+        self.usage.remove(used);
+        *used = self.tick;
+        self.usage.insert(self.tick, key.clone());
+        Some(content.clone())
+    }
+
+    /// contents bigger than the budget are not cached
+    pub fn insert(&mut self, key: K, content: Arc<Vec<u8>>) {
+        self.remove(&key);
+        if content.len() > self.budget {
+            return;
+        }
+        self.tick += 1;
+        self.used += content.len();
+        self.usage.insert(self.tick, key.clone());
+        self.entries.insert(key, (content, self.tick));
+        self.evict();
+    }
+
+    /// the generation to pass to insert_unchanged
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// inserts a content read at the generation, unless the cache is cleared since then
+    pub fn insert_unchanged(&mut self, key: K, content: Arc<Vec<u8>>, generation: u64) {
+        if generation == self.generation {
+            self.insert(key, content);
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        if let Some((content, used)) = self.entries.remove(key) {
+            self.usage.remove(&used);
+            self.used -= content.len();
+        }
+    }
+
+    /// removes the contents of the matching keys. the contents read before are not cached
+    pub fn remove_matching(&mut self, matches: impl Fn(&K) -> bool) {
+        self.generation += 1;
+        let keys: Vec<K> = self
+            .entries
+            .keys()
+            .filter(|key| matches(key))
+            .cloned()
+            .collect();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.generation += 1;
+        self.entries.clear();
+        self.usage.clear();
+        self.used = 0;
+    }
+
+    fn evict(&mut self) {
+        while self.used > self.budget {
+            let Some((_, key)) = self.usage.pop_first() else {
+                break;
+            };
+            if let Some((content, _)) = self.entries.remove(&key) {
+                self.used -= content.len();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web_cache::ContentCache;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_content_cache() {
+        let mut cache: ContentCache<&str> = ContentCache::new(10);
+        cache.insert("a", Arc::new(vec![0; 4]));
+        cache.insert("b", Arc::new(vec![0; 4]));
+        // a is used more recently than b
+        assert!(cache.get(&"a").is_some());
+        cache.insert("c", Arc::new(vec![0; 4]));
+        assert!(cache.get(&"b").is_none());
+        assert!(cache.get(&"a").is_some());
+        assert!(cache.get(&"c").is_some());
+
+        // too big content is not cached
+        cache.insert("d", Arc::new(vec![0; 11]));
+        assert!(cache.get(&"d").is_none());
+
+        // replacing updates the used size
+        cache.insert("a", Arc::new(vec![0; 7]));
+        assert!(cache.get(&"a").is_some());
+        assert!(cache.get(&"c").is_none());
+
+        cache.set_budget(5);
+        assert!(cache.get(&"a").is_none());
+        let generation = cache.generation();
+        cache.clear();
+        cache.insert("e", Arc::new(vec![0; 5]));
+        assert_eq!(cache.get(&"e").unwrap().len(), 5);
+
+        // contents read before the clear are stale
+        cache.insert_unchanged("f", Arc::new(vec![0; 1]), generation);
+        assert!(cache.get(&"f").is_none());
+        cache.insert_unchanged("f", Arc::new(vec![0; 1]), cache.generation());
+        assert!(cache.get(&"f").is_some());
+
+        // only the matching contents are removed
+        let mut cache: ContentCache<(&str, u8)> = ContentCache::new(10);
+        cache.insert(("a", 0), Arc::new(vec![0; 2]));
+        cache.insert(("a", 1), Arc::new(vec![0; 2]));
+        cache.insert(("b", 0), Arc::new(vec![0; 2]));
+        let generation = cache.generation();
+        cache.remove_matching(|(name, _)| *name == "a");
+        assert!(cache.get(&("a", 0)).is_none());
+        assert!(cache.get(&("a", 1)).is_none());
+        assert!(cache.get(&("b", 0)).is_some());
+        cache.insert_unchanged(("a", 0), Arc::new(vec![0; 2]), generation);
+        assert!(cache.get(&("a", 0)).is_none());
+        // the removed contents are not counted as used
+        cache.insert(("c", 0), Arc::new(vec![0; 8]));
+        assert!(cache.get(&("b", 0)).is_some());
+    }
+}
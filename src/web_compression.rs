@@ -0,0 +1,201 @@
+use deno_core::anyhow::Error;
+use flate2::write::GzEncoder;
+use http_types::{Response, StatusCode};
+use std::io::{self, Write};
+use std::sync::Arc;
+
+const CONTENT_ENCODING: &str = "Content-Encoding";
+const CONTENT_LENGTH: &str = "Content-Length";
+const VARY: &str = "Vary";
+const ACCEPT_ENCODING: &str = "Accept-Encoding";
+
+// brotli window size
+const BROTLI_LGWIN: u32 = 22;
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// supported content encodings
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+/// The negotiated compression of a request response
+#[derive(Clone, Copy)]
+pub struct Compression {
+    pub encoding: Encoding,
+    // smaller responses are sent uncompressed
+    pub min_size: usize,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// the preferred encoding of the Accept-Encoding header.
+    /// brotli is preferred over gzip with the same quality
+    pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+        let mut brotli: Option<f32> = None;
+        let mut gzip: Option<f32> = None;
+        let mut any: Option<f32> = None;
+        for item in accept_encoding.split(',') {
+            let mut parts = item.split(';');
+            let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+            let quality = parts
+                .find_map(|part| part.trim().strip_prefix("q="))
+                .and_then(|quality| quality.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            match name.as_str() {
+                "br" => brotli = Some(quality),
+                "gzip" | "x-gzip" => gzip = Some(quality),
+                "*" => any = Some(quality),
+                _ => {}
+            }
+        }
+        let brotli = brotli.or(any).unwrap_or(0.0);
+        let gzip = gzip.or(any).unwrap_or(0.0);
+        if brotli > 0.0 && brotli >= gzip {
+            Some(Encoding::Brotli)
+        } else if gzip > 0.0 {
+            Some(Encoding::Gzip)
+        } else {
+            None
+        }
+    }
+
+    /// compresses on a blocking thread, not to hold the async workers
+    pub async fn compress_blocking(
+        self,
+        bytes: Arc<Vec<u8>>,
+        best: bool,
+    ) -> Result<Vec<u8>, Error> {
+        let compressed = tokio::task::spawn_blocking(move || self.compress(&bytes, best)).await?;
+        Ok(compressed?)
+    }
+
+    /// best compresses slowly for cached static content, otherwise fast for on the fly compression
+    pub fn compress(&self, bytes: &[u8], best: bool) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let quality = if best { 11 } else { 4 };
+                let mut writer = brotli::CompressorWriter::new(
+                    Vec::with_capacity(bytes.len() / 2),
+                    BROTLI_BUFFER_SIZE,
+                    quality,
+                    BROTLI_LGWIN,
+                );
+                writer.write_all(bytes)?;
+                Ok(writer.into_inner())
+            }
+            Encoding::Gzip => {
+                let level = if best {
+                    flate2::Compression::best()
+                } else {
+                    flate2::Compression::default()
+                };
+                let mut encoder = GzEncoder::new(Vec::with_capacity(bytes.len() / 2), level);
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// text like content types. already compressed images, fonts and archives are not compressed again
+pub fn is_compressible(mime_essence: &str) -> bool {
+    let mime = mime_essence.to_ascii_lowercase();
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime.as_str(),
+            "application/javascript"
+                | "application/x-javascript"
+                | "application/json"
+                | "application/xml"
+                | "application/wasm"
+                | "image/svg+xml"
+        )
+}
+
+/// responses of compressible content type vary by the accepted encoding
+pub fn set_vary(response: &mut Response) {
+    response.insert_header(VARY, ACCEPT_ENCODING);
+}
+
+pub fn set_content_encoding(response: &mut Response, encoding: Encoding) {
+    response.insert_header(CONTENT_ENCODING, encoding.name());
+    set_vary(response);
+}
+
+/// compresses the body on the fly when it is big enough and of compressible content type.
+/// responses with own encoding or length are sent as they are
+pub async fn compress_response(
+    compression: &Compression,
+    response: &mut Response,
+    body: Vec<u8>,
+) -> Vec<u8> {
+    let compressible = response
+        .content_type()
+        .is_some_and(|mime| is_compressible(mime.essence()));
+    if !compressible
+        || body.len() < compression.min_size
+        || response.header(CONTENT_ENCODING).is_some()
+        || response.header(CONTENT_LENGTH).is_some()
+        || matches!(
+            response.status(),
+            StatusCode::NoContent | StatusCode::NotModified | StatusCode::PartialContent
+        )
+    {
+        return body;
+    }
+    let body = Arc::new(body);
+    match compression
+        .encoding
+        .compress_blocking(body.clone(), false)
+        .await
+    {
+        Ok(compressed) => {
+            set_content_encoding(response, compression.encoding);
+            compressed
+        }
+        Err(error) => {
+            eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+            Arc::try_unwrap(body).unwrap_or_else(|body| body.to_vec())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web_compression::{is_compressible, Encoding};
+
+    #[test]
+    fn test_negotiate_encoding() {
+        assert_eq!(
+            Encoding::negotiate("gzip, deflate, br"),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(Encoding::negotiate("gzip, deflate"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::negotiate("br;q=0.5, gzip"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::negotiate("br;q=0, gzip;q=0"), None);
+        assert_eq!(Encoding::negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(Encoding::negotiate("br;q=0, *;q=0.1"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::negotiate("identity"), None);
+        assert_eq!(Encoding::negotiate(""), None);
+    }
+
+    #[test]
+    fn test_is_compressible() {
+        assert!(is_compressible("text/html"));
+        assert!(is_compressible("application/javascript"));
+        assert!(is_compressible("application/ld+json"));
+        assert!(is_compressible("image/svg+xml"));
+        assert!(!is_compressible("image/png"));
+        assert!(!is_compressible("application/zip"));
+    }
+}
@@ -0,0 +1,307 @@
+use deno_core::anyhow::Error;
+
+use crate::{db::DBManager, db_notification, nino_constants, nino_functions, nino_structures};
+use std::sync::{Arc, OnceLock, RwLock};
+
+// request path of the rule used for the paths without own rule
+const GLOBAL_PATH: &str = "*";
+const ANY: &str = "*";
+
+/// CORS rule of a request path and the paths under it
+#[derive(Clone, Debug, PartialEq)]
+pub struct CorsPolicy {
+    pub origins: Vec<String>,
+    pub methods: Vec<String>,
+    pub headers: Vec<String>,
+    pub expose_headers: String,
+    pub credentials: bool,
+    pub max_age: i32,
+}
+
+impl CorsPolicy {
+    /// the Access-Control-Allow-Origin for the origin, None when the origin is not allowed
+    pub fn allow_origin(&self, origin: &str) -> Option<String> {
+        if self.origins.iter().any(|allowed| allowed == ANY) {
+            Some(ANY.to_string())
+        } else if self
+            .origins
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(origin))
+        {
+            Some(origin.to_string())
+        } else {
+            None
+        }
+    }
+
+    /// credentials are allowed only for the listed origins, any site could use them with *
+    pub fn allow_credentials(&self) -> bool {
+        self.credentials && !self.origins.iter().any(|allowed| allowed == ANY)
+    }
+
+    /// true when the origin is listed explicitly, * does not list any origin
+    pub fn lists_origin(&self, origin: &str) -> bool {
+        self.origins
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(origin))
+    }
+
+    /// the headers of the answer to a preflight request, None when the request is not allowed
+    pub fn preflight_headers(
+        &self,
+        origin: &str,
+        method: &str,
+        request_headers: &str,
+    ) -> Option<Vec<(&'static str, String)>> {
+        let allow_origin = self.allow_origin(origin)?;
+        let method = method.trim();
+        if !self
+            .methods
+            .iter()
+            .any(|allowed| allowed == ANY || allowed.eq_ignore_ascii_case(method))
+        {
+            return None;
+        }
+        let requested: Vec<&str> = split_list(request_headers).collect();
+        let any_header = self.headers.iter().any(|allowed| allowed == ANY);
+        if !any_header
+            && !requested.iter().all(|header| {
+                self.headers
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(header))
+            })
+        {
+            return None;
+        }
+
+        let mut headers = vec![
+            ("Access-Control-Allow-Origin", allow_origin),
+            ("Access-Control-Allow-Methods", method.to_string()),
+        ];
+        if !requested.is_empty() {
+            // the requested headers are allowed, * is not a wildcard with credentials
+            headers.push(("Access-Control-Allow-Headers", requested.join(", ")));
+        }
+        if self.allow_credentials() {
+            headers.push(("Access-Control-Allow-Credentials", "true".to_string()));
+        }
+        if self.max_age > 0 {
+            headers.push(("Access-Control-Max-Age", self.max_age.to_string()));
+        }
+        headers.push(("Vary", "Origin".to_string()));
+        Some(headers)
+    }
+
+    /// the headers of the response to an actual request, empty when the origin is not allowed
+    pub fn response_headers(&self, origin: &str) -> Vec<(&'static str, String)> {
+        let Some(allow_origin) = self.allow_origin(origin) else {
+            return Vec::new();
+        };
+        let mut headers = vec![("Access-Control-Allow-Origin", allow_origin)];
+        if self.allow_credentials() {
+            headers.push(("Access-Control-Allow-Credentials", "true".to_string()));
+        }
+        if !self.expose_headers.is_empty() {
+            headers.push(("Access-Control-Expose-Headers", self.expose_headers.clone()));
+        }
+        headers.push(("Vary", "Origin".to_string()));
+        headers
+    }
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+}
+
+fn to_list(list: &str) -> Vec<String> {
+    split_list(list).map(|item| item.to_string()).collect()
+}
+
+/// the rule with the longest path containing the request path, otherwise the global rule.
+/// the rules are ordered by descending path length
+fn find_policy<'a>(
+    policies: &'a [(String, Arc<CorsPolicy>)],
+    request_path: &str,
+) -> Option<&'a Arc<CorsPolicy>> {
+    policies
+        .iter()
+        .find(|(path, _)| {
+            path != GLOBAL_PATH
+                && nino_functions::path_has_prefix(request_path, std::slice::from_ref(path))
+        })
+        .or_else(|| policies.iter().find(|(path, _)| path == GLOBAL_PATH))
+        .map(|(_, policy)| policy)
+}
+
+/// Resolves the CORS rules of the request paths.
+/// the rules are loaded once and reloaded on cors notification
+#[derive(Clone)]
+pub struct CorsManager {
+    db: Arc<DBManager>,
+}
+
+static CORS_CACHE: OnceLock<RwLock<Option<Arc<Vec<(String, Arc<CorsPolicy>)>>>>> = OnceLock::new();
+
+impl CorsManager {
+    pub fn new(
+        db: Arc<DBManager>,
+        db_subscribe: tokio::sync::broadcast::Receiver<nino_structures::NotificationMessage>,
+    ) -> CorsManager {
+        CORS_CACHE.get_or_init(|| RwLock::new(None));
+        let this = Self { db };
+        tokio::spawn(async move {
+            Self::invalidator(db_subscribe).await;
+        });
+        this
+    }
+
+    pub async fn invalidator(
+        mut db_subscribe: tokio::sync::broadcast::Receiver<nino_structures::NotificationMessage>,
+    ) {
+        loop {
+            match db_subscribe.recv().await {
+                Err(error) => {
+                    eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+                }
+                Ok(message) => {
+                    if message
+                        .text
+                        .starts_with(db_notification::NOTIFICATION_PREFIX_CORS)
+                    {
+                        *CORS_CACHE.get().unwrap().write().unwrap() = None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// the CORS rule of the request path, None when CORS is not configured for it
+    pub async fn get_policy(&self, request_path: &str) -> Result<Option<Arc<CorsPolicy>>, Error> {
+        let policies = self.get_policies().await?;
+        Ok(find_policy(&policies, request_path).cloned())
+    }
+
+    async fn get_policies(&self) -> Result<Arc<Vec<(String, Arc<CorsPolicy>)>>, Error> {
+        if let Some(policies) = CORS_CACHE.get().unwrap().read().unwrap().as_ref() {
+            return Ok(policies.clone());
+        }
+        let query = format!(
+            "SELECT request_path, allowed_origins, allowed_methods, allowed_headers, exposed_headers, credentials_flag, max_age FROM {} ORDER BY LENGTH(request_path) DESC",
+            nino_constants::CORS_TABLE
+        );
+        let rows = self.db.query(&query, &[]).await?;
+        let policies: Arc<Vec<(String, Arc<CorsPolicy>)>> = Arc::new(
+            rows.iter()
+                .map(|row| {
+                    let path: String = row.get(0);
+                    let origins: String = row.get(1);
+                    let methods: Option<String> = row.get(2);
+                    let headers: Option<String> = row.get(3);
+                    let expose_headers: Option<String> = row.get(4);
+                    let policy = CorsPolicy {
+                        origins: to_list(&origins),
+                        methods: to_list(&methods.unwrap_or_default()),
+                        headers: to_list(&headers.unwrap_or_default()),
+                        expose_headers: to_list(&expose_headers.unwrap_or_default()).join(", "),
+                        credentials: row.get::<_, Option<bool>>(5).unwrap_or(false),
+                        max_age: row.get::<_, Option<i32>>(6).unwrap_or(0),
+                    };
+                    if policy.credentials && !policy.allow_credentials() {
+                        let error = format!("CORS rule {}: credentials need listed origins", path);
+                        eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+                    }
+                    (nino_functions::normalize_path(path), Arc::new(policy))
+                })
+                .collect(),
+        );
+        *CORS_CACHE.get().unwrap().write().unwrap() = Some(policies.clone());
+        Ok(policies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web_cors::{find_policy, to_list, CorsPolicy};
+    use std::sync::Arc;
+
+    fn policy(origins: &str, credentials: bool) -> CorsPolicy {
+        CorsPolicy {
+            origins: to_list(origins),
+            methods: to_list("GET, POST"),
+            headers: to_list("Content-Type, Authorization"),
+            expose_headers: "ETag".to_string(),
+            credentials,
+            max_age: 600,
+        }
+    }
+
+    #[test]
+    fn test_cors_policy() {
+        let any = policy("*", false);
+        assert_eq!(any.allow_origin("https://a.com"), Some("*".to_string()));
+        // credentials are not allowed with *, the origin is not reflected
+        let any = policy("*", true);
+        assert_eq!(any.allow_origin("https://a.com"), Some("*".to_string()));
+        assert!(!any.allow_credentials());
+        assert_eq!(
+            any.response_headers("https://a.com"),
+            vec![
+                ("Access-Control-Allow-Origin", "*".to_string()),
+                ("Access-Control-Expose-Headers", "ETag".to_string()),
+                ("Vary", "Origin".to_string()),
+            ]
+        );
+        let listed = policy("https://a.com, https://b.com", true);
+        assert_eq!(listed.allow_origin("https://c.com"), None);
+        assert!(listed.lists_origin("https://A.com"));
+        assert!(!listed.lists_origin("https://c.com"));
+        assert!(!policy("*", false).lists_origin("https://a.com"));
+        assert!(listed.response_headers("https://c.com").is_empty());
+        assert_eq!(
+            listed.response_headers("https://b.com"),
+            vec![
+                ("Access-Control-Allow-Origin", "https://b.com".to_string()),
+                ("Access-Control-Allow-Credentials", "true".to_string()),
+                ("Access-Control-Expose-Headers", "ETag".to_string()),
+                ("Vary", "Origin".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            listed.preflight_headers("https://a.com", "post", "content-type"),
+            Some(vec![
+                ("Access-Control-Allow-Origin", "https://a.com".to_string()),
+                ("Access-Control-Allow-Methods", "post".to_string()),
+                ("Access-Control-Allow-Headers", "content-type".to_string()),
+                ("Access-Control-Allow-Credentials", "true".to_string()),
+                ("Access-Control-Max-Age", "600".to_string()),
+                ("Vary", "Origin".to_string()),
+            ])
+        );
+        assert_eq!(
+            listed.preflight_headers("https://a.com", "DELETE", ""),
+            None
+        );
+        assert_eq!(
+            listed.preflight_headers("https://a.com", "GET", "X-Custom"),
+            None
+        );
+        assert_eq!(listed.preflight_headers("https://c.com", "GET", ""), None);
+    }
+
+    #[test]
+    fn test_find_policy() {
+        let policies = vec![
+            ("api/public".to_string(), Arc::new(policy("*", false))),
+            ("api".to_string(), Arc::new(policy("https://a.com", true))),
+            ("*".to_string(), Arc::new(policy("https://b.com", false))),
+        ];
+        let origins = |path: &str| find_policy(&policies, path).map(|p| p.origins.join(","));
+        assert_eq!(origins("api/public/items"), Some("*".to_string()));
+        assert_eq!(origins("api/items"), Some("https://a.com".to_string()));
+        assert_eq!(origins("apis"), Some("https://b.com".to_string()));
+        assert_eq!(find_policy(&policies[..2], "portal"), None);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::db_log::DBLogger;
+use crate::db_settings::SettingsManager;
+use crate::nino_constants;
+use crate::nino_functions;
+use crate::nino_structures::{ErrorInfo, LogInfo, ServletTask};
+use crate::web_responses::{ResponseInfo, ResponseManager};
+use deno_core::anyhow::Error;
+use http_types::{Response, StatusCode};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Serves the error pages configured in the settings.
+/// the pages are responses from nino_response - static or executable modules
+#[derive(Clone)]
+pub struct ErrorPageManager {
+    settings: Arc<SettingsManager>,
+    responses: Arc<ResponseManager>,
+}
+
+impl ErrorPageManager {
+    pub fn new(
+        settings: Arc<SettingsManager>,
+        responses: Arc<ResponseManager>,
+    ) -> ErrorPageManager {
+        Self {
+            settings,
+            responses,
+        }
+    }
+
+    /// the configured error page response for the status
+    pub async fn get_page(&self, status: StatusCode) -> Option<(String, ResponseInfo)> {
+        let setting = match status {
+            StatusCode::Forbidden => nino_constants::SETTINGS_NINO_WEB_ERROR_PAGE_FORBIDDEN,
+            StatusCode::NotFound => nino_constants::SETTINGS_NINO_WEB_ERROR_PAGE_NOT_FOUND,
+            StatusCode::MethodNotAllowed => {
+                nino_constants::SETTINGS_NINO_WEB_ERROR_PAGE_METHOD_NOT_ALLOWED
+            }
+            StatusCode::InternalServerError => {
+                nino_constants::SETTINGS_NINO_WEB_ERROR_PAGE_SERVER_ERROR
+            }
+            _ => return None,
+        };
+        let name = self.settings.get_setting_str(setting, "").await;
+        if name.is_empty() {
+            return None;
+        }
+        match self.responses.get_response(&name).await {
+            Ok(Some(response_info)) => Some((name, response_info)),
+            Ok(None) => {
+                eprintln!(
+                    "ERROR {}:{}:error page response '{}' does not exist",
+                    file!(),
+                    line!(),
+                    name
+                );
+                None
+            }
+            Err(error) => {
+                eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+                None
+            }
+        }
+    }
+
+    /// logs the full trace with a new error id.
+    /// the client gets the trace only when stack traces are enabled
+    pub async fn server_error(&self, trace: String, mut log: LogInfo) -> ErrorInfo {
+        let id = Uuid::new_v4().to_string();
+        let stack_trace = self
+            .settings
+            .get_setting_i32(
+                nino_constants::SETTINGS_NINO_WEB_ERROR_STACK_TRACE,
+                nino_constants::SETTINGS_NINO_WEB_ERROR_STACK_TRACE_DEFAULT,
+            )
+            .await;
+        let message = if stack_trace != 0 {
+            trace.clone()
+        } else {
+            format!("internal server error, error id: {}", id)
+        };
+        eprintln!("ERROR {}:{}:error id {}: {}", file!(), line!(), id, trace);
+        log.message = format!("error id {}: {}", id, trace);
+        DBLogger::log(log).await;
+        ErrorInfo {
+            status: u16::from(StatusCode::InternalServerError),
+            id,
+            message,
+        }
+    }
+
+    /// sends the error page for the task error, or the error message as text when no page is defined.
+    /// executable pages are queued to the js threads with the error in request.error
+    pub async fn send_error(
+        &self,
+        mut servlet_task: ServletTask,
+        use_page: bool,
+        headers: &[(&str, &str)],
+    ) -> Result<(), Error> {
+        let error = servlet_task
+            .error
+            .clone()
+            .ok_or(Error::msg("the task has no error"))?;
+        let status =
+            StatusCode::try_from(error.status).map_err(|error| Error::msg(error.to_string()))?;
+        let page = if use_page {
+            self.get_page(status).await
+        } else {
+            None
+        };
+        // executable pages are not queued while the javascript threads are behind
+        let page = match page {
+            Some((_, response_info)) if response_info.execute && self.is_backlogged().await => None,
+            page => page,
+        };
+        let mut response = Response::new(status);
+        for (key, value) in headers {
+            response.insert_header(*key, *value);
+        }
+        match page {
+            Some((name, response_info)) if response_info.execute => {
+                servlet_task.js_module = Some(name);
+                servlet_task.response = Some(response);
+                self.responses
+                    .serve_module(servlet_task, &response_info)
+                    .await
+            }
+            Some((name, response_info)) => {
+                let content = self.responses.get_response_javascript(&name).await?;
+                response.set_content_type(response_info.mime);
+                response.set_body(http_types::Body::from(content));
+                nino_functions::send_response_to_stream(servlet_task.stream, &mut response).await
+            }
+            None => {
+                response.set_body(http_types::Body::from_string(error.message));
+                nino_functions::send_response_to_stream(servlet_task.stream, &mut response).await
+            }
+        }
+    }
+
+    /// more javascript tasks wait for a thread than the task backlog
+    async fn is_backlogged(&self) -> bool {
+        let task_backlog = self
+            .settings
+            .get_setting_usize(
+                nino_constants::SETTINGS_NINO_WEB_TASK_BACKLOG,
+                nino_constants::SETTINGS_NINO_WEB_TASK_BACKLOG_DEFAULT,
+            )
+            .await;
+        task_backlog > 0 && self.responses.task_backlog() >= task_backlog
+    }
+}
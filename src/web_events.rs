@@ -0,0 +1,152 @@
+use crate::nino_constants::info;
+use crate::nino_functions;
+use crate::nino_structures::NotificationMessage;
+use crate::web_stream::WebConnection;
+use async_std::io::WriteExt;
+use deno_core::anyhow::Error;
+use http_types::{Response, StatusCode};
+use std::collections::HashMap;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+const USER_PLACEHOLDER: &str = "{user}";
+// comment line - ignored by the browsers
+const PING: &[u8] = b": ping\n\n";
+
+/// the notification prefixes of an event request - comma separated in the response name.
+/// {user} is replaced with the authenticated user and {name} with the path parameter.
+/// prefixes with unresolved placeholders are skipped, so nobody gets the messages of others
+pub fn event_prefixes(channels: &str, user: &str, params: &HashMap<String, String>) -> Vec<String> {
+    channels
+        .split(',')
+        .map(|channel| channel.trim())
+        .filter(|channel| !channel.is_empty())
+        .filter_map(|channel| {
+            let mut prefix = channel.to_string();
+            if !user.is_empty() {
+                prefix = prefix.replace(USER_PLACEHOLDER, user);
+            }
+            for (name, value) in params {
+                prefix = prefix.replace(&format!("{{{}}}", name), value);
+            }
+            if prefix.contains('{') {
+                None
+            } else {
+                Some(prefix)
+            }
+        })
+        .collect()
+}
+
+/// the event of a message. every line of the data is sent as separate data field
+pub fn event_frame(data: &str) -> String {
+    let mut frame = String::with_capacity(data.len() + 16);
+    for line in data.split('\n') {
+        frame.push_str("data: ");
+        frame.push_str(line.strip_suffix('\r').unwrap_or(line));
+        frame.push('\n');
+    }
+    frame.push('\n');
+    frame
+}
+
+/// Server-Sent Events stream of the notifications starting with one of the prefixes.
+/// the connection is kept open and served without javascript thread,
+/// the messages are sent without the prefix
+pub async fn serve_events(
+    mut stream: WebConnection,
+    mut subscriber: Receiver<NotificationMessage>,
+    prefixes: Vec<String>,
+    ping_ms: u32,
+) {
+    // the end of the stream is the end of the connection
+    stream.keep_alive = false;
+    if let Err(error) = write_events(&stream, &mut subscriber, &prefixes, ping_ms).await {
+        // requestor has closed the stream
+        info!("OK {}:{}:{}", file!(), line!(), error);
+    }
+    stream.release(false);
+}
+
+async fn write_events(
+    stream: &WebConnection,
+    subscriber: &mut Receiver<NotificationMessage>,
+    prefixes: &[String],
+    ping_ms: u32,
+) -> Result<(), Error> {
+    let mut response = Response::new(StatusCode::Ok);
+    response.insert_header("Content-Type", "text/event-stream");
+    response.insert_header("Cache-Control", "no-cache");
+    // reverse proxies like nginx would buffer the events otherwise
+    response.insert_header("X-Accel-Buffering", "no");
+    nino_functions::write_response_head(stream, &mut response).await?;
+    let mut out = stream.stream.clone();
+    out.flush().await?;
+    if stream.head {
+        return Ok(());
+    }
+
+    let ping = tokio::time::Duration::from_millis(ping_ms as u64);
+    loop {
+        let received = if ping_ms > 0 {
+            match tokio::time::timeout(ping, subscriber.recv()).await {
+                Ok(received) => received,
+                Err(_) => {
+                    // idle connection - closed connections are found by the failing write
+                    out.write_all(PING).await?;
+                    out.flush().await?;
+                    continue;
+                }
+            }
+        } else {
+            subscriber.recv().await
+        };
+        match received {
+            Ok(message) => {
+                let data = prefixes
+                    .iter()
+                    .find_map(|prefix| message.text.strip_prefix(prefix.as_str()));
+                if let Some(data) = data {
+                    out.write_all(event_frame(data).as_bytes()).await?;
+                    out.flush().await?;
+                }
+            }
+            Err(RecvError::Lagged(count)) => {
+                // the messages are lost for this connection only
+                eprintln!(
+                    "ERROR {}:{}:event stream skipped {} messages",
+                    file!(),
+                    line!(),
+                    count
+                );
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web_events::{event_frame, event_prefixes};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_event_prefixes() {
+        let params = HashMap::from([("topic".to_string(), "news".to_string())]);
+        assert_eq!(
+            event_prefixes("portal:, user:{user}:, topic:{topic}:", "john", &params),
+            vec!["portal:", "user:john:", "topic:news:"]
+        );
+        // unresolved placeholders are skipped
+        assert_eq!(
+            event_prefixes("user:{user}:, room:{room}:, portal:", "", &params),
+            vec!["portal:"]
+        );
+        assert!(event_prefixes(" , ", "john", &params).is_empty());
+    }
+
+    #[test]
+    fn test_event_frame() {
+        assert_eq!(event_frame("hello"), "data: hello\n\n");
+        assert_eq!(event_frame("a\r\nb\n"), "data: a\ndata: b\ndata: \n\n");
+    }
+}
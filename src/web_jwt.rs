@@ -0,0 +1,360 @@
+use crate::db_settings::SettingsManager;
+use crate::{db_notification, nino_constants, nino_structures};
+use deno_core::anyhow::Error;
+use deno_core::serde_json::Value;
+use hmac::{digest::KeyInit, Hmac};
+use jwt::{Claims, PKeyWithDigest, RegisteredClaims, SignWithKey, VerifyWithKey};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private, Public};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// allowed clock difference between the cluster nodes in seconds
+const CLOCK_LEEWAY_S: u64 = 60;
+
+enum SigningKey {
+    Hmac(Hmac<Sha256>),
+    PKey(PKeyWithDigest<Private>),
+}
+
+enum VerifyingKey {
+    Hmac(Hmac<Sha256>),
+    PKey(PKeyWithDigest<Public>),
+}
+
+/// the current signing key and all keys accepted for verification.
+/// the first verifying key is the current one, the rest are previous keys kept for rotation
+pub struct JwtKeys {
+    signing: SigningKey,
+    verifying: Vec<VerifyingKey>,
+    issuer: String,
+    expiration_s: u64,
+}
+
+impl JwtKeys {
+    pub fn hmac(
+        secret: &str,
+        previous_secret: &str,
+        issuer: String,
+        expiration_s: u64,
+    ) -> Result<JwtKeys, Error> {
+        let signing = SigningKey::Hmac(Hmac::new_from_slice(secret.as_bytes())?);
+        let mut verifying = vec![VerifyingKey::Hmac(Hmac::new_from_slice(secret.as_bytes())?)];
+        if !previous_secret.is_empty() {
+            verifying.push(VerifyingKey::Hmac(Hmac::new_from_slice(
+                previous_secret.as_bytes(),
+            )?));
+        }
+        Ok(JwtKeys {
+            signing,
+            verifying,
+            issuer,
+            expiration_s,
+        })
+    }
+
+    /// RS256 or ES256 keys depending on the type of the PEM private key
+    pub fn pkey(
+        private_key_pem: &str,
+        public_key_pem: &str,
+        previous_public_key_pem: &str,
+        issuer: String,
+        expiration_s: u64,
+    ) -> Result<JwtKeys, Error> {
+        let signing = SigningKey::PKey(PKeyWithDigest {
+            digest: MessageDigest::sha256(),
+            key: PKey::private_key_from_pem(private_key_pem.as_bytes())?,
+        });
+        let mut verifying = Vec::with_capacity(2);
+        for public_key_pem in [public_key_pem, previous_public_key_pem] {
+            if !public_key_pem.is_empty() {
+                verifying.push(VerifyingKey::PKey(PKeyWithDigest {
+                    digest: MessageDigest::sha256(),
+                    key: PKey::public_key_from_pem(public_key_pem.as_bytes())?,
+                }));
+            }
+        }
+        if verifying.is_empty() {
+            return Err(Error::msg("jwt public key is not defined"));
+        }
+        Ok(JwtKeys {
+            signing,
+            verifying,
+            issuer,
+            expiration_s,
+        })
+    }
+
+    pub fn sign(&self, user: &str, now: u64) -> Result<String, Error> {
+        let mut private: BTreeMap<String, Value> = BTreeMap::new();
+        private.insert(nino_constants::JWT_USER.to_string(), Value::from(user));
+        let claims = Claims {
+            registered: RegisteredClaims {
+                issuer: Some(self.issuer.clone()),
+                issued_at: Some(now),
+                not_before: Some(now),
+                expiration: Some(now + self.expiration_s),
+                ..Default::default()
+            },
+            private,
+        };
+        let jwt = match &self.signing {
+            SigningKey::Hmac(key) => claims.sign_with_key(key)?,
+            SigningKey::PKey(key) => claims.sign_with_key(key)?,
+        };
+        Ok(jwt)
+    }
+
+    /// returns the user from a valid jwt
+    pub fn verify(&self, jwt: &str, now: u64) -> Result<String, Error> {
+        let mut last_error = Error::msg("no jwt verification key");
+        for key in self.verifying.iter() {
+            let claims: Result<Claims, jwt::Error> = match key {
+                VerifyingKey::Hmac(key) => jwt.verify_with_key(key),
+                VerifyingKey::PKey(key) => jwt.verify_with_key(key),
+            };
+            match claims {
+                Ok(claims) => return self.check_claims(claims, now),
+                Err(error) => last_error = error.into(),
+            }
+        }
+        Err(last_error)
+    }
+
+    fn check_claims(&self, claims: Claims, now: u64) -> Result<String, Error> {
+        let registered = &claims.registered;
+        match registered.expiration {
+            Some(expiration) if expiration + CLOCK_LEEWAY_S >= now => {}
+            Some(_) => return Err(Error::msg("jwt has expired")),
+            None => return Err(Error::msg("jwt has no expiration")),
+        }
+        if registered
+            .not_before
+            .is_some_and(|nbf| nbf > now + CLOCK_LEEWAY_S)
+        {
+            return Err(Error::msg("jwt is not valid yet"));
+        }
+        if registered
+            .issued_at
+            .is_some_and(|iat| iat > now + CLOCK_LEEWAY_S)
+        {
+            return Err(Error::msg("jwt is issued in the future"));
+        }
+        if registered.issuer.as_deref() != Some(self.issuer.as_str()) {
+            return Err(Error::msg("jwt issuer does not match"));
+        }
+        match claims
+            .private
+            .get(nino_constants::JWT_USER)
+            .and_then(Value::as_str)
+        {
+            Some(user) => Ok(user.to_string()),
+            None => Err(Error::msg("jwt has no user")),
+        }
+    }
+}
+
+/// Keeps the keys for signing and verifying user JWTs.
+/// keys are loaded from the settings or environment variables and reloaded on setting change
+#[derive(Clone)]
+pub struct JwtManager {
+    settings: Arc<SettingsManager>,
+}
+
+static JWT_KEYS: OnceLock<RwLock<Option<Arc<JwtKeys>>>> = OnceLock::new();
+static GENERATED_SECRET: OnceLock<String> = OnceLock::new();
+
+impl JwtManager {
+    pub async fn new(
+        settings: Arc<SettingsManager>,
+        settings_subscribe: tokio::sync::broadcast::Receiver<nino_structures::NotificationMessage>,
+    ) -> JwtManager {
+        JWT_KEYS.get_or_init(|| RwLock::new(None));
+        let this = Self { settings };
+        // keys should be available before serving any request
+        this.reload_keys().await;
+        let thizz = this.clone();
+        tokio::spawn(async move {
+            thizz.invalidator(settings_subscribe).await;
+        });
+        this
+    }
+
+    pub async fn invalidator(
+        &self,
+        mut settings_subscribe: tokio::sync::broadcast::Receiver<
+            nino_structures::NotificationMessage,
+        >,
+    ) {
+        loop {
+            match settings_subscribe.recv().await {
+                Err(error) => {
+                    eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+                }
+                Ok(message) => {
+                    if message
+                        .text
+                        .starts_with(db_notification::NOTIFICATION_PREFIX_SETTING)
+                    {
+                        self.reload_keys().await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn reload_keys(&self) {
+        match self.load_keys().await {
+            Ok(keys) => {
+                *JWT_KEYS.get().unwrap().write().unwrap() = Some(Arc::new(keys));
+            }
+            Err(error) => {
+                // keep the previous keys
+                eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+            }
+        }
+    }
+
+    /// environment variable with the upper case setting name has priority over the setting
+    async fn get_secret(&self, settings_key: &str) -> String {
+        match std::env::var(settings_key.to_uppercase()) {
+            Ok(value) => value,
+            Err(_) => self.settings.get_setting_str(settings_key, "").await,
+        }
+    }
+
+    async fn load_keys(&self) -> Result<JwtKeys, Error> {
+        let algorithm = self
+            .settings
+            .get_setting_str(
+                nino_constants::SETTINGS_NINO_JWT_ALGORITHM,
+                nino_constants::SETTINGS_NINO_JWT_ALGORITHM_DEFAULT,
+            )
+            .await
+            .to_uppercase();
+        let issuer = self
+            .settings
+            .get_setting_str(
+                nino_constants::SETTINGS_NINO_JWT_ISSUER,
+                nino_constants::SETTINGS_NINO_JWT_ISSUER_DEFAULT,
+            )
+            .await;
+        let expiration_s = self
+            .settings
+            .get_setting_i32(
+                nino_constants::SETTINGS_NINO_JWT_EXPIRATION_S,
+                nino_constants::SETTINGS_NINO_JWT_EXPIRATION_S_DEFAULT,
+            )
+            .await
+            .max(1) as u64;
+
+        match algorithm.as_str() {
+            "HS256" => {
+                let mut secret = self
+                    .get_secret(nino_constants::SETTINGS_NINO_JWT_SECRET)
+                    .await;
+                if secret.is_empty() {
+                    eprintln!(
+                        "WARNING: {} is not defined, using generated secret valid only for this instance",
+                        nino_constants::SETTINGS_NINO_JWT_SECRET
+                    );
+                    secret = GENERATED_SECRET
+                        .get_or_init(|| {
+                            format!(
+                                "{}{}",
+                                uuid::Uuid::new_v4().simple(),
+                                uuid::Uuid::new_v4().simple()
+                            )
+                        })
+                        .clone();
+                }
+                let previous_secret = self
+                    .get_secret(nino_constants::SETTINGS_NINO_JWT_SECRET_PREVIOUS)
+                    .await;
+                JwtKeys::hmac(&secret, &previous_secret, issuer, expiration_s)
+            }
+            "RS256" | "ES256" => {
+                let private_key = self
+                    .get_secret(nino_constants::SETTINGS_NINO_JWT_PRIVATE_KEY)
+                    .await;
+                let public_key = self
+                    .get_secret(nino_constants::SETTINGS_NINO_JWT_PUBLIC_KEY)
+                    .await;
+                let previous_public_key = self
+                    .get_secret(nino_constants::SETTINGS_NINO_JWT_PUBLIC_KEY_PREVIOUS)
+                    .await;
+                JwtKeys::pkey(
+                    &private_key,
+                    &public_key,
+                    &previous_public_key,
+                    issuer,
+                    expiration_s,
+                )
+            }
+            _ => Err(Error::msg(format!(
+                "unsupported jwt algorithm: {}",
+                algorithm
+            ))),
+        }
+    }
+
+    fn keys() -> Result<Arc<JwtKeys>, Error> {
+        match JWT_KEYS.get().and_then(|keys| keys.read().unwrap().clone()) {
+            Some(keys) => Ok(keys),
+            None => Err(Error::msg("jwt keys are not loaded")),
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+
+    /// creates signed jwt for the user
+    pub fn sign(user: &str) -> Result<String, Error> {
+        Self::keys()?.sign(user, Self::now())
+    }
+
+    /// returns the user of a valid jwt
+    pub fn verify(jwt: &str) -> Result<String, Error> {
+        Self::keys()?.verify(jwt, Self::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web_jwt::JwtKeys;
+
+    #[test]
+    fn test_jwt_claims() {
+        let now = 1_700_000_000;
+        let keys = JwtKeys::hmac("secret", "", "nino".into(), 3600).unwrap();
+        let jwt = keys.sign("admin", now).unwrap();
+        assert_eq!(keys.verify(&jwt, now + 10).unwrap(), "admin");
+        // expired
+        assert!(keys.verify(&jwt, now + 3600 + 120).is_err());
+        // not valid yet
+        assert!(keys.verify(&jwt, now - 120).is_err());
+        // other issuer
+        let other = JwtKeys::hmac("secret", "", "other".into(), 3600).unwrap();
+        assert!(other.verify(&jwt, now).is_err());
+        // wrong secret
+        let wrong = JwtKeys::hmac("wrong", "", "nino".into(), 3600).unwrap();
+        assert!(wrong.verify(&jwt, now).is_err());
+    }
+
+    #[test]
+    fn test_jwt_rotation() {
+        let now = 1_700_000_000;
+        let old_keys = JwtKeys::hmac("old", "", "nino".into(), 3600).unwrap();
+        let jwt = old_keys.sign("user", now).unwrap();
+        let rotated = JwtKeys::hmac("new", "old", "nino".into(), 3600).unwrap();
+        assert_eq!(rotated.verify(&jwt, now).unwrap(), "user");
+        let new_jwt = rotated.sign("user", now).unwrap();
+        assert!(old_keys.verify(&new_jwt, now).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// a bucket refills completely within this time, so idle buckets are full and can be dropped
+const REFILL_PERIOD: Duration = Duration::from_secs(60);
+
+/// token bucket of a client, refilled with limit tokens per minute up to limit tokens
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(limit: u32, now: Instant) -> Bucket {
+        Bucket {
+            tokens: limit as f64,
+            updated: now,
+        }
+    }
+
+    /// takes a token. Err with the time until the next token
+    fn take(&mut self, limit: u32, now: Instant) -> Result<(), Duration> {
+        let capacity = limit as f64;
+        let rate = capacity / REFILL_PERIOD.as_secs_f64();
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(capacity);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+/// the requests per minute of a request: its own limit, 0 - the default, negative - unlimited.
+/// None when the requests are not limited
+pub fn effective_limit(request_limit: i32, default_limit: u32) -> Option<u32> {
+    match request_limit {
+        limit if limit < 0 => None,
+        0 if default_limit == 0 => None,
+        0 => Some(default_limit),
+        limit => Some(limit as u32),
+    }
+}
+
+/// Limits the requests per minute of the clients (users or addresses) with token buckets
+#[derive(Clone)]
+pub struct RateLimiter {
+    default_limit: u32,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(default_limit: u32) -> RateLimiter {
+        let this = Self {
+            default_limit,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        };
+        let buckets = this.buckets.clone();
+        tokio::spawn(async move {
+            Self::sweeping(buckets).await;
+        });
+        this
+    }
+
+    /// drops the buckets of the idle clients
+    async fn sweeping(buckets: Arc<Mutex<HashMap<String, Bucket>>>) {
+        let mut interval = tokio::time::interval(REFILL_PERIOD);
+        loop {
+            interval.tick().await;
+            let now = Instant::now();
+            buckets
+                .lock()
+                .unwrap()
+                .retain(|_, bucket| now.saturating_duration_since(bucket.updated) < REFILL_PERIOD);
+        }
+    }
+
+    /// takes a token of the client for the request.
+    /// Err with the seconds to wait when the client is over the limit
+    pub fn check(&self, key: String, request_limit: i32) -> Result<(), u64> {
+        let Some(limit) = effective_limit(request_limit, self.default_limit) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets
+            .entry(key)
+            .or_insert_with(|| Bucket::new(limit, now));
+        bucket
+            .take(limit, now)
+            .map_err(|wait| wait.as_secs_f64().ceil().max(1.0) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web_limits::{effective_limit, Bucket};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_effective_limit() {
+        assert_eq!(effective_limit(0, 0), None);
+        assert_eq!(effective_limit(0, 60), Some(60));
+        assert_eq!(effective_limit(10, 60), Some(10));
+        assert_eq!(effective_limit(-1, 60), None);
+    }
+
+    #[test]
+    fn test_bucket() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(2, now);
+        assert!(bucket.take(2, now).is_ok());
+        assert!(bucket.take(2, now).is_ok());
+        // 2 per minute - the next token in 30 seconds
+        let wait = bucket.take(2, now).unwrap_err();
+        assert_eq!(wait.as_secs(), 30);
+        assert!(bucket.take(2, now + Duration::from_secs(15)).is_err());
+        assert!(bucket.take(2, now + Duration::from_secs(31)).is_ok());
+        // refilled up to the limit only
+        let later = now + Duration::from_secs(600);
+        assert!(bucket.take(2, later).is_ok());
+        assert!(bucket.take(2, later).is_ok());
+        assert!(bucket.take(2, later).is_err());
+    }
+}
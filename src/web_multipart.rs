@@ -0,0 +1,341 @@
+use std::fmt;
+
+const CRLF: &[u8] = b"\r\n";
+const HEADERS_END: &[u8] = b"\r\n\r\n";
+
+/// a single part of multipart/form-data request body
+#[derive(Clone)]
+pub struct MultipartPart {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub content: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum MultipartError {
+    Malformed(String),
+    PartTooLarge(String),
+    BodyTooLarge,
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MultipartError::Malformed(reason) => write!(f, "malformed multipart body: {}", reason),
+            MultipartError::PartTooLarge(name) => {
+                write!(f, "multipart part '{}' exceeds the maximum part size", name)
+            }
+            MultipartError::BodyTooLarge => write!(f, "request body is too large"),
+        }
+    }
+}
+
+/// returns the boundary if the content type is multipart/form-data
+pub fn multipart_boundary(content_type: &str) -> Option<String> {
+    let mut tokens = split_parameters(content_type).into_iter();
+    let mime = tokens.next()?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    tokens
+        .filter_map(|token| parameter_value(&token, "boundary"))
+        .find(|boundary| !boundary.is_empty())
+}
+
+// the headers of a part are small, a longer header block is malformed
+const MAX_HEADERS_SIZE: usize = 16 * 1024;
+
+enum ParserState {
+    Preamble,
+    Boundary,
+    Headers,
+    Content(MultipartPart),
+    Done,
+}
+
+/// parses multipart/form-data body while it is read, so a part over
+/// the maximum part size is rejected before the rest of the body is read.
+/// the body is passed whole on every call, with the newly read bytes appended
+pub struct MultipartParser {
+    delimiter: Vec<u8>,
+    close_delimiter: Vec<u8>,
+    max_part_size: usize,
+    state: ParserState,
+    // start of the not parsed bytes
+    position: usize,
+    // the searched delimiter is not before this offset
+    scan: usize,
+    parts: Vec<MultipartPart>,
+}
+
+impl MultipartParser {
+    pub fn new(boundary: &str, max_part_size: usize) -> MultipartParser {
+        let delimiter = format!("--{}", boundary).into_bytes();
+        let mut close_delimiter = CRLF.to_vec();
+        close_delimiter.extend_from_slice(&delimiter);
+        MultipartParser {
+            delimiter,
+            close_delimiter,
+            max_part_size,
+            state: ParserState::Preamble,
+            position: 0,
+            scan: 0,
+            parts: Vec::new(),
+        }
+    }
+
+    /// parses the body read so far
+    pub fn parse(&mut self, body: &[u8]) -> Result<(), MultipartError> {
+        loop {
+            match std::mem::replace(&mut self.state, ParserState::Done) {
+                ParserState::Preamble => {
+                    match search(&mut self.scan, self.position, body, &self.delimiter) {
+                        Some(position) => {
+                            self.position = position + self.delimiter.len();
+                            self.state = ParserState::Boundary;
+                        }
+                        None => {
+                            self.state = ParserState::Preamble;
+                            return Ok(());
+                        }
+                    }
+                }
+                ParserState::Boundary => {
+                    let rest = &body[self.position..];
+                    if rest.len() < CRLF.len() {
+                        self.state = ParserState::Boundary;
+                        return Ok(());
+                    }
+                    if rest.starts_with(b"--") {
+                        // closing delimiter
+                        return Ok(());
+                    }
+                    if !rest.starts_with(CRLF) {
+                        return Err(MultipartError::Malformed(
+                            "boundary without new line".into(),
+                        ));
+                    }
+                    self.position += CRLF.len();
+                    self.state = ParserState::Headers;
+                }
+                ParserState::Headers => {
+                    match search(&mut self.scan, self.position, body, HEADERS_END) {
+                        Some(headers_end) => {
+                            let headers =
+                                String::from_utf8_lossy(&body[self.position..headers_end]);
+                            self.state = ParserState::Content(parse_part_headers(&headers));
+                            self.position = headers_end + HEADERS_END.len();
+                        }
+                        None if body.len() - self.position > MAX_HEADERS_SIZE => {
+                            return Err(MultipartError::Malformed(
+                                "part headers are too large".into(),
+                            ));
+                        }
+                        None => {
+                            self.state = ParserState::Headers;
+                            return Ok(());
+                        }
+                    }
+                }
+                ParserState::Content(mut part) => {
+                    match search(&mut self.scan, self.position, body, &self.close_delimiter) {
+                        Some(content_end) => {
+                            if content_end - self.position > self.max_part_size {
+                                return Err(MultipartError::PartTooLarge(part.name));
+                            }
+                            part.content = body[self.position..content_end].to_vec();
+                            self.parts.push(part);
+                            self.position = content_end + self.close_delimiter.len();
+                            self.state = ParserState::Boundary;
+                        }
+                        None => {
+                            // the bytes before a partially read delimiter are content
+                            let content = (body.len() - self.position)
+                                .saturating_sub(self.close_delimiter.len() - 1);
+                            if content > self.max_part_size {
+                                return Err(MultipartError::PartTooLarge(part.name));
+                            }
+                            self.state = ParserState::Content(part);
+                            return Ok(());
+                        }
+                    }
+                }
+                ParserState::Done => return Ok(()),
+            }
+        }
+    }
+
+    /// returns the parts once the whole body is parsed
+    pub fn finish(self, body: &[u8]) -> Result<Vec<MultipartPart>, MultipartError> {
+        let reason = match self.state {
+            ParserState::Done => return Ok(self.parts),
+            ParserState::Preamble => "no boundary found",
+            ParserState::Boundary if body.len() > self.position => "boundary without new line",
+            ParserState::Boundary => "body without closing boundary",
+            ParserState::Headers => "part without headers",
+            ParserState::Content(_) => "part without closing boundary",
+        };
+        Err(MultipartError::Malformed(reason.into()))
+    }
+}
+
+/// finds the needle in the not parsed bytes, without scanning again the bytes
+/// searched by the previous calls
+fn search(scan: &mut usize, position: usize, body: &[u8], needle: &[u8]) -> Option<usize> {
+    let found = find(body, needle, position.max(*scan));
+    *scan = match found {
+        Some(found) => found + needle.len(),
+        None => body.len().saturating_sub(needle.len() - 1),
+    };
+    found
+}
+
+fn parse_part_headers(headers: &str) -> MultipartPart {
+    let mut part = MultipartPart {
+        name: String::new(),
+        filename: None,
+        content_type: None,
+        content: Vec::new(),
+    };
+    for header in headers.split("\r\n") {
+        let Some((key, value)) = header.split_once(':') else {
+            continue;
+        };
+        let key = key.trim();
+        if key.eq_ignore_ascii_case("Content-Disposition") {
+            for token in split_parameters(value) {
+                if let Some(name) = parameter_value(&token, "name") {
+                    part.name = name;
+                } else if let Some(filename) = parameter_value(&token, "filename") {
+                    part.filename = Some(filename);
+                }
+            }
+        } else if key.eq_ignore_ascii_case("Content-Type") {
+            part.content_type = Some(value.trim().to_string());
+        }
+    }
+    part
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if from >= haystack.len() {
+        return None;
+    }
+    haystack[from..]
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|position| position + from)
+}
+
+/// split header value by ';' ignoring the ones in quotes
+fn split_parameters(value: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in value.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                token.push(c);
+            }
+            ';' if !quoted => tokens.push(std::mem::take(&mut token)),
+            _ => token.push(c),
+        }
+    }
+    tokens.push(token);
+    tokens
+}
+
+fn parameter_value(token: &str, name: &str) -> Option<String> {
+    let (key, value) = token.split_once('=')?;
+    if !key.trim().eq_ignore_ascii_case(name) {
+        return None;
+    }
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    Some(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web_multipart::{
+        multipart_boundary, MultipartError, MultipartParser, MultipartPart,
+    };
+
+    fn parse_multipart(
+        body: &[u8],
+        boundary: &str,
+        max_part_size: usize,
+    ) -> Result<Vec<MultipartPart>, MultipartError> {
+        let mut parser = MultipartParser::new(boundary, max_part_size);
+        parser.parse(body)?;
+        parser.finish(body)
+    }
+
+    const BODY: &str = "preamble\r\n--XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+        hello world\r\n--XyZ\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"a;b.txt\"\r\n\
+        Content-Type: text/plain\r\n\r\n\
+        line1\r\nline2\r\n--XyZ--\r\n";
+
+    #[test]
+    fn test_multipart_boundary() {
+        assert_eq!(
+            multipart_boundary("multipart/form-data; boundary=\"XyZ\"").unwrap(),
+            "XyZ"
+        );
+        assert_eq!(
+            multipart_boundary("Multipart/Form-Data;charset=utf-8;boundary=abc").unwrap(),
+            "abc"
+        );
+        assert!(multipart_boundary("application/x-www-form-urlencoded").is_none());
+    }
+
+    #[test]
+    fn test_parse_multipart() {
+        let parts = parse_multipart(BODY.as_bytes(), "XyZ", 1024).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "title");
+        assert!(parts[0].filename.is_none());
+        assert_eq!(parts[0].content, b"hello world");
+        assert_eq!(parts[1].name, "file");
+        assert_eq!(parts[1].filename.as_deref(), Some("a;b.txt"));
+        assert_eq!(parts[1].content_type.as_deref(), Some("text/plain"));
+        assert_eq!(parts[1].content, b"line1\r\nline2");
+
+        match parse_multipart(BODY.as_bytes(), "XyZ", 8) {
+            Err(MultipartError::PartTooLarge(name)) => assert_eq!(name, "title"),
+            _ => panic!("part size limit is not honoured"),
+        }
+        assert!(parse_multipart(b"--XyZ\r\nbroken", "XyZ", 1024).is_err());
+    }
+
+    #[test]
+    fn test_multipart_parser() {
+        // the body is read a few bytes at a time
+        let body = BODY.as_bytes();
+        let mut parser = MultipartParser::new("XyZ", 1024);
+        for read in (0..body.len()).step_by(3) {
+            parser.parse(&body[..read]).unwrap();
+        }
+        parser.parse(body).unwrap();
+        let parts = parser.finish(body).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].content, b"hello world");
+        assert_eq!(parts[1].content, b"line1\r\nline2");
+
+        // a large part is rejected before its closing boundary is read
+        let mut large = b"--XyZ\r\nContent-Disposition: form-data; name=\"big\"\r\n\r\n".to_vec();
+        large.extend_from_slice(&[b'a'; 64]);
+        let mut parser = MultipartParser::new("XyZ", 32);
+        match parser.parse(&large) {
+            Err(MultipartError::PartTooLarge(name)) => assert_eq!(name, "big"),
+            _ => panic!("part size limit is not honoured while reading"),
+        }
+    }
+}
@@ -4,12 +4,14 @@ use crate::{
     db::DBManager,
     db_notification,
     nino_constants::{self, USE_REQUEST_CACHE},
+    nino_functions::RoutePattern,
     nino_structures,
 };
 use std::{
     collections::HashMap,
     sync::{Arc, OnceLock, RwLock},
 };
+use tokio_postgres::Row;
 
 #[derive(Clone)]
 pub struct RequestManager {
@@ -18,19 +20,29 @@ pub struct RequestManager {
 
 #[derive(Clone)]
 pub struct RequestInfo {
+    /// upper case HTTP method or * for any method
+    pub method: String,
     pub name: String,
     pub redirect: bool,
+    /// Server-Sent Events of the notifications with the prefixes in name
+    pub events: bool,
+    /// upgrades to websocket with the events handled by the name module
+    pub websocket: bool,
     pub authorize: bool,
+    /// the user needs at least one of these roles. empty means no role check
+    pub required_roles: Vec<String>,
+    /// requests per minute of a client. 0 uses the default limit, negative is unlimited
+    pub rate_limit: i32,
 }
 
-static REQUEST_CACHE: OnceLock<RwLock<HashMap<String, RequestInfo>>> = OnceLock::new();
+static REQUEST_CACHE: OnceLock<RwLock<RequestRoutes>> = OnceLock::new();
 
 impl RequestManager {
     pub fn new(
         db: Arc<DBManager>,
         db_subscribe: tokio::sync::broadcast::Receiver<nino_structures::NotificationMessage>,
     ) -> RequestManager {
-        REQUEST_CACHE.get_or_init(|| RwLock::new(HashMap::new()));
+        REQUEST_CACHE.get_or_init(|| RwLock::new(RequestRoutes::default()));
         let this = Self { db };
         let thizz = this.clone();
         tokio::spawn(async move {
@@ -66,7 +78,8 @@ impl RequestManager {
     async fn reload_requests(&self) {
         //reload the db aliases
         let query: String = format!(
-            "SELECT request_path, response_name, redirect_flag, authorize_flag FROM {}",
+            "SELECT {} FROM {}",
+            REQUEST_COLUMNS,
             nino_constants::REQUESTS_TABLE
         );
         match self.db.query(&query, &[]).await {
@@ -74,49 +87,208 @@ impl RequestManager {
                 eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
             }
             Ok(rows) => {
-                let mut map = REQUEST_CACHE.get().unwrap().write().unwrap();
-                map.clear();
-                for row in rows {
-                    let path: String = row.get(0);
-                    let name: String = row.get(1);
-                    let redirect: bool = row.get(2);
-                    let authorize: bool = row.get(3);
-                    map.insert(
-                        path,
-                        RequestInfo {
-                            name,
-                            redirect,
-                            authorize,
-                        },
-                    );
-                }
+                let routes = RequestRoutes::from_rows(rows);
+                *REQUEST_CACHE.get().unwrap().write().unwrap() = routes;
             }
         }
     }
 
-    pub async fn get_request(&self, path: &String) -> Result<Option<RequestInfo>, Error> {
+    /// finds the requests by the normalized path or by pattern matching the url path.
+    /// returns the requests for all methods of the path with the captured path parameters
+    pub async fn get_request(
+        &self,
+        path: &String,
+        url_path: &str,
+    ) -> Result<Option<(Vec<RequestInfo>, HashMap<String, String>)>, Error> {
         if USE_REQUEST_CACHE {
-            let map = REQUEST_CACHE.get().unwrap().read().unwrap();
-            Ok(map.get(path).cloned())
+            let routes = REQUEST_CACHE.get().unwrap().read().unwrap();
+            Ok(routes.find(path, url_path))
         } else {
             let query: String = format!(
-                "SELECT response_name, redirect_flag, authorize_flag FROM {} WHERE request_path = $1",
+                "SELECT {} FROM {} WHERE request_path = $1",
+                REQUEST_COLUMNS,
+                nino_constants::REQUESTS_TABLE
+            );
+            let rows = self.db.query(&query, &[&path]).await?;
+            if !rows.is_empty() {
+                let requests = rows.iter().map(|row| row_to_request(row).1).collect();
+                return Ok(Some((requests, HashMap::new())));
+            }
+            let query: String = format!(
+                "SELECT {} FROM {} WHERE request_path LIKE '%:%' OR request_path LIKE '%*%'",
+                REQUEST_COLUMNS,
                 nino_constants::REQUESTS_TABLE
             );
-            let result = self.db.query_opt(&query, &[&path]).await?;
-            match result {
-                None => Ok(None),
-                Some(row) => {
-                    let name: String = row.get(0);
-                    let redirect: bool = row.get(1);
-                    let authorize: bool = row.get(2);
-                    Ok(Some(RequestInfo {
-                        name,
-                        redirect,
-                        authorize,
-                    }))
+            let rows = self.db.query(&query, &[]).await?;
+            Ok(RequestRoutes::from_rows(rows).find(path, url_path))
+        }
+    }
+}
+
+const REQUEST_COLUMNS: &str =
+    "request_path, response_name, redirect_flag, authorize_flag, required_roles, request_method, \
+    event_flag, websocket_flag, rate_limit";
+
+const ANY_METHOD: &str = "*";
+// methods allowed for requests defined with * method
+const ALL_METHODS: &str = "GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS";
+
+fn row_to_request(row: &Row) -> (String, RequestInfo) {
+    let path: String = row.get(0);
+    let name: String = row.get(1);
+    let redirect: bool = row.get(2);
+    let authorize: bool = row.get(3);
+    let required_roles: Option<String> = row.get(4);
+    let method: Option<String> = row.get(5);
+    let method = method
+        .map(|method| method.trim().to_uppercase())
+        .filter(|method| !method.is_empty())
+        .unwrap_or_else(|| ANY_METHOD.to_string());
+    let events: Option<bool> = row.get(6);
+    let websocket: Option<bool> = row.get(7);
+    let rate_limit: Option<i32> = row.get(8);
+    (
+        path,
+        RequestInfo {
+            method,
+            name,
+            redirect,
+            events: events.unwrap_or(false),
+            websocket: websocket.unwrap_or(false),
+            authorize,
+            required_roles: split_roles(required_roles),
+            rate_limit: rate_limit.unwrap_or(0),
+        },
+    )
+}
+
+/// exact paths and the patterns ordered by precedence with the requests of all methods
+#[derive(Default)]
+struct RequestRoutes {
+    exact: HashMap<String, Vec<RequestInfo>>,
+    patterns: Vec<(RoutePattern, Vec<RequestInfo>)>,
+}
+
+impl RequestRoutes {
+    fn from_rows(rows: Vec<Row>) -> RequestRoutes {
+        let mut paths: HashMap<String, Vec<RequestInfo>> = HashMap::new();
+        for row in rows {
+            let (path, request_info) = row_to_request(&row);
+            paths.entry(path).or_default().push(request_info);
+        }
+        let mut routes = RequestRoutes::default();
+        for (path, requests) in paths {
+            match RoutePattern::parse(&path) {
+                Ok(Some(pattern)) => routes.patterns.push((pattern, requests)),
+                Ok(None) => {
+                    routes.exact.insert(path, requests);
+                }
+                Err(error) => {
+                    eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
                 }
             }
         }
+        routes.patterns.sort_by(|(a, _), (b, _)| a.precedence(b));
+        routes
+    }
+
+    fn find(
+        &self,
+        path: &String,
+        url_path: &str,
+    ) -> Option<(Vec<RequestInfo>, HashMap<String, String>)> {
+        // exact paths have precedence over the patterns
+        if let Some(requests) = self.exact.get(path) {
+            return Some((requests.clone(), HashMap::new()));
+        }
+        self.patterns.iter().find_map(|(pattern, requests)| {
+            pattern
+                .matches(url_path)
+                .map(|params| (requests.clone(), params))
+        })
+    }
+}
+
+/// the request for the method - exact method first, then any method.
+/// HEAD uses the GET request when not defined. OPTIONS needs explicit definition
+pub fn select_method(requests: &[RequestInfo], method: &str) -> Option<RequestInfo> {
+    let find = |method: &str| requests.iter().find(|request| request.method == method);
+    let found = match method {
+        "OPTIONS" => find(method),
+        "HEAD" => find(method)
+            .or_else(|| find("GET"))
+            .or_else(|| find(ANY_METHOD)),
+        _ => find(method).or_else(|| find(ANY_METHOD)),
+    };
+    found.cloned()
+}
+
+/// value of the Allow header for the requests of a path
+pub fn allowed_methods(requests: &[RequestInfo]) -> String {
+    if requests.iter().any(|request| request.method == ANY_METHOD) {
+        return ALL_METHODS.to_string();
+    }
+    let mut methods: Vec<&str> = requests
+        .iter()
+        .map(|request| request.method.as_str())
+        .collect();
+    if methods.contains(&"GET") && !methods.contains(&"HEAD") {
+        methods.push("HEAD");
+    }
+    if !methods.contains(&"OPTIONS") {
+        methods.push("OPTIONS");
+    }
+    methods.join(", ")
+}
+
+/// required roles are stored comma separated
+fn split_roles(roles: Option<String>) -> Vec<String> {
+    roles
+        .unwrap_or_default()
+        .split(',')
+        .map(|role| role.trim())
+        .filter(|role| !role.is_empty())
+        .map(|role| role.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web_requests::{allowed_methods, select_method, RequestInfo};
+
+    fn request(method: &str, name: &str) -> RequestInfo {
+        RequestInfo {
+            method: method.to_string(),
+            name: name.to_string(),
+            redirect: false,
+            events: false,
+            websocket: false,
+            authorize: false,
+            required_roles: Vec::new(),
+            rate_limit: 0,
+        }
+    }
+
+    #[test]
+    fn test_select_method() {
+        let requests = vec![request("GET", "items/list"), request("POST", "items/add")];
+        let name = |method: &str| select_method(&requests, method).map(|r| r.name);
+        assert_eq!(name("GET"), Some("items/list".to_string()));
+        assert_eq!(name("POST"), Some("items/add".to_string()));
+        assert_eq!(name("HEAD"), Some("items/list".to_string()));
+        assert_eq!(name("DELETE"), None);
+        assert_eq!(name("OPTIONS"), None);
+        assert_eq!(allowed_methods(&requests), "GET, POST, HEAD, OPTIONS");
+
+        let requests = vec![request("*", "items"), request("DELETE", "items/delete")];
+        let name = |method: &str| select_method(&requests, method).map(|r| r.name);
+        assert_eq!(name("DELETE"), Some("items/delete".to_string()));
+        assert_eq!(name("PUT"), Some("items".to_string()));
+        assert_eq!(name("HEAD"), Some("items".to_string()));
+        assert_eq!(name("OPTIONS"), None);
+        assert_eq!(
+            allowed_methods(&requests),
+            "GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS"
+        );
     }
 }
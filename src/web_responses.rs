@@ -1,59 +1,114 @@
 use crate::db_notification::{self, Notifier};
+use crate::db_settings::SettingsManager;
 use crate::nino_constants::{info, USE_RESPONSE_CACHE};
-use crate::nino_functions;
+use crate::nino_functions::{self, ByteRanges};
 use crate::nino_structures::ServletTask;
+use crate::web_cache::ContentCache;
+use crate::web_compression::{is_compressible, set_content_encoding, set_vary, Encoding};
 use crate::web_requests::RequestInfo;
+use crate::web_stream::WebConnection;
 use crate::{
     db::DBManager,
-    nino_constants,
+    js_core, nino_constants,
     nino_structures::{self, JSTask},
 };
 use async_channel::{Receiver, Sender};
-use async_std::net::TcpStream;
+use async_std::io::WriteExt;
 use deno_core::anyhow::Error;
-use http_types::{Mime, Response, StatusCode};
+use http_types::{Mime, Request, Response, StatusCode};
 use std::collections::HashMap;
 use std::str::FromStr;
-use std::sync::{Arc, OnceLock, RwLock};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::SystemTime;
+use tokio_postgres::Row;
+use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct ResponseManager {
     db: Arc<DBManager>,
-    js_thread_count: usize,
+    // invalidation messages for each javascript runtime
+    js_messages: Arc<Mutex<Vec<Sender<String>>>>,
     web_task_sx: Sender<nino_structures::JSTask>,
     web_task_rx: Receiver<nino_structures::JSTask>,
     notifier: Arc<Notifier>,
+    settings: Arc<SettingsManager>,
 }
 
 #[derive(Clone)]
 pub struct ResponseInfo {
     pub mime: Mime,
     pub execute: bool,
+    // content hash of the served content, empty when not available
+    pub etag: String,
+    pub modified: Option<SystemTime>,
+    // the response cache policy. empty uses the default one
+    pub cache_control: String,
+    // size of the served content
+    pub size: usize,
+    // execution time budget in ms of the module. 0 uses the default, negative is unlimited
+    pub timeout_ms: i32,
+}
+
+// the hash and size of the served content are set by the nino_response_modified trigger
+const RESPONSE_COLUMNS: &str = "response_name, response_mime_type, execute_flag, response_hash, \
+    response_modified, response_cache_control, response_size, response_timeout";
+
+fn row_to_response(row: &Row) -> (String, ResponseInfo) {
+    let name: String = row.get(0);
+    let mime_str: String = row.get(1);
+    let mime = Mime::from_str(&mime_str).unwrap();
+    let execute: bool = row.get(2);
+    let etag: Option<String> = row.get(3);
+    let modified: Option<SystemTime> = row.get(4);
+    let cache_control: Option<String> = row.get(5);
+    let size: Option<i32> = row.get(6);
+    let timeout_ms: Option<i32> = row.get(7);
+    (
+        name,
+        ResponseInfo {
+            mime,
+            execute,
+            etag: etag.unwrap_or_default(),
+            modified,
+            cache_control: cache_control.unwrap_or_default(),
+            size: size.unwrap_or(0) as usize,
+            timeout_ms: timeout_ms.unwrap_or(0),
+        },
+    )
 }
 
 static RESPONSE_CACHE: OnceLock<RwLock<HashMap<String, ResponseInfo>>> = OnceLock::new();
+// small static contents and their compressed variants. None is the uncompressed content
+static CONTENT_CACHE: OnceLock<Mutex<ContentCache<(String, Option<Encoding>)>>> = OnceLock::new();
+
+// bigger static contents are read from the database and written in chunks of this size
+const STREAM_CHUNK_SIZE: usize = 256 * 1024;
 
 impl ResponseManager {
     pub fn new(
         db: Arc<DBManager>,
-        js_thread_count: usize,
         notifier: Arc<Notifier>,
         db_subscribe: tokio::sync::broadcast::Receiver<nino_structures::NotificationMessage>,
+        settings: Arc<SettingsManager>,
     ) -> ResponseManager {
         RESPONSE_CACHE.get_or_init(|| RwLock::new(HashMap::new()));
+        CONTENT_CACHE.get_or_init(|| Mutex::new(ContentCache::new(0)));
         // web_task channel is used to send tasks to the js threads
         let (web_task_sx, web_task_rx) = async_channel::unbounded::<nino_structures::JSTask>();
         let this = Self {
             db,
-            js_thread_count,
+            js_messages: Arc::new(Mutex::new(Vec::new())),
             web_task_sx,
             web_task_rx,
             notifier,
+            settings,
         };
         let thizz = this.clone();
+        let settings_subscribe = this.settings.subscribe();
         tokio::spawn(async move {
+            thizz.reload_cache_size().await;
             thizz.reload_responses().await;
-            thizz.invalidator(db_subscribe).await;
+            thizz.invalidator(db_subscribe, settings_subscribe).await;
         });
         this
     }
@@ -66,42 +121,116 @@ impl ResponseManager {
         self.web_task_rx.clone()
     }
 
+    pub fn get_web_task_sx(&self) -> Sender<JSTask> {
+        self.web_task_sx.clone()
+    }
+
+    /// the invalidation messages for a javascript runtime, every runtime gets all messages
+    pub fn subscribe_js_messages(&self) -> Receiver<String> {
+        let (messages_sx, messages_rx) = async_channel::unbounded::<String>();
+        self.js_messages.lock().unwrap().push(messages_sx);
+        messages_rx
+    }
+
+    /// the javascript tasks waiting for a thread
+    pub fn task_backlog(&self) -> usize {
+        self.web_task_sx.len()
+    }
+
     pub async fn invalidator(
         &self,
         mut db_subscribe: tokio::sync::broadcast::Receiver<nino_structures::NotificationMessage>,
+        mut settings_subscribe: tokio::sync::broadcast::Receiver<
+            nino_structures::NotificationMessage,
+        >,
     ) {
         loop {
-            match db_subscribe.recv().await {
+            let received = tokio::select! {
+                received = db_subscribe.recv() => match received {
+                    // received from the settings manager once its cache is cleared
+                    Ok(message)
+                        if message
+                            .text
+                            .starts_with(db_notification::NOTIFICATION_PREFIX_SETTING) =>
+                    {
+                        continue
+                    }
+                    received => received,
+                },
+                received = settings_subscribe.recv() => received,
+            };
+            match received {
                 Err(error) => {
                     eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
                 }
                 Ok(message) => {
-                    info!("MSG:responses: {}", message.text);
-
                     if message
                         .text
-                        .starts_with(db_notification::NOTIFICATION_PREFIX_RESPONSE)
+                        .starts_with(db_notification::NOTIFICATION_PREFIX_WEBSOCKET)
                     {
-                        //reload the db aliases
-                        self.reload_responses().await;
+                        // delivered to the connections by the socket manager
+                        continue;
                     }
+                    info!("MSG:responses: {}", message.text);
 
-                    // send invalidation messages to the js threads
-                    let web_task = nino_structures::JSTask::Message(message.text);
-                    for _ in 0..self.js_thread_count {
-                        if let Err(error) = self.web_task_sx.send(web_task.clone()).await {
-                            eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+                    if let Some(module) = message
+                        .text
+                        .strip_prefix(db_notification::NOTIFICATION_PREFIX_RESPONSE)
+                    {
+                        if module.is_empty() {
+                            self.reload_responses().await;
+                            CONTENT_CACHE.get().unwrap().lock().unwrap().clear();
+                        } else {
+                            // a deploy notifies every changed response
+                            self.reload_response(module).await;
+                            CONTENT_CACHE
+                                .get()
+                                .unwrap()
+                                .lock()
+                                .unwrap()
+                                .remove_matching(|(name, _)| name == module);
                         }
+                        // before the js runtimes restart with the startup snapshot
+                        js_core::module_changed(module);
+                    }
+                    if message
+                        .text
+                        .starts_with(db_notification::NOTIFICATION_PREFIX_SETTING)
+                    {
+                        self.reload_cache_size().await;
                     }
+
+                    // send invalidation messages to the js runtimes, the dropped ones are removed
+                    self.js_messages
+                        .lock()
+                        .unwrap()
+                        .retain(|messages_sx| messages_sx.try_send(message.text.clone()).is_ok());
                 }
             }
         }
     }
 
+    async fn reload_cache_size(&self) {
+        let budget = self
+            .settings
+            .get_setting_i32(
+                nino_constants::SETTINGS_NINO_WEB_STATIC_CACHE_SIZE,
+                nino_constants::SETTINGS_NINO_WEB_STATIC_CACHE_SIZE_DEFAULT,
+            )
+            .await;
+        CONTENT_CACHE
+            .get()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .set_budget(budget.max(0) as usize);
+    }
+
     async fn reload_responses(&self) {
         //reload the db aliases
         let query: String = format!(
-            "SELECT response_name, response_mime_type, execute_flag, transpile_flag FROM {}",
+            "SELECT {} FROM {}",
+            RESPONSE_COLUMNS,
             nino_constants::RESPONSE_TABLE
         );
 
@@ -113,11 +242,34 @@ impl ResponseManager {
                 let mut map = RESPONSE_CACHE.get().unwrap().write().unwrap();
                 map.clear();
                 for row in rows {
-                    let name: String = row.get(0);
-                    let mime_str: String = row.get(1);
-                    let mime = Mime::from_str(&mime_str).unwrap();
-                    let execute: bool = row.get(2);
-                    map.insert(name, ResponseInfo { mime, execute });
+                    let (name, response_info) = row_to_response(&row);
+                    map.insert(name, response_info);
+                }
+            }
+        }
+    }
+
+    /// reloads the response of the name, removes it when it is deleted
+    async fn reload_response(&self, name: &str) {
+        let query: String = format!(
+            "SELECT {} FROM {} WHERE response_name = $1",
+            RESPONSE_COLUMNS,
+            nino_constants::RESPONSE_TABLE
+        );
+        match self.db.query_opt(&query, &[&name]).await {
+            Err(error) => {
+                eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+            }
+            Ok(row) => {
+                let mut map = RESPONSE_CACHE.get().unwrap().write().unwrap();
+                match row {
+                    Some(row) => {
+                        let (name, response_info) = row_to_response(&row);
+                        map.insert(name, response_info);
+                    }
+                    None => {
+                        map.remove(name);
+                    }
                 }
             }
         }
@@ -129,19 +281,12 @@ impl ResponseManager {
             Ok(map.get(name).cloned())
         } else {
             let query: String = format!(
-                "SELECT response_mime_type, execute_flag, transpile_flag FROM {} WHERE response_name = $1",
+                "SELECT {} FROM {} WHERE response_name = $1",
+                RESPONSE_COLUMNS,
                 nino_constants::RESPONSE_TABLE
             );
             let result = self.db.query_opt(&query, &[&name]).await?;
-            match result {
-                None => Ok(None),
-                Some(row) => {
-                    let mime_str: String = row.get(0);
-                    let mime = Mime::from_str(&mime_str).unwrap();
-                    let execute: bool = row.get(1);
-                    Ok(Some(ResponseInfo { mime, execute }))
-                }
-            }
+            Ok(result.map(|row| row_to_response(&row).1))
         }
     }
 
@@ -183,6 +328,30 @@ impl ResponseManager {
         }
     }
 
+    /// reads only the range of the served content with substring in the database
+    pub async fn get_response_range(
+        &self,
+        name: &str,
+        start: usize,
+        end: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let query: String = format!(
+            "SELECT substring((CASE WHEN transpile_flag THEN javascript ELSE response_content END) FROM $2 FOR $3) FROM {} WHERE response_name = $1",
+            nino_constants::RESPONSE_TABLE
+        );
+        // substring positions start from 1
+        let from = (start + 1) as i32;
+        let length = (end - start + 1) as i32;
+        let row = self.db.query_opt(&query, &[&name, &from, &length]).await?;
+        match row {
+            None => Err(Error::msg(format!(
+                "response '{}' does not exist in database",
+                name
+            ))),
+            Some(row) => Ok(row.get(0)),
+        }
+    }
+
     pub async fn serve_dynamic(
         &self,
         mut servlet_task: ServletTask,
@@ -190,35 +359,294 @@ impl ResponseManager {
         response_info: &ResponseInfo,
     ) -> Result<(), Error> {
         // default response
-        let mut response = Response::new(200);
-        let js_module = request_info.name.clone();
-        response.set_content_type(response_info.mime.clone());
+        servlet_task.js_module = Some(request_info.name.clone());
+        servlet_task.response = Some(Response::new(200));
+        servlet_task.timeout_ms = response_info.timeout_ms;
+        self.serve_module(servlet_task, response_info).await
+    }
+
+    /// sends the task with module and response already set to the javascript threads
+    pub async fn serve_module(
+        &self,
+        mut servlet_task: ServletTask,
+        response_info: &ResponseInfo,
+    ) -> Result<(), Error> {
+        if let Some(response) = servlet_task.response.as_mut() {
+            response.set_content_type(response_info.mime.clone());
+        }
         //send new task to the javascript threads
-        servlet_task.js_module = Some(js_module);
-        servlet_task.response = Some(response);
         let web_task = nino_structures::JSTask::Servlet(servlet_task);
         self.web_task_sx.send(web_task).await?;
         Ok(())
     }
 
+    /// serves the static content with validators of the content.
+    /// conditional requests of unchanged content are answered with 304
     pub async fn serve_static(
         &self,
         request_info: RequestInfo,
         response_info: ResponseInfo,
-        stream: Box<TcpStream>,
-        web_client_caching: bool,
+        request: &Request,
+        stream: WebConnection,
+        cache_control: &str,
     ) -> Result<(), Error> {
-        // serve content
-        let content = self.get_response_javascript(&request_info.name).await?;
         let mut response = Response::new(StatusCode::Ok);
-        response.set_content_type(response_info.mime);
-        response.set_body(http_types::Body::from(content));
-        if web_client_caching {
-            // set expiration/cache time to 24 hours
-            response.append_header("Cache-Control", "max-age=86400");
+        response.insert_header("Accept-Ranges", "bytes");
+        let identity_etag = if response_info.etag.is_empty() {
+            None
+        } else {
+            Some(format!("\"{}\"", response_info.etag))
+        };
+
+        // ranges are served from the uncompressed content
+        let ranges = match request.header("Range") {
+            Some(range)
+                if if_range_matches(request, identity_etag.as_deref(), response_info.modified) =>
+            {
+                nino_functions::parse_ranges(range.last().as_str(), response_info.size)
+            }
+            _ => ByteRanges::Full,
+        };
+        // bigger contents are streamed from the database as they are
+        let item_size = self
+            .settings
+            .get_setting_i32(
+                nino_constants::SETTINGS_NINO_WEB_STATIC_CACHE_ITEM_SIZE,
+                nino_constants::SETTINGS_NINO_WEB_STATIC_CACHE_ITEM_SIZE_DEFAULT,
+            )
+            .await
+            .max(0) as usize;
+        let buffered = response_info.size <= item_size;
+        let compression = if is_compressible(response_info.mime.essence()) {
+            set_vary(&mut response);
+            stream.compression.filter(|compression| {
+                buffered && ranges == ByteRanges::Full && response_info.size >= compression.min_size
+            })
+        } else {
+            None
+        };
+        response.set_content_type(response_info.mime.clone());
+
+        // compressed variants have own entity tags
+        let etag = match (compression, identity_etag) {
+            (Some(compression), Some(_)) => Some(format!(
+                "\"{}-{}\"",
+                response_info.etag,
+                compression.encoding.name()
+            )),
+            (_, etag) => etag,
+        };
+        if let Some(etag) = &etag {
+            response.insert_header("ETag", etag.as_str());
+        }
+        if let Some(modified) = response_info.modified {
+            response.insert_header("Last-Modified", nino_functions::http_date(modified));
+        }
+        response.insert_header("Cache-Control", cache_control);
+        if is_not_modified(request, etag.as_deref(), response_info.modified) {
+            response.set_status(StatusCode::NotModified);
+            nino_functions::send_response_to_stream(stream, &mut response).await?;
+            return Ok(());
+        }
+        match ranges {
+            ByteRanges::Full => {}
+            ByteRanges::Unsatisfiable => {
+                response.set_status(StatusCode::RequestedRangeNotSatisfiable);
+                response.insert_header("Content-Range", format!("bytes */{}", response_info.size));
+                nino_functions::send_response_to_stream(stream, &mut response).await?;
+                return Ok(());
+            }
+            ByteRanges::Partial(ranges) => {
+                return self
+                    .serve_ranges(&request_info.name, &response_info, ranges, response, stream)
+                    .await;
+            }
+        }
+
+        if !buffered {
+            let size = response_info.size;
+            return self
+                .stream_content(&request_info.name, 0, size, response, stream)
+                .await;
+        }
+
+        // serve content - compressed variants are computed once
+        let encoding = compression.map(|compression| compression.encoding);
+        let key = (request_info.name.clone(), encoding);
+        if let Some(content) = Self::cache_get(&key) {
+            if let Some(encoding) = encoding {
+                set_content_encoding(&mut response, encoding);
+            }
+            return nino_functions::send_bytes_to_stream(stream, &mut response, &content).await;
+        }
+        // a response changed while its content is read or compressed is not cached
+        let generation = Self::cache_generation();
+        let mut content = match Self::cache_get(&(request_info.name.clone(), None)) {
+            Some(content) => content,
+            None => {
+                let content = Arc::new(self.get_response_javascript(&request_info.name).await?);
+                let key = (request_info.name.clone(), None);
+                Self::cache_set(key, content.clone(), generation);
+                content
+            }
+        };
+        if let Some(encoding) = encoding {
+            match encoding.compress_blocking(content.clone(), true).await {
+                Ok(compressed) => {
+                    content = Arc::new(compressed);
+                    Self::cache_set(key, content.clone(), generation);
+                    set_content_encoding(&mut response, encoding);
+                }
+                Err(error) => eprintln!("ERROR {}:{}:{}", file!(), line!(), error),
+            }
+        }
+        nino_functions::send_bytes_to_stream(stream, &mut response, &content).await
+    }
+
+    /// 206 partial content. multiple ranges are sent as multipart/byteranges
+    async fn serve_ranges(
+        &self,
+        name: &str,
+        response_info: &ResponseInfo,
+        ranges: Vec<(usize, usize)>,
+        mut response: Response,
+        stream: WebConnection,
+    ) -> Result<(), Error> {
+        response.set_status(StatusCode::PartialContent);
+        if let [(start, end)] = ranges[..] {
+            response.insert_header(
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, end, response_info.size),
+            );
+            return self
+                .stream_content(name, start, end + 1, response, stream)
+                .await;
+        } else {
+            let boundary = Uuid::new_v4().simple().to_string();
+            let mut body: Vec<u8> = Vec::new();
+            for (start, end) in ranges {
+                let content = self.get_response_range(name, start, end).await?;
+                body.extend_from_slice(
+                    format!(
+                        "--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                        boundary, response_info.mime, start, end, response_info.size
+                    )
+                    .as_bytes(),
+                );
+                body.extend_from_slice(&content);
+                body.extend_from_slice(b"\r\n");
+            }
+            body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+            response.set_content_type(
+                Mime::from_str(&format!("multipart/byteranges; boundary={}", boundary))
+                    .map_err(|error| Error::msg(error.to_string()))?,
+            );
+            response.set_body(http_types::Body::from(body));
         }
+        nino_functions::send_response_to_stream(stream, &mut response).await
+    }
 
-        nino_functions::send_response_to_stream(stream, &mut response).await?;
+    /// writes the content bytes from start to end (exclusive) reading them in chunks from the database
+    async fn stream_content(
+        &self,
+        name: &str,
+        start: usize,
+        end: usize,
+        mut response: Response,
+        stream: WebConnection,
+    ) -> Result<(), Error> {
+        response.insert_header("Content-Length", (end - start).to_string());
+        let result = self
+            .write_content(name, start, end, &mut response, &stream)
+            .await;
+        stream.release(result.is_ok());
+        result
+    }
+
+    async fn write_content(
+        &self,
+        name: &str,
+        start: usize,
+        end: usize,
+        response: &mut Response,
+        stream: &WebConnection,
+    ) -> Result<(), Error> {
+        nino_functions::write_response_head(stream, response).await?;
+        let mut out = stream.stream.clone();
+        let mut position = start;
+        while !stream.head && position < end {
+            let chunk_end = end.min(position + STREAM_CHUNK_SIZE);
+            let chunk = self
+                .get_response_range(name, position, chunk_end - 1)
+                .await?;
+            if chunk.len() != chunk_end - position {
+                // the content changed while streaming
+                return Err(Error::msg(format!(
+                    "response '{}' changed while sending",
+                    name
+                )));
+            }
+            out.write_all(&chunk).await?;
+            position = chunk_end;
+        }
+        out.flush().await?;
         Ok(())
     }
+
+    fn cache_get(key: &(String, Option<Encoding>)) -> Option<Arc<Vec<u8>>> {
+        CONTENT_CACHE.get().unwrap().lock().unwrap().get(key)
+    }
+
+    fn cache_generation() -> u64 {
+        CONTENT_CACHE.get().unwrap().lock().unwrap().generation()
+    }
+
+    fn cache_set(key: (String, Option<Encoding>), content: Arc<Vec<u8>>, generation: u64) {
+        CONTENT_CACHE
+            .get()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .insert_unchanged(key, content, generation);
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// If-Range allows the range only for unchanged content - strong entity tag or exact date
+fn if_range_matches(request: &Request, etag: Option<&str>, modified: Option<SystemTime>) -> bool {
+    let Some(if_range) = request.header("If-Range") else {
+        return true;
+    };
+    let if_range = if_range.last().as_str().trim();
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        return etag.is_some_and(|etag| etag == if_range);
+    }
+    match (nino_functions::parse_http_date(if_range), modified) {
+        (Some(date), Some(modified)) => unix_seconds(date) == unix_seconds(modified),
+        _ => false,
+    }
+}
+
+/// If-None-Match has precedence over If-Modified-Since
+fn is_not_modified(request: &Request, etag: Option<&str>, modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = request.header("If-None-Match") {
+        return etag.is_some_and(|etag| {
+            if_none_match
+                .iter()
+                .any(|value| nino_functions::etag_matches(value.as_str(), etag))
+        });
+    }
+    let since = request
+        .header("If-Modified-Since")
+        .and_then(|values| nino_functions::parse_http_date(values.last().as_str()));
+    match (since, modified) {
+        // http dates have seconds precision
+        (Some(since), Some(modified)) => unix_seconds(modified) <= unix_seconds(since),
+        _ => false,
+    }
 }
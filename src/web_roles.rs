@@ -0,0 +1,81 @@
+use deno_core::anyhow::Error;
+
+use crate::{db::DBManager, db_notification, nino_constants, nino_structures};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, OnceLock, RwLock},
+};
+
+/// Resolves the roles of the authenticated users.
+/// roles are cached per user and the cache is cleared on role notification
+#[derive(Clone)]
+pub struct RoleManager {
+    db: Arc<DBManager>,
+}
+
+static ROLE_CACHE: OnceLock<RwLock<HashMap<String, Arc<HashSet<String>>>>> = OnceLock::new();
+
+impl RoleManager {
+    pub fn new(
+        db: Arc<DBManager>,
+        db_subscribe: tokio::sync::broadcast::Receiver<nino_structures::NotificationMessage>,
+    ) -> RoleManager {
+        ROLE_CACHE.get_or_init(|| RwLock::new(HashMap::new()));
+        let this = Self { db };
+        tokio::spawn(async move {
+            Self::invalidator(db_subscribe).await;
+        });
+        this
+    }
+
+    pub async fn invalidator(
+        mut db_subscribe: tokio::sync::broadcast::Receiver<nino_structures::NotificationMessage>,
+    ) {
+        loop {
+            match db_subscribe.recv().await {
+                Err(error) => {
+                    eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+                }
+                Ok(message) => {
+                    if message
+                        .text
+                        .starts_with(db_notification::NOTIFICATION_PREFIX_ROLE)
+                    {
+                        ROLE_CACHE.get().unwrap().write().unwrap().clear();
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn get_user_roles(&self, user: &str) -> Result<Arc<HashSet<String>>, Error> {
+        if let Some(roles) = ROLE_CACHE.get().unwrap().read().unwrap().get(user) {
+            return Ok(roles.clone());
+        }
+        let query = format!(
+            "SELECT user_role FROM {} WHERE user_name = $1",
+            nino_constants::USER_ROLE_TABLE
+        );
+        let rows = self.db.query(&query, &[&user]).await?;
+        let roles: Arc<HashSet<String>> = Arc::new(rows.iter().map(|row| row.get(0)).collect());
+        ROLE_CACHE
+            .get()
+            .unwrap()
+            .write()
+            .unwrap()
+            .insert(user.to_string(), roles.clone());
+        Ok(roles)
+    }
+
+    /// true if the user has at least one of the required roles
+    pub async fn has_any_role(&self, user: &str, required_roles: &[String]) -> Result<bool, Error> {
+        if required_roles.is_empty() {
+            return Ok(true);
+        }
+        if user.is_empty() {
+            return Ok(false);
+        }
+        let roles = self.get_user_roles(user).await?;
+        Ok(required_roles.iter().any(|role| roles.contains(role)))
+    }
+}
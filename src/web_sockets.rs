@@ -0,0 +1,430 @@
+use crate::db_notification::{self, Notifier};
+use crate::nino_constants::info;
+use crate::nino_structures::{self, JSTask, SocketData, SocketEvent, SocketTask};
+use crate::web_stream::{WebConnection, WebStream};
+use async_channel::{Receiver, Sender};
+use async_std::io::WriteExt;
+use base64::Engine;
+use deno_core::anyhow::Error;
+use fastwebsockets::{
+    FragmentCollectorRead, Frame, OpCode, Payload, Role, WebSocket, WebSocketError, WebSocketWrite,
+};
+use http_types::convert::{Deserialize, Serialize};
+use http_types::{Method, Request, Url};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock, RwLock};
+use uuid::Uuid;
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const CLOSE_NORMAL: u16 = 1000;
+// the javascript threads are behind
+const CLOSE_TRY_AGAIN_LATER: u16 = 1013;
+// frames waiting for a slow reader. the connection is closed when they are more
+const MAX_QUEUED_FRAMES: usize = 256;
+
+/// the connections of this instance and their groups
+#[derive(Default)]
+struct SocketRegistry {
+    connections: HashMap<String, Sender<(OpCode, Vec<u8>)>>,
+    groups: HashMap<String, HashSet<String>>,
+}
+
+static SOCKETS: OnceLock<RwLock<SocketRegistry>> = OnceLock::new();
+
+fn registry() -> &'static RwLock<SocketRegistry> {
+    SOCKETS.get_or_init(|| RwLock::new(SocketRegistry::default()))
+}
+
+/// message for a connection or the connections of a group on any instance.
+/// sent as notification, so the payload is limited to about 8000 bytes
+#[derive(Serialize, Deserialize)]
+pub struct SocketMessage {
+    pub connection: Option<String>,
+    pub group: Option<String>,
+    pub text: String,
+}
+
+/// the connection of an upgraded request
+pub struct SocketInfo {
+    pub js_module: String,
+    pub request_path: String,
+    pub user: String,
+    pub params: HashMap<String, String>,
+}
+
+/// Serves the websocket connections and dispatches their events to the javascript threads
+#[derive(Clone)]
+pub struct SocketManager {
+    web_task_sx: Sender<JSTask>,
+    max_message_size: usize,
+    // queued javascript tasks above which the events are refused. 0 - unlimited
+    task_backlog: usize,
+}
+
+impl SocketManager {
+    pub fn new(
+        notifier: Arc<Notifier>,
+        web_task_sx: Sender<JSTask>,
+        max_message_size: usize,
+        task_backlog: usize,
+    ) -> SocketManager {
+        let db_subscribe = notifier.get_subscriber();
+        let this = Self {
+            web_task_sx,
+            max_message_size,
+            task_backlog,
+        };
+        let thizz = this.clone();
+        tokio::spawn(async move {
+            thizz.delivering(db_subscribe).await;
+        });
+        this
+    }
+
+    /// sends the messages of the notifications to the connections of this instance
+    async fn delivering(
+        &self,
+        mut db_subscribe: tokio::sync::broadcast::Receiver<nino_structures::NotificationMessage>,
+    ) {
+        loop {
+            match db_subscribe.recv().await {
+                Err(error) => {
+                    eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+                }
+                Ok(message) => {
+                    let Some(json) = message
+                        .text
+                        .strip_prefix(db_notification::NOTIFICATION_PREFIX_WEBSOCKET)
+                    else {
+                        continue;
+                    };
+                    match deno_core::serde_json::from_str::<SocketMessage>(json) {
+                        Ok(message) => deliver(message),
+                        Err(error) => eprintln!("ERROR {}:{}:{}", file!(), line!(), error),
+                    }
+                }
+            }
+        }
+    }
+
+    /// answers the upgrade request and serves the connection until it is closed.
+    /// the events of a connection are handled one after another
+    pub async fn serve(&self, stream: WebConnection, accept: String, socket: SocketInfo) {
+        let handshake = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept
+        );
+        let mut out = stream.stream.clone();
+        let written = match out.write_all(handshake.as_bytes()).await {
+            Ok(()) => out.flush().await,
+            Err(error) => Err(error),
+        };
+        if let Err(error) = written {
+            info!("ERROR {}:{}:{}", file!(), line!(), error);
+            stream.release(false);
+            return;
+        }
+
+        let mut ws = WebSocket::after_handshake((*stream.stream).clone(), Role::Server);
+        ws.set_auto_close(true);
+        ws.set_auto_pong(true);
+        ws.set_max_message_size(self.max_message_size);
+        // clones share the connection, so the reader and the writer work independently
+        let (read, write) = ws.split(|stream: WebStream| (stream.clone(), stream));
+
+        let id = Uuid::new_v4().to_string();
+        let (frame_sx, frame_rx) = async_channel::bounded::<(OpCode, Vec<u8>)>(MAX_QUEUED_FRAMES);
+        registry()
+            .write()
+            .unwrap()
+            .connections
+            .insert(id.clone(), frame_sx.clone());
+
+        let reading = async {
+            if self.dispatch(&id, &socket, SocketEvent::Open).await {
+                self.read_frames(&id, &socket, read, frame_sx).await;
+                // the writer ends with the last sender of the connection
+                unregister(&id);
+                self.dispatch(&id, &socket, SocketEvent::Close).await;
+            } else {
+                close_overloaded(&frame_sx);
+                unregister(&id);
+            }
+        };
+        let writing = async {
+            write_frames(write, frame_rx).await;
+            // the closed queue or connection ends the reading as well
+            let _r = stream.stream.shutdown(std::net::Shutdown::Both);
+        };
+        tokio::join!(reading, writing);
+        stream.release(false);
+    }
+
+    async fn read_frames(
+        &self,
+        id: &str,
+        socket: &SocketInfo,
+        read: fastwebsockets::WebSocketRead<WebStream>,
+        frame_sx: Sender<(OpCode, Vec<u8>)>,
+    ) {
+        let mut read = FragmentCollectorRead::new(read);
+        loop {
+            // pong and close replies are sent by the writer
+            let mut reply = |frame: Frame| {
+                let _r = frame_sx.try_send((frame.opcode, frame.payload.to_vec()));
+                async { Ok::<(), WebSocketError>(()) }
+            };
+            let frame = match read.read_frame(&mut reply).await {
+                Ok(frame) => frame,
+                Err(error) => {
+                    // requestor has closed the stream
+                    info!("OK {}:{}:{}", file!(), line!(), error);
+                    return;
+                }
+            };
+            let data = match frame.opcode {
+                OpCode::Text => match String::from_utf8(frame.payload.to_vec()) {
+                    Ok(text) => SocketData::Text(text),
+                    Err(error) => {
+                        info!("ERROR {}:{}:{}", file!(), line!(), error);
+                        return;
+                    }
+                },
+                OpCode::Binary => SocketData::Binary(frame.payload.to_vec()),
+                OpCode::Close => return,
+                _ => continue,
+            };
+            if !self.dispatch(id, socket, SocketEvent::Message(data)).await {
+                close_overloaded(&frame_sx);
+                return;
+            }
+        }
+    }
+
+    /// queues the event to the javascript threads and waits until it is handled.
+    /// false when the event is not queued - the threads are behind or stopped.
+    /// the close event is queued over the backlog, so every opened connection is closed in javascript
+    async fn dispatch(&self, id: &str, socket: &SocketInfo, event: SocketEvent) -> bool {
+        if self.task_backlog > 0
+            && self.web_task_sx.len() >= self.task_backlog
+            && !matches!(event, SocketEvent::Close)
+        {
+            return false;
+        }
+        let (done_sx, done_rx) = async_channel::bounded::<()>(1);
+        let task = JSTask::Socket(SocketTask {
+            id: id.to_string(),
+            event,
+            js_module: socket.js_module.clone(),
+            request_path: socket.request_path.clone(),
+            user: socket.user.clone(),
+            params: socket.params.clone(),
+            done: done_sx,
+        });
+        if let Err(error) = self.web_task_sx.send(task).await {
+            eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+            return false;
+        }
+        // closed when the javascript thread drops the task
+        let _r = done_rx.recv().await;
+        true
+    }
+}
+
+/// queues the close frame telling the client to try again later
+fn close_overloaded(frame_sx: &Sender<(OpCode, Vec<u8>)>) {
+    let _r = frame_sx.try_send((OpCode::Close, CLOSE_TRY_AGAIN_LATER.to_be_bytes().to_vec()));
+}
+
+async fn write_frames(mut write: WebSocketWrite<WebStream>, frame_rx: Receiver<(OpCode, Vec<u8>)>) {
+    while let Ok((opcode, payload)) = frame_rx.recv().await {
+        let close = matches!(opcode, OpCode::Close);
+        let frame = Frame::new(true, opcode, None, Payload::Owned(payload));
+        if let Err(error) = write.write_frame(frame).await {
+            info!("OK {}:{}:{}", file!(), line!(), error);
+            return;
+        }
+        if close {
+            return;
+        }
+    }
+}
+
+/// sends the message through the notifier to the connections of all instances
+pub async fn publish(notifier: &Notifier, message: &SocketMessage) -> Result<(), Error> {
+    let json = deno_core::serde_json::to_string(message)?;
+    notifier
+        .notify(format!(
+            "{}{}",
+            db_notification::NOTIFICATION_PREFIX_WEBSOCKET,
+            json
+        ))
+        .await?;
+    Ok(())
+}
+
+fn unregister(id: &str) {
+    let mut registry = registry().write().unwrap();
+    registry.connections.remove(id);
+    registry.groups.retain(|_, members| {
+        members.remove(id);
+        !members.is_empty()
+    });
+}
+
+fn deliver(message: SocketMessage) {
+    if let Some(connection) = &message.connection {
+        send(connection, SocketData::Text(message.text.clone()));
+    }
+    if let Some(group) = &message.group {
+        let members: Vec<String> = {
+            let registry = registry().read().unwrap();
+            match registry.groups.get(group) {
+                Some(members) => members.iter().cloned().collect(),
+                None => return,
+            }
+        };
+        for member in members {
+            send(&member, SocketData::Text(message.text.clone()));
+        }
+    }
+}
+
+/// sends the data to a connection of this instance. false when the connection is not here
+pub fn send(id: &str, data: SocketData) -> bool {
+    let frame = match data {
+        SocketData::Text(text) => (OpCode::Text, text.into_bytes()),
+        SocketData::Binary(bytes) => (OpCode::Binary, bytes),
+    };
+    send_frame(id, frame)
+}
+
+/// closes a connection of this instance
+pub fn close(id: &str) -> bool {
+    send_frame(id, (OpCode::Close, CLOSE_NORMAL.to_be_bytes().to_vec()))
+}
+
+fn send_frame(id: &str, frame: (OpCode, Vec<u8>)) -> bool {
+    let registry = registry().read().unwrap();
+    match registry.connections.get(id) {
+        Some(sender) => match sender.try_send(frame) {
+            Ok(()) => true,
+            Err(async_channel::TrySendError::Full(_)) => {
+                // the reader is too slow, the queued frames are sent and the connection closed
+                sender.close();
+                false
+            }
+            Err(_) => false,
+        },
+        None => false,
+    }
+}
+
+/// adds a connection of this instance to the group
+pub fn join(id: &str, group: &str) -> bool {
+    let mut registry = registry().write().unwrap();
+    if !registry.connections.contains_key(id) {
+        return false;
+    }
+    registry
+        .groups
+        .entry(group.to_string())
+        .or_default()
+        .insert(id.to_string());
+    true
+}
+
+pub fn leave(id: &str, group: &str) {
+    let mut registry = registry().write().unwrap();
+    if let Some(members) = registry.groups.get_mut(group) {
+        members.remove(id);
+        if members.is_empty() {
+            registry.groups.remove(group);
+        }
+    }
+}
+
+/// the Sec-WebSocket-Accept of a valid upgrade request, None for other requests
+pub fn accept_key(request: &Request) -> Option<String> {
+    let upgrade = request.header("Upgrade")?.iter().any(|value| {
+        value
+            .as_str()
+            .split(',')
+            .any(|protocol| protocol.trim().eq_ignore_ascii_case("websocket"))
+    });
+    let version = request
+        .header("Sec-WebSocket-Version")?
+        .last()
+        .as_str()
+        .trim()
+        == "13";
+    let key = request.header("Sec-WebSocket-Key")?.last().as_str().trim();
+    if !upgrade || !version || key.is_empty() || request.method() != Method::Get {
+        return None;
+    }
+    Some(websocket_accept(key))
+}
+
+/// browsers do not apply CORS to websockets, so the Origin of the upgrade is checked.
+/// true when the Origin is the requested host
+pub fn is_same_origin(request: &Request, origin: &str) -> bool {
+    let Ok(origin) = Url::parse(origin) else {
+        return false;
+    };
+    let Some(host) = request
+        .header("Host")
+        .map(|values| values.last().as_str().trim())
+    else {
+        return false;
+    };
+    Url::parse(&format!("{}://{}", origin.scheme(), host))
+        .is_ok_and(|host| host.origin() == origin.origin())
+}
+
+fn websocket_accept(key: &str) -> String {
+    let digest = openssl::sha::sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web_sockets::{accept_key, is_same_origin, websocket_accept};
+    use http_types::{Method, Request, Url};
+
+    #[test]
+    fn test_websocket_accept() {
+        // the example of RFC 6455
+        assert_eq!(
+            websocket_accept("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+
+        let url = Url::parse("http://localhost/chat").unwrap();
+        let mut request = Request::new(Method::Get, url.clone());
+        assert_eq!(accept_key(&request), None);
+        request.insert_header("Upgrade", "websocket");
+        request.insert_header("Connection", "Upgrade");
+        request.insert_header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==");
+        request.insert_header("Sec-WebSocket-Version", "13");
+        assert_eq!(
+            accept_key(&request),
+            Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".to_string())
+        );
+        request.insert_header("Sec-WebSocket-Version", "8");
+        assert_eq!(accept_key(&request), None);
+    }
+
+    #[test]
+    fn test_is_same_origin() {
+        let url = Url::parse("http://localhost/chat").unwrap();
+        let mut request = Request::new(Method::Get, url);
+        request.insert_header("Host", "example.com:8443");
+        assert!(is_same_origin(&request, "https://example.com:8443"));
+        assert!(!is_same_origin(&request, "https://example.com"));
+        assert!(!is_same_origin(&request, "https://evil.com:8443"));
+        assert!(!is_same_origin(&request, "null"));
+        // the default port is omitted from the origin
+        request.insert_header("Host", "example.com:443");
+        assert!(is_same_origin(&request, "https://example.com"));
+    }
+}
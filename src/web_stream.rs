@@ -0,0 +1,386 @@
+use crate::web_compression::Compression;
+use async_std::io::{Read, Write};
+use async_std::net::TcpStream;
+use futures_rustls::server::TlsStream;
+use std::io;
+use std::net::{Shutdown, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// A client connection - plain TCP or TLS over TCP.
+/// clones share the same connection, the same way async_std TcpStream clones do
+#[derive(Clone)]
+pub enum WebStream {
+    Plain(TcpStream),
+    Tls(Arc<Mutex<TlsStream<TcpStream>>>),
+}
+
+impl WebStream {
+    pub fn tls(stream: TlsStream<TcpStream>) -> WebStream {
+        WebStream::Tls(Arc::new(Mutex::new(stream)))
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            WebStream::Plain(stream) => stream.peer_addr(),
+            WebStream::Tls(stream) => stream.lock().unwrap().get_ref().0.peer_addr(),
+        }
+    }
+
+    /// closes the underlying TCP connection.
+    /// TLS data is already flushed by the writers, so close_notify is skipped
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            WebStream::Plain(stream) => stream.shutdown(how),
+            WebStream::Tls(stream) => stream.lock().unwrap().get_ref().0.shutdown(how),
+        }
+    }
+}
+
+impl Read for WebStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            WebStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            WebStream::Tls(stream) => Pin::new(&mut *stream.lock().unwrap()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl Write for WebStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            WebStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            WebStream::Tls(stream) => Pin::new(&mut *stream.lock().unwrap()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            WebStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            WebStream::Tls(stream) => Pin::new(&mut *stream.lock().unwrap()).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            WebStream::Plain(stream) => Pin::new(stream).poll_close(cx),
+            WebStream::Tls(stream) => Pin::new(&mut *stream.lock().unwrap()).poll_close(cx),
+        }
+    }
+}
+
+// tokio io of the upgraded websocket connections
+impl tokio::io::AsyncRead for WebStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match Read::poll_read(self, cx, buf.initialize_unfilled()) {
+            Poll::Ready(Ok(length)) => {
+                buf.advance(length);
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(error)) => Poll::Ready(Err(error)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl tokio::io::AsyncWrite for WebStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Write::poll_write(self, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Write::poll_flush(self, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Write::poll_close(self, cx)
+    }
+}
+
+/// The client connection of a single request.
+/// the response writers hand keep alive connections back to the serving loop
+#[derive(Clone)]
+pub struct WebConnection {
+    pub stream: Box<WebStream>,
+    pub keep_alive: bool,
+    // HEAD request - the response is written without body
+    pub head: bool,
+    // negotiated response compression. None sends responses uncompressed
+    pub compression: Option<Compression>,
+    // headers added to every response of the request, ex: CORS
+    pub headers: Vec<(&'static str, String)>,
+    release: Option<async_channel::Sender<bool>>,
+}
+
+impl WebConnection {
+    pub fn new(
+        stream: Box<WebStream>,
+        keep_alive: bool,
+        head: bool,
+        release: async_channel::Sender<bool>,
+    ) -> WebConnection {
+        WebConnection {
+            stream,
+            keep_alive,
+            head,
+            compression: None,
+            headers: Vec::new(),
+            release: Some(release),
+        }
+    }
+
+    /// connection closed after the response
+    pub fn closing(stream: Box<WebStream>) -> WebConnection {
+        WebConnection {
+            stream,
+            keep_alive: false,
+            head: false,
+            compression: None,
+            headers: Vec::new(),
+            release: None,
+        }
+    }
+
+    /// called when the response is written.
+    /// reusable false closes the connection even when keep alive
+    pub fn release(&self, reusable: bool) {
+        let reuse = self.keep_alive && reusable;
+        if !reuse && self.stream.shutdown(Shutdown::Both).is_err() {
+            // stream already closed
+        }
+        if let Some(release) = &self.release {
+            let _r = release.try_send(reuse);
+        }
+    }
+}
+
+const HEAD_END: &[u8] = b"\r\n\r\n";
+const MAX_HEAD_BUFFER: usize = 64 * 1024;
+const READ_CHUNK: usize = 8 * 1024;
+
+#[derive(Clone, Copy)]
+enum Framing {
+    // waiting for the request head
+    Head,
+    // the head is found and is being handed to the decoder. body None is chunked
+    HeadFound {
+        remaining: usize,
+        body: Option<usize>,
+    },
+    // reading request body with known length
+    Body {
+        remaining: usize,
+    },
+    // chunked request body - the end is known only to the decoder
+    Unframed,
+}
+
+struct ReaderState {
+    buffer: Vec<u8>,
+    framing: Framing,
+}
+
+/// Reads the HTTP/1.1 requests of a connection one at a time.
+/// the decoder gets only the bytes of the current request, so bytes of pipelined
+/// requests stay in this buffer and are not lost in the decoder buffers
+#[derive(Clone)]
+pub struct RequestReader {
+    stream: WebStream,
+    state: Arc<Mutex<ReaderState>>,
+}
+
+impl RequestReader {
+    pub fn new(stream: WebStream) -> RequestReader {
+        RequestReader {
+            stream,
+            state: Arc::new(Mutex::new(ReaderState {
+                buffer: Vec::new(),
+                framing: Framing::Head,
+            })),
+        }
+    }
+
+    /// prepares for reading the next request on the connection.
+    /// false if the body of the current request is not completely read
+    pub fn next_request(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.framing {
+            Framing::Head | Framing::Body { remaining: 0 } => {
+                state.framing = Framing::Head;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// the body length of the request head - None when the body is chunked
+fn body_length(head: &[u8]) -> Option<usize> {
+    let head = String::from_utf8_lossy(head);
+    let mut length = 0;
+    for line in head.split("\r\n").skip(1) {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let key = key.trim();
+        if key.eq_ignore_ascii_case("Transfer-Encoding") {
+            if value.to_ascii_lowercase().contains("chunked") {
+                return None;
+            }
+        } else if key.eq_ignore_ascii_case("Content-Length") {
+            // invalid length is rejected by the decoder
+            length = value.trim().parse::<usize>().unwrap_or(0);
+        }
+    }
+    Some(length)
+}
+
+fn find_head_end(buffer: &[u8]) -> Option<usize> {
+    buffer
+        .windows(HEAD_END.len())
+        .position(|window| window == HEAD_END)
+        .map(|position| position + HEAD_END.len())
+}
+
+impl Read for RequestReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let mut state = this.state.lock().unwrap();
+        loop {
+            let limit = match state.framing {
+                Framing::Head => match find_head_end(&state.buffer) {
+                    Some(head_end) => {
+                        state.framing = Framing::HeadFound {
+                            remaining: head_end,
+                            body: body_length(&state.buffer[..head_end]),
+                        };
+                        continue;
+                    }
+                    // too long head - let the decoder reject it
+                    None if state.buffer.len() > MAX_HEAD_BUFFER => state.buffer.len(),
+                    None => 0,
+                },
+                Framing::HeadFound { remaining, .. } => remaining,
+                Framing::Body { remaining } => {
+                    if remaining == 0 {
+                        return Poll::Ready(Ok(0));
+                    }
+                    remaining
+                }
+                Framing::Unframed => usize::MAX,
+            };
+
+            if limit > 0 && !state.buffer.is_empty() {
+                // serve from the buffer
+                let size = limit.min(buf.len()).min(state.buffer.len());
+                buf[..size].copy_from_slice(&state.buffer[..size]);
+                state.buffer.drain(..size);
+                state.framing = match state.framing {
+                    Framing::HeadFound { remaining, body } if remaining > size => {
+                        Framing::HeadFound {
+                            remaining: remaining - size,
+                            body,
+                        }
+                    }
+                    Framing::HeadFound {
+                        body: Some(body), ..
+                    } => Framing::Body { remaining: body },
+                    Framing::HeadFound { body: None, .. } => Framing::Unframed,
+                    Framing::Body { remaining } => Framing::Body {
+                        remaining: remaining - size,
+                    },
+                    // too long head or chunked body
+                    Framing::Head | Framing::Unframed => Framing::Unframed,
+                };
+                return Poll::Ready(Ok(size));
+            }
+
+            if let Framing::Body { remaining } = state.framing {
+                // read the body directly without buffering
+                let size = remaining.min(buf.len());
+                let read = match Pin::new(&mut this.stream).poll_read(cx, &mut buf[..size]) {
+                    Poll::Ready(Ok(read)) => read,
+                    other => return other,
+                };
+                state.framing = Framing::Body {
+                    remaining: remaining - read,
+                };
+                return Poll::Ready(Ok(read));
+            }
+            if let Framing::Unframed = state.framing {
+                return Pin::new(&mut this.stream).poll_read(cx, buf);
+            }
+
+            // fill the buffer with more of the head
+            let mut chunk = [0u8; READ_CHUNK];
+            match Pin::new(&mut this.stream).poll_read(cx, &mut chunk) {
+                Poll::Ready(Ok(0)) => {
+                    // connection closed - hand the incomplete head to the decoder
+                    state.framing = Framing::Unframed;
+                    if state.buffer.is_empty() {
+                        return Poll::Ready(Ok(0));
+                    }
+                }
+                Poll::Ready(Ok(read)) => state.buffer.extend_from_slice(&chunk[..read]),
+                other => return other,
+            }
+        }
+    }
+}
+
+impl Write for RequestReader {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web_stream::{body_length, find_head_end};
+
+    #[test]
+    fn test_request_framing() {
+        let head = b"POST /a HTTP/1.1\r\ncontent-length: 12\r\n\r\nbody";
+        assert_eq!(find_head_end(head), Some(head.len() - 4));
+        assert_eq!(body_length(head), Some(12));
+        assert_eq!(body_length(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n"), Some(0));
+        assert_eq!(
+            body_length(b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n"),
+            None
+        );
+        assert_eq!(find_head_end(b"GET / HTTP/1.1\r\nHost: a\r\n"), None);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::db_settings::SettingsManager;
+use crate::{db_notification, nino_constants, nino_structures};
+use deno_core::anyhow::Error;
+use futures_rustls::rustls::pki_types::pem::PemObject;
+use futures_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use futures_rustls::rustls::ServerConfig;
+use futures_rustls::TlsAcceptor;
+use std::sync::{Arc, OnceLock, RwLock};
+
+const PEM_PREFIX: &str = "-----BEGIN";
+
+/// Keeps the TLS acceptor for the web server.
+/// the certificate and key are loaded from the settings and reloaded on setting change
+#[derive(Clone)]
+pub struct TlsManager {
+    settings: Arc<SettingsManager>,
+}
+
+static TLS_ACCEPTOR: OnceLock<RwLock<Option<TlsAcceptor>>> = OnceLock::new();
+
+impl TlsManager {
+    pub async fn new(
+        settings: Arc<SettingsManager>,
+        settings_subscribe: tokio::sync::broadcast::Receiver<nino_structures::NotificationMessage>,
+    ) -> TlsManager {
+        TLS_ACCEPTOR.get_or_init(|| RwLock::new(None));
+        let this = Self { settings };
+        // the server should know if it is HTTPS before listening
+        this.reload_acceptor().await;
+        let thizz = this.clone();
+        tokio::spawn(async move {
+            thizz.invalidator(settings_subscribe).await;
+        });
+        this
+    }
+
+    pub async fn invalidator(
+        &self,
+        mut settings_subscribe: tokio::sync::broadcast::Receiver<
+            nino_structures::NotificationMessage,
+        >,
+    ) {
+        loop {
+            match settings_subscribe.recv().await {
+                Err(error) => {
+                    eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+                }
+                Ok(message) => {
+                    if message
+                        .text
+                        .starts_with(db_notification::NOTIFICATION_PREFIX_SETTING)
+                    {
+                        self.reload_acceptor().await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn reload_acceptor(&self) {
+        match self.load_acceptor().await {
+            Ok(acceptor) => {
+                *TLS_ACCEPTOR.get().unwrap().write().unwrap() = acceptor;
+            }
+            Err(error) => {
+                // keep the previous certificate
+                eprintln!("ERROR {}:{}:{}", file!(), line!(), error);
+            }
+        }
+    }
+
+    /// returns None when no certificate is configured
+    async fn load_acceptor(&self) -> Result<Option<TlsAcceptor>, Error> {
+        let certificate = self
+            .settings
+            .get_setting_str(nino_constants::SETTINGS_NINO_WEB_TLS_CERTIFICATE, "")
+            .await;
+        if certificate.is_empty() {
+            return Ok(None);
+        }
+        let private_key = self
+            .settings
+            .get_setting_str(nino_constants::SETTINGS_NINO_WEB_TLS_PRIVATE_KEY, "")
+            .await;
+
+        let certificate = read_pem(&certificate)?;
+        let private_key = read_pem(&private_key)?;
+        let chain = CertificateDer::pem_slice_iter(&certificate)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| Error::msg(format!("invalid TLS certificate: {}", error)))?;
+        if chain.is_empty() {
+            return Err(Error::msg("no TLS certificate found"));
+        }
+        let key = PrivateKeyDer::from_pem_slice(&private_key)
+            .map_err(|error| Error::msg(format!("invalid TLS private key: {}", error)))?;
+
+        let mut config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(chain, key)?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(Some(TlsAcceptor::from(Arc::new(config))))
+    }
+
+    /// the current acceptor, None when the server is plain HTTP
+    pub fn acceptor() -> Option<TlsAcceptor> {
+        TLS_ACCEPTOR
+            .get()
+            .and_then(|acceptor| acceptor.read().unwrap().clone())
+    }
+}
+
+/// the setting holds either the PEM content or the path to the PEM file
+fn read_pem(value: &str) -> Result<Vec<u8>, Error> {
+    if value.trim_start().starts_with(PEM_PREFIX) {
+        Ok(value.as_bytes().to_vec())
+    } else {
+        std::fs::read(value.trim())
+            .map_err(|error| Error::msg(format!("cannot read {}: {}", value, error)))
+    }
+}
@@ -1,3 +1,5 @@
+import taskId from '_task';
+
 export default async function getDB() {
     const core = Deno.core;
 
@@ -7,7 +9,7 @@ export default async function getDB() {
     } else {
         name = "_main";
     }
-    const db_alias = core.ops.nino_tx_get_connection_name(name);
+    const db_alias = await core.ops.nino_a_tx_get_connection_name(taskId(), name);
     core.print('db alias :' + db_alias + '\n');
 
     var normalizeParams = function (args) {
@@ -42,7 +44,7 @@ export default async function getDB() {
         var { params, paramTypes } = normalizeParams(queryArray);
 
         if (params[0].toUpperCase().startsWith("SELECT")) {
-            const queryResult = core.ops.nino_tx_execute_query(name, params, paramTypes);
+            const queryResult = await core.ops.nino_a_tx_execute_query(taskId(), name, params, paramTypes);
             if (callback) {
                 for (var row of queryResult.rows) {
                     const params = [...row, queryResult.rowNames, queryResult.rowTypes];
@@ -55,7 +57,7 @@ export default async function getDB() {
                 return queryResult;
             }
         } else {
-            const queryResult = core.ops.nino_tx_execute_upsert(name, params, paramTypes);
+            const queryResult = await core.ops.nino_a_tx_execute_upsert(taskId(), name, params, paramTypes);
             return queryResult;
         }
     }
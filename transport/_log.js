@@ -1,3 +1,5 @@
+import taskId from '_task';
+
 export default async function log() {
     const core = Deno.core;
 
@@ -12,6 +14,6 @@ export default async function log() {
         }
 
         core.print(log_message);
-        await core.ops.nino_a_log(log_message);
+        await core.ops.nino_a_log(taskId(), log_message);
     }
 }
\ No newline at end of file
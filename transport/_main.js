@@ -1,14 +1,17 @@
 /*
  * This is the main entry point for the nino js threads
  */
+// standard web APIs as globals for the request modules
+import '_web';
+
 async function main() {
     const core = Deno.core;
     const module_invalidation_prefix = core.ops.nino_get_module_invalidation_prefix();
     const database_invalidation_prefix = core.ops.nino_get_database_invalidation_prefix();
 
-    const header_set = function (key, value) {
+    const header_set = function (id, key, value) {
         if (typeof key === 'string' && typeof value === 'string') {
-            core.ops.nino_set_response_header(key, value);
+            core.ops.nino_set_response_header(id, key, value);
         } else {
             throw new Error("response.set() parameters needs to be both strings not "
                 + JSON.stringify(key) + ", "
@@ -17,111 +20,221 @@ async function main() {
         }
     };
 
-    const response_status = function (status) {
+    const response_status = function (id, status) {
         if (typeof status == 'number') {
-            core.ops.nino_set_response_status(status);
+            core.ops.nino_set_response_status(id, status);
         } else {
             throw new Error("response.status() needs to be a number not " + JSON.stringify(status));
         }
     };
 
-    const get_body = function () {
-        return core.ops.nino_get_request_body();
+    const set_cookie = function (id, name, value, options) {
+        if (typeof name === 'string' && typeof value === 'string') {
+            core.ops.nino_set_response_cookie(id, name, value, options || {});
+        } else {
+            throw new Error("response.setCookie() name and value needs to be strings not "
+                + JSON.stringify(name) + ", "
+                + JSON.stringify(value)
+            );
+        }
+    };
+
+    const get_header = function (name) {
+        // headers are stored with lowercase names
+        const values = this.headers[String(name).toLowerCase()];
+        return values ? values[0] : undefined;
     };
 
-    const send_response = async function (response) {
+    const get_body = function (id) {
+        return core.ops.nino_get_request_body(id);
+    };
+
+    const get_body_bytes = function (id) {
+        return core.ops.nino_get_request_body_bytes(id);
+    };
+
+    // multipart/form-data parts with name, filename, contentType and content
+    const get_parts = function (id) {
+        return core.ops.nino_get_request_parts(id);
+    };
+
+    // reads the next chunk of a streamed body, returns null at the end
+    const read_body = async function (id, size) {
+        return await core.ops.nino_a_read_request_body(id, size || 65536);
+    };
+
+    const send_response = async function (id, response) {
         // core.print('response typeof ' + (typeof response) + '\n');
         if (response === undefined || response === null) {
             throw new Error("response should not be undefined nor null");
         }
         if (typeof response === 'string') {
-            await core.ops.nino_a_set_response_send_text(response);
+            await core.ops.nino_a_set_response_send_text(id, response);
         } else if (typeof response === "number") {
-            await core.ops.nino_a_set_response_send_text(String.valueOf(response));
+            await core.ops.nino_a_set_response_send_text(id, String.valueOf(response));
         } else if (response instanceof Uint8Array) {
-            await core.ops.nino_a_set_response_send_buf(response);
+            await core.ops.nino_a_set_response_send_buf(id, response);
         } else if (typeof response === 'object'
             && Object.hasOwn(response, 'proxy_fetch_result_as_response')
             && response.proxy_fetch_result_as_response == true) {
             var p = response;
-            await core.ops.nino_a_set_response_from_fetch(p.url,
+            await core.ops.nino_a_set_response_from_fetch(id, p.url,
                 p.timeout,
                 p.method,
                 p.headers,
                 p.body);
         } else {
-            await core.ops.nino_a_set_response_send_text(JSON.stringify(response));
+            await core.ops.nino_a_set_response_send_text(id, JSON.stringify(response));
+        }
+    };
+
+    // writes a chunk of the body with Transfer-Encoding: chunked.
+    // the first write sends the status and headers, so they can not be changed after it
+    const write_response = async function (id, chunk) {
+        if (chunk === undefined || chunk === null) {
+            throw new Error("response.write() chunk should not be undefined nor null");
+        }
+        if (chunk instanceof Uint8Array) {
+            await core.ops.nino_a_response_write(id, chunk);
+        } else {
+            await core.ops.nino_a_response_write(id, core.encode(String(chunk)));
+        }
+    };
+
+    const end_response = async function (id) {
+        await core.ops.nino_a_response_end(id);
+    };
+
+    const send_socket = function (id, message) {
+        if (message instanceof Uint8Array) {
+            return core.ops.nino_socket_send_binary(id, message);
+        } else if (typeof message === 'string') {
+            return core.ops.nino_socket_send_text(id, message);
+        } else {
+            return core.ops.nino_socket_send_text(id, JSON.stringify(message));
+        }
+    };
+
+    // websocket events are handled by the open, message and close exports of the module
+    const handle_socket_event = async function (id, mod, event) {
+        const handler = mod[event.type];
+        if (typeof handler !== "function") {
+            return;
         }
+        const socket = {
+            id: event.id,
+            path: event.path,
+            user: event.user,
+            params: event.params,
+            send: function (message) {
+                return send_socket(event.id, message);
+            },
+            join: function (group) {
+                return core.ops.nino_socket_join(event.id, String(group));
+            },
+            leave: function (group) {
+                core.ops.nino_socket_leave(event.id, String(group));
+            },
+            close: function () {
+                return core.ops.nino_socket_close(event.id);
+            },
+        };
+        const data = event.binary ? core.ops.nino_get_socket_bytes(id) : event.text;
+        await handler(socket, data);
     };
 
     const get_jwt = function (username) {
         return core.ops.nino_get_user_jwt(username.toString());
     };
 
-    // main loop
-    for (; ;) {
-        try {
-            // core.print('_main try\n');
-            const module = core.ops.nino_begin_task();
+    // restarts the js engine to reset the compiled modules.
+    // the workers end when their current task is done, the engine is created again after the restarting threads
+    let restarting = false;
+    const restart = function () {
+        if (!restarting) {
+            restarting = true;
+            core.ops.nino_stop_tasks();
+        }
+    };
 
+    const run_task = async function (task) {
+        const id = task.id;
+        const module = task.module;
+        try {
             debugger;
             if (module) {
                 // request for module execution
                 // core.print('module ' + module + '\n');
                 const mod = await import(module);
                 // core.print('after import ' + (typeof mod) + '\n');
-                let handler = mod.default;
-                if (!handler) {
-                    throw new Error("module '" + module + "' has no export default async function");
-                }
-                if (typeof handler !== "function") {
-                    throw new Error("module '" + module + "' export default async function is not a function");
-                }
+                const socket_event = core.ops.nino_get_socket_event(id);
+                if (socket_event) {
+                    await handle_socket_event(id, mod, socket_event);
+                } else {
+                    let handler = mod.default;
+                    if (!handler) {
+                        throw new Error("module '" + module + "' has no export default async function");
+                    }
+                    if (typeof handler !== "function") {
+                        throw new Error("module '" + module + "' export default async function is not a function");
+                    }
 
-                const handler_arguments_count = handler.length;
-                // core.print('default handler with ' + handler_arguments_count + ' arguments\n');
-                const request = core.ops.nino_get_request();
-                request.set = header_set;
-                request.status = response_status;
-                request.getBody = get_body;
-                request.getJWT = get_jwt;
-
-                if (handler_arguments_count <= 1) {
-                    // rest handler with request param
-                    // core.print('handler 1 request: ' + JSON.stringify(request) + '\n');
-                    let response = await handler(request);
-                    // core.print('result = ' + response + '\n');
-                    await send_response(response);
-
-                } else if (handler_arguments_count == 2) {
-                    // servlet handler with request and response params
-                    const response = {
-                        set: header_set,
-                        status: response_status,
-                        send: async function (response) {
-                            await send_response(response);
-                        },
-                    };
-
-                    await handler(request, response);
-                    // core.print('result = ' + (result) + '\n');
+                    const handler_arguments_count = handler.length;
+                    // core.print('default handler with ' + handler_arguments_count + ' arguments\n');
+                    // the functions are bound to the task, other tasks run while the handler awaits
+                    const request = core.ops.nino_get_request(id);
+                    request.set = (key, value) => header_set(id, key, value);
+                    request.status = (status) => response_status(id, status);
+                    request.setCookie = (name, value, options) => set_cookie(id, name, value, options);
+                    request.header = get_header;
+                    request.getBody = () => get_body(id);
+                    request.getBodyBytes = () => get_body_bytes(id);
+                    request.readBody = (size) => read_body(id, size);
+                    request.getParts = () => get_parts(id);
+                    request.getJWT = get_jwt;
 
-                } else {
-                    throw new Error("module '" + module + "' default async function should take up to 2 parameters for rest and servlet modes");
+                    if (handler_arguments_count <= 1) {
+                        // rest handler with request param
+                        // core.print('handler 1 request: ' + JSON.stringify(request) + '\n');
+                        let response = await handler(request);
+                        // core.print('result = ' + response + '\n');
+                        await send_response(id, response);
+
+                    } else if (handler_arguments_count == 2) {
+                        // servlet handler with request and response params
+                        const response = {
+                            set: request.set,
+                            status: request.status,
+                            setCookie: request.setCookie,
+                            send: async function (response) {
+                                await send_response(id, response);
+                            },
+                            write: (chunk) => write_response(id, chunk),
+                            end: () => end_response(id),
+                        };
+
+                        await handler(request, response);
+                        // core.print('result = ' + (result) + '\n');
+
+                    } else {
+                        throw new Error("module '" + module + "' default async function should take up to 2 parameters for rest and servlet modes");
+                    }
                 }
 
             } else {
-                const invalidation_message = core.ops.nino_get_invalidation_message();
+                const invalidation_message = core.ops.nino_get_invalidation_message(id);
                 if (invalidation_message) {
                     core.print('MSG:js: ' + invalidation_message + '\n');
                     //request for cache invalidation
                     if (invalidation_message.startsWith(module_invalidation_prefix)) {
-                        // modules has been changed
-                        // restart js engine to reset the compiled modules
-                        break;
+                        // the changed module, empty for all modules
+                        const changed = invalidation_message.substring(module_invalidation_prefix.length);
+                        if (!changed || core.ops.nino_is_module_loaded(changed)) {
+                            restart();
+                        }
                     } else if (invalidation_message.startsWith(database_invalidation_prefix)) {
                         // reload databse aliases
-                        core.ops.nino_reload_database_aliases();
+                        await core.ops.nino_a_reload_database_aliases();
                     } else {
                         // future js message listeners could be implemented here
                         // TODO: add dynamic dispatch to "exit_message_*"
@@ -131,34 +244,58 @@ async function main() {
                 }
             }
             const commit = true;
-            core.ops.nino_tx_end(commit);
-            await core.ops.nino_a_broadcast_message(commit);
-            await core.ops.nino_a_end_task();
+            await core.ops.nino_a_tx_end(id, commit);
+            await core.ops.nino_a_broadcast_message(id, commit);
+            await core.ops.nino_a_end_task(id);
 
         } catch (e) {
             try {
                 let errorMessage = 'JS_ERROR: ' + e + '\n' + e.stack;
                 core.print(errorMessage + '\n');
-                core.ops.nino_set_response_status(500);
-                core.ops.nino_set_response_header('Content-Type', 'text/plain;charset=UTF-8');
-                await core.ops.nino_a_set_response_send_text(errorMessage);
+                // logged with error id and served by the server error page
+                await core.ops.nino_a_send_server_error(id, errorMessage);
             } catch (ex) {
                 let errorMessage = 'JS_ERROR_ERR: ' + ex + '\n' + ex.stack;
                 core.print(errorMessage + '\n');
             }
             try {
                 const commit = false;
-                core.ops.nino_tx_end(commit);
-                await core.ops.nino_a_broadcast_message(commit);
-                await core.ops.nino_a_end_task();
+                await core.ops.nino_a_tx_end(id, commit);
+                await core.ops.nino_a_broadcast_message(id, commit);
+                await core.ops.nino_a_end_task(id);
             } catch (ex) {
                 let errorMessage = 'JS_ERROR_ERR: ' + ex + '\n' + ex.stack;
                 core.print(errorMessage + '\n');
             }
         }
+    };
+
+    // worker loop, the workers of the thread take turns while their tasks await
+    const worker = async function () {
+        for (; ;) {
+            // core.print('_main try\n');
+            const task = await core.ops.nino_a_begin_task();
+            if (!task) {
+                // the thread is stopped
+                return;
+            }
+            // the library modules find the task of the running code in the async context
+            core.setAsyncContext(task.id);
+            try {
+                await run_task(task);
+            } finally {
+                core.setAsyncContext(undefined);
+            }
+        }
+    };
+
+    const workers = [];
+    for (let i = 0; i < core.ops.nino_get_concurrent_tasks(); i++) {
+        workers.push(worker());
     }
+    await Promise.all(workers);
 }
 
 (async () => {
     await main();
-})();
\ No newline at end of file
+})();
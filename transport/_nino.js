@@ -89,15 +89,19 @@ export default class nino_core {
 
     static async ninoRequestsGet() {
         const conn = await db();
-        const sql = SELECT request_path, response_name, redirect_flag, authorize_flag 
+        const sql = SELECT request_path, response_name, redirect_flag, authorize_flag, required_roles, request_method, event_flag, websocket_flag 
                     FROM nino_request 
-                    ORDER BY request_path;
+                    ORDER BY request_path, request_method;
         var result = [];
-        await conn.query(sql, function (request_path, response_name, redirect_flag, authorize_flag) {
+        await conn.query(sql, function (request_path, response_name, redirect_flag, authorize_flag, required_roles, request_method, event_flag, websocket_flag) {
             result.push({
                 request_path: request_path,
+                request_method: request_method,
                 redirect_flag: redirect_flag,
+                event_flag: event_flag,
+                websocket_flag: websocket_flag,
                 authorize_flag: authorize_flag,
+                required_roles: required_roles,
                 response_name: response_name,
             });
             return true;
@@ -105,19 +109,25 @@ export default class nino_core {
         return result;
     }
 
-    static async ninoRequestsDetail(name) {
+    static async ninoRequestsDetail(name, method) {
         const conn = await db();
-        const sql = SELECT request_path, response_name, redirect_flag, authorize_flag
+        const sql = SELECT request_path, response_name, redirect_flag, authorize_flag, required_roles, request_method, event_flag, websocket_flag, rate_limit
                     FROM nino_request 
-                    WHERE request_path = : name;
+                    WHERE request_path = : name
+                      AND request_method = : method;
 
         var result;
-        await conn.query(sql, function (request_path, response_name, redirect_flag, authorize_flag) {
+        await conn.query(sql, function (request_path, response_name, redirect_flag, authorize_flag, required_roles, request_method, event_flag, websocket_flag, rate_limit) {
             result = {
                 request_path: request_path,
+                request_method: request_method,
                 response_name: response_name,
                 redirect_flag: redirect_flag,
+                event_flag: event_flag,
+                websocket_flag: websocket_flag,
+                rate_limit: rate_limit,
                 authorize_flag: authorize_flag,
+                required_roles: required_roles,
             };
             return false;
         });
@@ -145,17 +155,22 @@ export default class nino_core {
 
     static async ninoResponsesDetail(name) {
         const conn = await db();
-        const sql = SELECT response_name, response_mime_type, execute_flag, transpile_flag, response_content, javascript
+        const sql = SELECT response_name, response_mime_type, execute_flag, transpile_flag, response_content, javascript,
+                           response_modified, response_cache_control, response_timeout
                     FROM nino_response 
                     WHERE response_name = : name;
 
         var result;
-        await conn.query(sql, function (response_name, response_mime_type, execute_flag, transpile_flag, response_content, javascript) {
+        await conn.query(sql, function (response_name, response_mime_type, execute_flag, transpile_flag, response_content, javascript,
+                                        response_modified, response_cache_control, response_timeout) {
             result = {
                 response_name: response_name,
                 response_mime_type: response_mime_type,
                 execute_flag: execute_flag,
                 transpile_flag: transpile_flag,
+                response_modified: response_modified,
+                response_cache_control: response_cache_control,
+                response_timeout: response_timeout,
                 response_content, response_content,
                 javascript: javascript
             };
@@ -20,7 +20,8 @@ export default async function portal_rest(request) {
         if (!name || !name[0]) {
           return { error: "no query parameter 'name' provided" };
         }
-        return await nino.ninoRequestsDetail(name[0]);
+        const method = request.parameters['method'];
+        return await nino.ninoRequestsDetail(name[0], (method && method[0]) ? method[0] : '*');
       }
     case '/responses/get':
       return await nino.ninoResponsesGet();
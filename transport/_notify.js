@@ -1,3 +1,5 @@
+import taskId from '_task';
+
 export default async function notify(message) {
     const core = Deno.core;
 
@@ -8,5 +10,5 @@ export default async function notify(message) {
     } else {
         message = JSON.stringify(message);
     }
-    return core.ops.nino_broadcast_message(message);
+    return core.ops.nino_broadcast_message(taskId(), message);
 }
\ No newline at end of file
@@ -0,0 +1,24 @@
+/*
+ * Sends text messages to the websocket connections of all nino instances.
+ * the messages are sent as notifications, so they are limited to about 8000 bytes
+ */
+const core = Deno.core;
+
+function toText(message) {
+    if (typeof message === 'string') {
+        return message;
+    }
+    return JSON.stringify(message);
+}
+
+// sends the message to the connection with the id
+export async function sendTo(id, message) {
+    await core.ops.nino_a_socket_publish({ connection: String(id), text: toText(message) });
+}
+
+// sends the message to all connections joined to the group
+export async function sendToGroup(group, message) {
+    await core.ops.nino_a_socket_publish({ group: String(group), text: toText(message) });
+}
+
+export default { sendTo, sendToGroup };
@@ -0,0 +1,7 @@
+// the id of the task running the code, the ops of requests, transactions and logs need it.
+// set by the main loop in the async context, so it follows the awaits of the handler
+export default function taskId() {
+    const core = Deno.core;
+
+    return core.getAsyncContext() ?? 0;
+}
@@ -30,8 +30,9 @@ async function main() {
         }
         await conn.query(["UPDATE nino_response SET javascript_length = length(javascript) WHERE transpile_flag = TRUE"]);
 
-        core.ops.nino_tx_end(true);
-        await core.ops.nino_a_end_task();
+        // runs outside of tasks, with the task id 0
+        await core.ops.nino_a_tx_end(0, true);
+        await core.ops.nino_a_end_task(0);
 
     } catch (e) {
         let errorMessage = 'JS_ERROR: ' + e + '\n' + e.stack;
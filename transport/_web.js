@@ -0,0 +1,665 @@
+/*
+ * Standard web APIs for the nino modules, installed as globals on the web ops.
+ * imported by _main before any request module, so npm style libraries run unmodified
+ */
+const core = Deno.core;
+const ops = core.ops;
+
+const define = function (name, value) {
+    Object.defineProperty(globalThis, name, {
+        value,
+        writable: true,
+        enumerable: false,
+        configurable: true,
+    });
+};
+
+// console
+
+const format = function (arg) {
+    if (typeof arg === 'string') {
+        return arg;
+    }
+    if (arg instanceof Error) {
+        return arg.stack || String(arg);
+    }
+    if (arg === undefined || typeof arg === 'function' || typeof arg === 'symbol' || typeof arg === 'bigint') {
+        return String(arg);
+    }
+    try {
+        return JSON.stringify(arg);
+    } catch (e) {
+        // cyclic objects
+        return String(arg);
+    }
+};
+
+// the V8 console reaches the inspector only
+const inspector_console = globalThis.console;
+
+const print = function (method, is_error, args) {
+    core.print(args.map(format).join(' ') + '\n', is_error);
+    if (inspector_console && typeof inspector_console[method] === 'function') {
+        inspector_console[method](...args);
+    }
+};
+
+const timers_started = new Map();
+
+define('console', {
+    log: (...args) => print('log', false, args),
+    info: (...args) => print('info', false, args),
+    debug: (...args) => print('debug', false, args),
+    warn: (...args) => print('warn', true, args),
+    error: (...args) => print('error', true, args),
+    trace: (...args) => print('trace', true, [...args, new Error().stack]),
+    assert: (condition, ...args) => {
+        if (!condition) {
+            print('assert', true, ['Assertion failed:', ...args]);
+        }
+    },
+    time: (label = 'default') => {
+        timers_started.set(label, Date.now());
+    },
+    timeEnd: (label = 'default') => {
+        const started = timers_started.get(label);
+        timers_started.delete(label);
+        if (started !== undefined) {
+            print('log', false, [label + ': ' + (Date.now() - started) + 'ms']);
+        }
+    },
+});
+
+// timers
+
+const MAX_DELAY = 0x7fffffff;
+let last_timer_id = 0;
+const timers = new Set();
+
+const start_timer = function (callback, delay, args, repeat) {
+    last_timer_id = last_timer_id % MAX_DELAY + 1;
+    const id = last_timer_id;
+    const millis = Math.min(Math.max(0, Math.floor(Number(delay) || 0)), MAX_DELAY);
+    // the callback runs in the task of the caller
+    const context = core.getAsyncContext();
+    timers.add(id);
+    (async () => {
+        do {
+            const fired = await ops.nino_a_web_timer(id, millis);
+            if (!fired || !timers.has(id)) {
+                return;
+            }
+            if (!repeat) {
+                timers.delete(id);
+            }
+            const previous = core.getAsyncContext();
+            core.setAsyncContext(context);
+            try {
+                if (typeof callback === 'function') {
+                    callback(...args);
+                } else {
+                    (0, eval)(String(callback));
+                }
+            } catch (e) {
+                globalThis.console.error(e);
+            } finally {
+                core.setAsyncContext(previous);
+            }
+        } while (timers.has(id));
+    })();
+    return id;
+};
+
+const clear_timer = function (id) {
+    if (timers.delete(id)) {
+        ops.nino_web_timer_cancel(id);
+    }
+};
+
+define('setTimeout', (callback, delay, ...args) => start_timer(callback, delay, args, false));
+define('setInterval', (callback, delay, ...args) => start_timer(callback, delay, args, true));
+define('clearTimeout', clear_timer);
+define('clearInterval', clear_timer);
+if (typeof globalThis.queueMicrotask !== 'function') {
+    define('queueMicrotask', (callback) => {
+        Promise.resolve().then(callback);
+    });
+}
+
+// encoding
+
+const to_bytes = function (data) {
+    if (data instanceof Uint8Array) {
+        return data;
+    }
+    if (data instanceof ArrayBuffer) {
+        return new Uint8Array(data);
+    }
+    if (ArrayBuffer.isView(data)) {
+        return new Uint8Array(data.buffer, data.byteOffset, data.byteLength);
+    }
+    throw new TypeError('expected an ArrayBuffer or ArrayBufferView');
+};
+
+class TextEncoder {
+    get encoding() {
+        return 'utf-8';
+    }
+
+    encode(input = '') {
+        return core.encode(String(input));
+    }
+
+    encodeInto(input, destination) {
+        const text = String(input);
+        let read = 0;
+        let written = 0;
+        for (const char of text) {
+            const bytes = core.encode(char);
+            if (written + bytes.length > destination.length) {
+                break;
+            }
+            destination.set(bytes, written);
+            written += bytes.length;
+            read += char.length;
+        }
+        return { read, written };
+    }
+}
+
+class TextDecoder {
+    #ignoreBOM;
+
+    constructor(label = 'utf-8', options = {}) {
+        const encoding = String(label).trim().toLowerCase();
+        if (encoding !== 'utf-8' && encoding !== 'utf8' && encoding !== 'unicode-1-1-utf-8') {
+            throw new RangeError("TextDecoder encoding '" + label + "' is not supported");
+        }
+        this.#ignoreBOM = Boolean(options.ignoreBOM);
+    }
+
+    get encoding() {
+        return 'utf-8';
+    }
+
+    decode(input = new Uint8Array(0)) {
+        let bytes = to_bytes(input);
+        if (!this.#ignoreBOM && bytes.length >= 3
+            && bytes[0] === 0xef && bytes[1] === 0xbb && bytes[2] === 0xbf) {
+            bytes = bytes.subarray(3);
+        }
+        return core.decode(bytes);
+    }
+}
+
+define('TextEncoder', TextEncoder);
+define('TextDecoder', TextDecoder);
+define('atob', (data) => ops.nino_web_atob(String(data)));
+define('btoa', (data) => ops.nino_web_btoa(String(data)));
+
+// URL
+
+class URLSearchParams {
+    #list = [];
+    #url = null;
+
+    constructor(init = '') {
+        if (init instanceof URLSearchParams) {
+            this.#list = init.#list.map(([name, value]) => [name, value]);
+        } else if (typeof init === 'object' && init !== null) {
+            const pairs = typeof init[Symbol.iterator] === 'function' ? init : Object.entries(init);
+            for (const pair of pairs) {
+                const [name, value] = [...pair];
+                this.#list.push([String(name), String(value)]);
+            }
+        } else {
+            this.#list = ops.nino_web_url_search_params_parse(String(init));
+        }
+    }
+
+    static _linked(url, search) {
+        const params = new URLSearchParams(search);
+        params.#url = url;
+        return params;
+    }
+
+    _reset(search) {
+        this.#list = ops.nino_web_url_search_params_parse(search);
+    }
+
+    #update() {
+        if (this.#url) {
+            this.#url._setSearch(this.toString());
+        }
+    }
+
+    get size() {
+        return this.#list.length;
+    }
+
+    append(name, value) {
+        this.#list.push([String(name), String(value)]);
+        this.#update();
+    }
+
+    delete(name, value) {
+        name = String(name);
+        this.#list = this.#list.filter(([n, v]) => n !== name || (value !== undefined && v !== String(value)));
+        this.#update();
+    }
+
+    get(name) {
+        const pair = this.#list.find(([n]) => n === String(name));
+        return pair ? pair[1] : null;
+    }
+
+    getAll(name) {
+        return this.#list.filter(([n]) => n === String(name)).map(([, v]) => v);
+    }
+
+    has(name, value) {
+        return this.#list.some(([n, v]) => n === String(name) && (value === undefined || v === String(value)));
+    }
+
+    set(name, value) {
+        name = String(name);
+        const index = this.#list.findIndex(([n]) => n === name);
+        if (index < 0) {
+            this.#list.push([name, String(value)]);
+        } else {
+            this.#list[index][1] = String(value);
+            this.#list = this.#list.filter(([n], i) => n !== name || i <= index);
+        }
+        this.#update();
+    }
+
+    sort() {
+        // stable sort by the UTF-16 code units of the names
+        this.#list.sort(([a], [b]) => (a < b ? -1 : a > b ? 1 : 0));
+        this.#update();
+    }
+
+    forEach(callback, thisArg) {
+        for (const [name, value] of this.#list) {
+            callback.call(thisArg, value, name, this);
+        }
+    }
+
+    *entries() {
+        for (const [name, value] of this.#list) {
+            yield [name, value];
+        }
+    }
+
+    *keys() {
+        for (const [name] of this.#list) {
+            yield name;
+        }
+    }
+
+    *values() {
+        for (const [, value] of this.#list) {
+            yield value;
+        }
+    }
+
+    [Symbol.iterator]() {
+        return this.entries();
+    }
+
+    toString() {
+        return ops.nino_web_url_search_params_stringify(this.#list);
+    }
+}
+
+class URL {
+    #parts;
+    #searchParams = null;
+
+    constructor(url, base) {
+        this.#parts = ops.nino_web_url_parse(String(url), base === undefined ? null : String(base));
+    }
+
+    static canParse(url, base) {
+        try {
+            new URL(url, base);
+            return true;
+        } catch (e) {
+            return false;
+        }
+    }
+
+    static parse(url, base) {
+        try {
+            return new URL(url, base);
+        } catch (e) {
+            return null;
+        }
+    }
+
+    #set(part, value) {
+        this.#parts = ops.nino_web_url_set(this.#parts.href, part, String(value));
+        if (this.#searchParams && (part === 'href' || part === 'search')) {
+            this.#searchParams._reset(this.#parts.search);
+        }
+    }
+
+    _setSearch(search) {
+        this.#parts = ops.nino_web_url_set(this.#parts.href, 'search', search);
+    }
+
+    get searchParams() {
+        if (!this.#searchParams) {
+            this.#searchParams = URLSearchParams._linked(this, this.#parts.search);
+        }
+        return this.#searchParams;
+    }
+
+    get href() { return this.#parts.href; }
+    set href(value) { this.#set('href', value); }
+    get origin() { return this.#parts.origin; }
+    get protocol() { return this.#parts.protocol; }
+    set protocol(value) { this.#set('protocol', value); }
+    get username() { return this.#parts.username; }
+    set username(value) { this.#set('username', value); }
+    get password() { return this.#parts.password; }
+    set password(value) { this.#set('password', value); }
+    get host() { return this.#parts.host; }
+    set host(value) { this.#set('host', value); }
+    get hostname() { return this.#parts.hostname; }
+    set hostname(value) { this.#set('hostname', value); }
+    get port() { return this.#parts.port; }
+    set port(value) { this.#set('port', value); }
+    get pathname() { return this.#parts.pathname; }
+    set pathname(value) { this.#set('pathname', value); }
+    get search() { return this.#parts.search; }
+    set search(value) { this.#set('search', value); }
+    get hash() { return this.#parts.hash; }
+    set hash(value) { this.#set('hash', value); }
+
+    toString() {
+        return this.#parts.href;
+    }
+
+    toJSON() {
+        return this.#parts.href;
+    }
+}
+
+define('URL', URL);
+define('URLSearchParams', URLSearchParams);
+
+// structuredClone
+
+define('structuredClone', (value) => {
+    const serialized = core.serialize(value, {}, (error) => {
+        throw new TypeError('structuredClone: ' + error);
+    });
+    return core.deserialize(serialized);
+});
+
+// crypto
+
+const MAX_RANDOM_BYTES = 65536;
+
+const crypto = {
+    getRandomValues(array) {
+        if (!ArrayBuffer.isView(array) || array instanceof Float32Array || array instanceof Float64Array
+            || array instanceof DataView) {
+            throw new TypeError('getRandomValues() needs an integer typed array');
+        }
+        if (array.byteLength > MAX_RANDOM_BYTES) {
+            throw new RangeError('getRandomValues() supports up to ' + MAX_RANDOM_BYTES + ' bytes');
+        }
+        ops.nino_web_random_values(to_bytes(array));
+        return array;
+    },
+
+    randomUUID() {
+        return ops.nino_web_random_uuid();
+    },
+
+    subtle: {
+        async digest(algorithm, data) {
+            const name = typeof algorithm === 'string' ? algorithm : algorithm.name;
+            const hash = ops.nino_web_digest(String(name), to_bytes(data));
+            return hash.buffer.slice(hash.byteOffset, hash.byteOffset + hash.byteLength);
+        },
+    },
+};
+
+define('crypto', crypto);
+
+// fetch
+
+class Headers {
+    #map = new Map();
+
+    constructor(init) {
+        if (init instanceof Headers) {
+            init.forEach((value, name) => this.append(name, value));
+        } else if (typeof init === 'object' && init !== null) {
+            const pairs = typeof init[Symbol.iterator] === 'function' ? init : Object.entries(init);
+            for (const pair of pairs) {
+                const [name, value] = [...pair];
+                this.append(name, value);
+            }
+        }
+    }
+
+    append(name, value) {
+        const key = String(name).toLowerCase();
+        const values = this.#map.get(key) || [];
+        values.push(String(value).trim());
+        this.#map.set(key, values);
+    }
+
+    delete(name) {
+        this.#map.delete(String(name).toLowerCase());
+    }
+
+    get(name) {
+        const values = this.#map.get(String(name).toLowerCase());
+        return values ? values.join(', ') : null;
+    }
+
+    getSetCookie() {
+        return [...(this.#map.get('set-cookie') || [])];
+    }
+
+    has(name) {
+        return this.#map.has(String(name).toLowerCase());
+    }
+
+    set(name, value) {
+        this.#map.set(String(name).toLowerCase(), [String(value).trim()]);
+    }
+
+    forEach(callback, thisArg) {
+        for (const [name, value] of this.entries()) {
+            callback.call(thisArg, value, name, this);
+        }
+    }
+
+    *entries() {
+        for (const name of [...this.#map.keys()].sort()) {
+            yield [name, this.get(name)];
+        }
+    }
+
+    *keys() {
+        for (const [name] of this.entries()) {
+            yield name;
+        }
+    }
+
+    *values() {
+        for (const [, value] of this.entries()) {
+            yield value;
+        }
+    }
+
+    [Symbol.iterator]() {
+        return this.entries();
+    }
+}
+
+// the body as bytes and its default content type
+const extract_body = function (body) {
+    if (body === undefined || body === null) {
+        return [null, null];
+    }
+    if (typeof body === 'string') {
+        return [core.encode(body), 'text/plain;charset=UTF-8'];
+    }
+    if (body instanceof URLSearchParams) {
+        return [core.encode(body.toString()), 'application/x-www-form-urlencoded;charset=UTF-8'];
+    }
+    if (body instanceof ArrayBuffer || ArrayBuffer.isView(body)) {
+        return [to_bytes(body).slice(), null];
+    }
+    return [core.encode(String(body)), 'text/plain;charset=UTF-8'];
+};
+
+class Body {
+    #body;
+    #bodyUsed = false;
+
+    _init(body, headers) {
+        const [bytes, content_type] = extract_body(body);
+        this.#body = bytes;
+        if (content_type && !headers.has('content-type')) {
+            headers.set('content-type', content_type);
+        }
+    }
+
+    _bytes() {
+        return this.#body;
+    }
+
+    get bodyUsed() {
+        return this.#bodyUsed;
+    }
+
+    async bytes() {
+        if (this.#bodyUsed) {
+            throw new TypeError('body already used');
+        }
+        this.#bodyUsed = true;
+        return this.#body ? this.#body.slice() : new Uint8Array(0);
+    }
+
+    async arrayBuffer() {
+        return (await this.bytes()).buffer;
+    }
+
+    async text() {
+        return core.decode(await this.bytes());
+    }
+
+    async json() {
+        return JSON.parse(await this.text());
+    }
+}
+
+class Request extends Body {
+    constructor(input, init = {}) {
+        super();
+        const request = input instanceof Request ? input : null;
+        this.url = request ? request.url : new URL(String(input)).href;
+        this.method = String(init.method || (request ? request.method : 'GET')).toUpperCase();
+        this.headers = new Headers(init.headers || (request ? request.headers : undefined));
+        this.redirect = init.redirect || 'follow';
+        this.signal = init.signal || null;
+        const body = init.body !== undefined ? init.body : (request ? request._bytes() : null);
+        if (body !== null && (this.method === 'GET' || this.method === 'HEAD')) {
+            throw new TypeError('Request with GET/HEAD method cannot have body');
+        }
+        this._init(body, this.headers);
+    }
+
+    clone() {
+        return new Request(this);
+    }
+}
+
+class Response extends Body {
+    constructor(body = null, init = {}) {
+        super();
+        this.status = init.status === undefined ? 200 : init.status;
+        this.statusText = init.statusText || '';
+        this.headers = new Headers(init.headers);
+        this.url = init.url || '';
+        this.redirected = false;
+        this.type = 'default';
+        this._init(body, this.headers);
+    }
+
+    get ok() {
+        return this.status >= 200 && this.status <= 299;
+    }
+
+    static json(data, init = {}) {
+        const headers = new Headers(init.headers);
+        if (!headers.has('content-type')) {
+            headers.set('content-type', 'application/json');
+        }
+        return new Response(JSON.stringify(data), { ...init, headers });
+    }
+
+    static redirect(url, status = 302) {
+        return new Response(null, { status, headers: { location: new URL(url).href } });
+    }
+
+    clone() {
+        return new Response(this._bytes(), this);
+    }
+}
+
+define('Headers', Headers);
+define('Request', Request);
+define('Response', Response);
+
+// fetch ids of the abort signals, 0 is a fetch without signal
+let last_fetch_id = 0;
+
+define('fetch', async (input, init = {}) => {
+    const request = new Request(input, init);
+    const signal = request.signal;
+    if (signal && signal.aborted) {
+        throw new Error('fetch() aborted');
+    }
+    let id = 0;
+    let abort = null;
+    if (signal && typeof signal.addEventListener === 'function') {
+        last_fetch_id = last_fetch_id % MAX_DELAY + 1;
+        id = last_fetch_id;
+        abort = () => ops.nino_web_fetch_cancel(id);
+        signal.addEventListener('abort', abort);
+    }
+    let result;
+    try {
+        result = await ops.nino_a_web_fetch(
+            {
+                id,
+                url: request.url,
+                method: request.method,
+                headers: [...request.headers],
+                redirect: request.redirect,
+            },
+            request._bytes() || new Uint8Array(0),
+        );
+    } finally {
+        if (abort && typeof signal.removeEventListener === 'function') {
+            signal.removeEventListener('abort', abort);
+        }
+    }
+    const response = new Response(result.body, {
+        status: result.status,
+        statusText: result.statusText,
+        headers: result.headers,
+        url: result.url,
+    });
+    response.redirected = result.redirected;
+    return response;
+});
@@ -40,10 +40,28 @@ CREATE INDEX IF NOT EXISTS nino_log_ix ON nino_log(log_timestamp);
 -- request table for defining the requests
 DROP TABLE IF EXISTS nino_request;
 CREATE TABLE IF NOT EXISTS nino_request (
-    request_path VARCHAR(1024) PRIMARY KEY,
+    request_path VARCHAR(1024) NOT NULL,
+    request_method VARCHAR(16) NOT NULL DEFAULT '*',
     response_name VARCHAR(1024) NOT NULL,
     redirect_flag BOOLEAN DEFAULT FALSE,
-    authorize_flag BOOLEAN DEFAULT FALSE
+    authorize_flag BOOLEAN DEFAULT FALSE,
+    required_roles VARCHAR(1024) DEFAULT '',
+    event_flag BOOLEAN DEFAULT FALSE,
+    websocket_flag BOOLEAN DEFAULT FALSE,
+    rate_limit INTEGER DEFAULT 0,
+    PRIMARY KEY (request_path, request_method)
+);
+
+-- CORS rules for the request paths and the paths under them. * is the rule of all other paths
+DROP TABLE IF EXISTS nino_cors;
+CREATE TABLE IF NOT EXISTS nino_cors (
+    request_path VARCHAR(1024) PRIMARY KEY,
+    allowed_origins VARCHAR(4096) NOT NULL DEFAULT '*',
+    allowed_methods VARCHAR(1024) DEFAULT 'GET, HEAD, POST, PUT, PATCH, DELETE',
+    allowed_headers VARCHAR(1024) DEFAULT '*',
+    exposed_headers VARCHAR(1024) DEFAULT '',
+    credentials_flag BOOLEAN DEFAULT FALSE,
+    max_age INTEGER DEFAULT 600
 );
 
 -- response table for defining static and dynamic requests
@@ -56,8 +74,33 @@ CREATE TABLE IF NOT EXISTS nino_response (
     response_content_length INT DEFAULT 0,
     response_content BYTEA NOT NULL, 
     javascript_length INT DEFAULT 0,
-    javascript BYTEA
+    javascript BYTEA,
+    response_modified TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    response_cache_control VARCHAR(256) DEFAULT '',
+    response_timeout INT DEFAULT 0,
+    response_hash VARCHAR(32),
+    response_size INT DEFAULT 0
 );
+-- uncompressed storage lets substring() read only the requested ranges of large contents
+ALTER TABLE nino_response ALTER COLUMN response_content SET STORAGE EXTERNAL;
+ALTER TABLE nino_response ALTER COLUMN javascript SET STORAGE EXTERNAL;
+
+-- response_modified is updated on content change and used as Last-Modified.
+-- response_hash (ETag) and response_size of the served content are stored, so they are not computed on every load.
+-- the init script executes statements ending the line with ; so the function body is on one line
+CREATE OR REPLACE FUNCTION nino_response_modified() RETURNS TRIGGER AS $$
+BEGIN NEW.response_modified := NOW(); NEW.response_hash := md5(CASE WHEN NEW.transpile_flag THEN NEW.javascript ELSE NEW.response_content END); NEW.response_size := COALESCE(octet_length(CASE WHEN NEW.transpile_flag THEN NEW.javascript ELSE NEW.response_content END), 0); RETURN NEW; END
+$$ LANGUAGE plpgsql;
+CREATE TRIGGER nino_response_modified_trigger
+BEFORE INSERT OR UPDATE OF response_mime_type, response_content, javascript, transpile_flag ON nino_response
+FOR EACH ROW EXECUTE FUNCTION nino_response_modified();
+-- changes are notified with the response name, the JS threads restart only when they have loaded the module
+CREATE OR REPLACE FUNCTION nino_response_notify() RETURNS TRIGGER AS $$
+BEGIN PERFORM pg_notify('nino', 'response:' || CASE WHEN TG_OP = 'DELETE' THEN OLD.response_name ELSE NEW.response_name END); RETURN NULL; END
+$$ LANGUAGE plpgsql;
+CREATE TRIGGER nino_response_notify_trigger
+AFTER INSERT OR UPDATE OR DELETE ON nino_response
+FOR EACH ROW EXECUTE FUNCTION nino_response_notify();
 
 -- user and role tables
 DROP TABLE IF EXISTS nino_user;
@@ -83,6 +126,13 @@ CREATE TABLE IF NOT EXISTS nino_user_role (
 );
 DROP INDEX IF EXISTS nino_user_role_ix;
 CREATE INDEX IF NOT EXISTS nino_user_role_ix ON nino_user_role(user_name);
+-- changes clear the cached user roles of all instances
+CREATE OR REPLACE FUNCTION nino_user_role_notify() RETURNS TRIGGER AS $$
+BEGIN PERFORM pg_notify('nino', 'role:'); RETURN NULL; END
+$$ LANGUAGE plpgsql;
+CREATE TRIGGER nino_user_role_notify_trigger
+AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON nino_user_role
+FOR EACH STATEMENT EXECUTE FUNCTION nino_user_role_notify();
 
 INSERT INTO nino_user_role(user_name, user_role) VALUES ('admin', 'admin');
 INSERT INTO nino_user_role(user_name, user_role) VALUES ('admin', 'portal');
@@ -0,0 +1,15 @@
+import socket from '_socket';
+
+// every connection joins the room of the path and gets the messages of the others
+export async function open(connection) {
+    connection.join('demo/chat/' + connection.params.room);
+    connection.send('joined ' + connection.params.room + ' as ' + connection.id);
+}
+
+export async function message(connection, text) {
+    await socket.sendToGroup('demo/chat/' + connection.params.room, connection.id + ': ' + text);
+}
+
+export async function close(connection) {
+    await socket.sendToGroup('demo/chat/' + connection.params.room, connection.id + ' left');
+}
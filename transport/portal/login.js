@@ -9,12 +9,12 @@ export default async function servlet(request, response) {
         const password = request.postParameters.password;
         var is_user_and_pass_ok = await nino.isValidUserAndPassword(username[0], password[0]);
         if (is_user_and_pass_ok) {
-            response.set("Set-Cookie", "nino=" + request.getJWT("admin"));
+            response.setCookie("nino", request.getJWT(username[0]), { path: "/", httpOnly: true, sameSite: "Lax" });
             response.set("Location", "/portal");
             return "";
         }
     }
-    response.set("Set-Cookie", "nino=");
+    response.setCookie("nino", "", { path: "/", maxAge: 0 });
     response.set("Location", "/login?error=user_and_pass_invalid");
     return "";
 }
\ No newline at end of file
@@ -31,10 +31,11 @@ export default function portlet_admin_requests() {
       try {
         let response = await fetch("/portal/rest?op=/requests/detail&" + new URLSearchParams({
           name: requests[selectIx].request_path,
+          method: requests[selectIx].request_method,
         }));
         const detailsRequest = await response.json();
 
-        if (detailsRequest.redirect_flag === 'true') {
+        if (detailsRequest.redirect_flag === 'true' || detailsRequest.event_flag === 'true') {
           setResponseDetails(detailsRequest);
         } else {
           // load response details
@@ -74,9 +75,13 @@ export default function portlet_admin_requests() {
   for (var i = 0; i < requests.length; i++) {
     var request = requests[i];
     requestRows.push(<tr class={(i == selectIx) ? "table-primary" : ""} data-index={i} onClick={onRowClick}>
+      <td>{request.request_method}</td>
       <td>{request.request_path}</td>
       <td><i class="align-middle" data-feather={request.authorize_flag == 'true' ? 'check-square' : 'minus'}></i></td>
       <td><i class="align-middle" data-feather={request.redirect_flag == 'true' ? 'check-square' : 'minus'}></i></td>
+      <td><i class="align-middle" data-feather={request.event_flag == 'true' ? 'check-square' : 'minus'}></i></td>
+      <td><i class="align-middle" data-feather={request.websocket_flag == 'true' ? 'check-square' : 'minus'}></i></td>
+      <td>{request.required_roles}</td>
       <td>{request.response_name}</td>
     </tr>);
   }
@@ -95,9 +100,13 @@ export default function portlet_admin_requests() {
               <table class="table table-hover my-0">
                 <thead>
                   <tr>
+                    <th>method</th>
                     <th>request path</th>
                     <th>authorize</th>
                     <th>redirect</th>
+                    <th>events</th>
+                    <th>websocket</th>
+                    <th>required roles</th>
                     <th>response name</th>
                   </tr>
                 </thead>
@@ -127,6 +136,10 @@ export default function portlet_admin_requests() {
                   <td>request path:&nbsp;&nbsp;</td>
                   <td>{responseDetails['request_path']}</td>
                 </tr>
+                <tr>
+                  <td>request method:</td>
+                  <td>{responseDetails['request_method']}</td>
+                </tr>
                 <tr>
                   <td>authorization required:</td>
                   <td>{responseDetails['authorize_flag']}</td>
@@ -135,6 +148,22 @@ export default function portlet_admin_requests() {
                   <td>redirect:</td>
                   <td>{responseDetails['redirect_flag']}</td>
                 </tr>
+                <tr>
+                  <td>events:</td>
+                  <td>{responseDetails['event_flag']}</td>
+                </tr>
+                <tr>
+                  <td>websocket:</td>
+                  <td>{responseDetails['websocket_flag']}</td>
+                </tr>
+                <tr>
+                  <td>rate limit:</td>
+                  <td>{responseDetails['rate_limit']}</td>
+                </tr>
+                <tr>
+                  <td>required roles:</td>
+                  <td>{responseDetails['required_roles']}</td>
+                </tr>
                 <tr>
                   <td>response name:&nbsp;&nbsp;</td>
                   <td>{responseDetails['response_name']}</td>
@@ -119,6 +119,18 @@ export default function portlet_admin_responses() {
                     <td>transpile:</td>
                     <td>{responseDetails['transpile_flag']}</td>
                   </tr>
+                  <tr>
+                    <td>modified:</td>
+                    <td>{responseDetails['response_modified']}</td>
+                  </tr>
+                  <tr>
+                    <td>cache control:</td>
+                    <td>{responseDetails['response_cache_control']}</td>
+                  </tr>
+                  <tr>
+                    <td>timeout:</td>
+                    <td>{responseDetails['response_timeout']}</td>
+                  </tr>
                   <tr>
                     <td>code: </td>
                     <td>